@@ -0,0 +1,98 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::pubsub::PubSub;
+use crate::resp_parser::RespValue;
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Per-connection state that outlives a single command.
+#[derive(Debug)]
+pub struct Client {
+    pub id: u64,
+    sender: UnboundedSender<RespValue>,
+    channels: HashSet<String>,
+    patterns: HashSet<String>,
+    /// Set by QUIT, so the connection is closed once the reply is sent.
+    closing: bool,
+}
+
+impl Client {
+    /// Creates a client along with the receiving end of its push channel,
+    /// which the connection loop drains to deliver pub/sub messages.
+    pub fn new() -> (Self, UnboundedReceiver<RespValue>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let client = Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            sender,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            closing: false,
+        };
+        (client, receiver)
+    }
+
+    /// Asks for the connection to be closed after the current reply.
+    pub fn close(&mut self) {
+        self.closing = true;
+    }
+
+    pub fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    pub fn subscription_count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription_count() > 0
+    }
+
+    pub fn channels(&self) -> Vec<String> {
+        self.channels.iter().cloned().collect()
+    }
+
+    pub fn patterns(&self) -> Vec<String> {
+        self.patterns.iter().cloned().collect()
+    }
+
+    pub fn subscribe(&mut self, pubsub: &PubSub, channel: String) {
+        if self.channels.insert(channel.clone()) {
+            pubsub.subscribe(channel, self.id, self.sender.clone());
+        }
+    }
+
+    pub fn unsubscribe(&mut self, pubsub: &PubSub, channel: &str) {
+        if self.channels.remove(channel) {
+            pubsub.unsubscribe(channel, self.id);
+        }
+    }
+
+    pub fn psubscribe(&mut self, pubsub: &PubSub, pattern: String) {
+        if self.patterns.insert(pattern.clone()) {
+            pubsub.psubscribe(pattern, self.id, self.sender.clone());
+        }
+    }
+
+    pub fn punsubscribe(&mut self, pubsub: &PubSub, pattern: &str) {
+        if self.patterns.remove(pattern) {
+            pubsub.punsubscribe(pattern, self.id);
+        }
+    }
+
+    /// Drops every subscription, used by RESET and when the connection goes
+    /// away.
+    pub fn unsubscribe_all(&mut self, pubsub: &PubSub) {
+        for channel in self.channels() {
+            self.unsubscribe(pubsub, &channel);
+        }
+        for pattern in self.patterns() {
+            self.punsubscribe(pubsub, &pattern);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(test)]
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+#[derive(Debug)]
+enum TimeSource {
+    /// Unix time read once at startup and advanced by a monotonic timer, so
+    /// steps of the system clock never move expirations.
+    Monotonic { anchor_ms: u64, started: Instant },
+    /// Time that only moves when told to.
+    #[cfg(test)]
+    Manual(watch::Sender<u64>),
+}
+
+/// Server clock in unix milliseconds.
+///
+/// Readers see a cached value that is refreshed once per command, so all
+/// expiration checks within a command agree with each other, and that never
+/// goes backwards.
+#[derive(Debug)]
+pub struct Clock {
+    source: TimeSource,
+    cached_ms: AtomicU64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        let anchor_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self {
+            source: TimeSource::Monotonic {
+                anchor_ms,
+                started: Instant::now(),
+            },
+            cached_ms: AtomicU64::new(anchor_ms),
+        }
+    }
+}
+
+impl Clock {
+    /// A clock frozen at `now_ms` that only moves through [`Clock::advance`],
+    /// letting tests drive expirations and blocking timeouts deterministically.
+    #[cfg(test)]
+    pub fn manual(now_ms: u64) -> Self {
+        let (sender, _) = watch::channel(now_ms);
+        Self {
+            source: TimeSource::Manual(sender),
+            cached_ms: AtomicU64::new(now_ms),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn advance(&self, by: Duration) {
+        if let TimeSource::Manual(sender) = &self.source {
+            let by = u64::try_from(by.as_millis()).unwrap_or(u64::MAX);
+            sender.send_modify(|now| *now = now.saturating_add(by));
+            self.refresh();
+        }
+    }
+
+    /// Re-reads the time source into the cache.
+    pub fn refresh(&self) -> u64 {
+        let now = self.source_ms();
+        self.cached_ms.fetch_max(now, Ordering::Relaxed).max(now)
+    }
+
+    pub fn now_ms(&self) -> u64 {
+        self.cached_ms.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once the clock has reached `deadline_ms`.
+    pub async fn sleep_until(&self, deadline_ms: u64) {
+        match &self.source {
+            TimeSource::Monotonic { .. } => {
+                let remaining = deadline_ms.saturating_sub(self.source_ms());
+                tokio::time::sleep(Duration::from_millis(remaining)).await;
+            }
+            #[cfg(test)]
+            TimeSource::Manual(sender) => {
+                let mut receiver = sender.subscribe();
+                let _ = receiver.wait_for(|now| *now >= deadline_ms).await;
+            }
+        }
+        self.refresh();
+    }
+
+    fn source_ms(&self) -> u64 {
+        match &self.source {
+            TimeSource::Monotonic { anchor_ms, started } => {
+                anchor_ms + started.elapsed().as_millis() as u64
+            }
+            #[cfg(test)]
+            TimeSource::Manual(sender) => *sender.borrow(),
+        }
+    }
+}
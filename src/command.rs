@@ -1,19 +1,57 @@
-use std::{
-    collections::HashMap,
-    sync::Arc,
-    time::{Duration, SystemTime},
-};
+use std::{sync::Arc, time::Duration};
 
-use crate::data::Store;
+use crate::client::Client;
+use crate::data::{
+    Aggregate, BitOperation, BitUnit, BitfieldOp, BitfieldOverflow, BitfieldType, ClaimOptions,
+    ExpireCondition, GeoOrder, GeoOrigin, GeoSearch, GeoShape, GeoUnit, GroupReadFrom,
+    GroupReadOptions, InsertPosition, LexBound, LexRange, ListEnd, MAX_BIT_OFFSET,
+    MAX_FIELD_EXPIRATION_MS, PendingFilter, ReadFrom, ScoreComparison, ScoreEnd, ScoreRange,
+    SetCondition, SetExpiration, SetOperation, SetOptions, SortOptions, Store, StramValue,
+    StreamEntryID, StreamInfo, TrimOptions, TrimStrategy, UnblockMode, ZAddOptions, ZRangeBy,
+    ZRangeSpec, is_valid_position,
+};
 use crate::resp_parser::RespValue;
 
-pub async fn handler(command: Vec<String>, memory: Arc<Store>) -> RespValue {
+pub async fn handler(request: Vec<Vec<u8>>, memory: Arc<Store>, client: &mut Client) -> RespValue {
+    // Keys and options are handled as text; binary-safe values are taken
+    // straight from `request`.
+    let command: Vec<String> = request
+        .iter()
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
     if command.is_empty() {
         return RespValue::Error("unknown command".to_string());
     }
 
-    match command[0].to_uppercase().as_str() {
+    memory.clock().refresh();
+
+    let name = command[0].to_uppercase();
+    if client.is_subscribed()
+        && !matches!(
+            name.as_str(),
+            "SUBSCRIBE" | "UNSUBSCRIBE" | "PSUBSCRIBE" | "PUNSUBSCRIBE" | "PING" | "QUIT" | "RESET"
+        )
+    {
+        return RespValue::Error(format!(
+            "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+            command[0].to_lowercase()
+        ));
+    }
+
+    match name.as_str() {
+        "PING" if client.is_subscribed() => RespValue::Array(vec![
+            RespValue::BulkString(Some("pong".to_string())),
+            RespValue::BulkString(Some(command.get(1).cloned().unwrap_or_default())),
+        ]),
         "PING" => RespValue::SimpleString("PONG".to_string()),
+        "QUIT" => {
+            client.close();
+            RespValue::SimpleString("OK".to_string())
+        }
+        "RESET" => {
+            client.unsubscribe_all(memory.pubsub());
+            RespValue::SimpleString("RESET".to_string())
+        }
         "ECHO" => {
             if command.len() < 2 {
                 return RespValue::Error(
@@ -27,295 +65,4152 @@ pub async fn handler(command: Vec<String>, memory: Arc<Store>) -> RespValue {
                 return RespValue::Error("wrong number of arguments for 'set' command".to_string());
             }
 
-            let duration = match command.get(3) {
-                Some(flag) => {
-                    if command.len() < 5 {
-                        return RespValue::Error(
-                            "wrong number of arguments for 'set' command".to_string(),
-                        );
+            let mut options = SetOptions::default();
+            let mut has_expiration = false;
+            let mut i = 3;
+            while i < command.len() {
+                let flag = command[i].to_uppercase();
+                match flag.as_str() {
+                    "NX" if options.condition != Some(SetCondition::IfExists) => {
+                        options.condition = Some(SetCondition::IfMissing);
                     }
-                    let time = match command[4].parse::<u64>() {
-                        Ok(time) => time,
-                        Err(_) => {
-                            return RespValue::Error(
-                                "value is not an integer or out of range".to_string(),
-                            );
-                        }
-                    };
-
-                    match flag.to_uppercase().as_str() {
-                        "EX" => Some(SystemTime::now() + Duration::from_secs(time)),
-                        "PX" => Some(SystemTime::now() + Duration::from_millis(time)),
-                        _ => {
+                    "XX" if options.condition != Some(SetCondition::IfMissing) => {
+                        options.condition = Some(SetCondition::IfExists);
+                    }
+                    "GET" => options.get = true,
+                    "KEEPTTL" if !has_expiration => {
+                        options.expiration = SetExpiration::Keep;
+                        has_expiration = true;
+                    }
+                    "EX" | "PX" | "EXAT" | "PXAT" if !has_expiration => {
+                        i += 1;
+                        let Some(value) = command.get(i) else {
                             return RespValue::Error("syntax error".to_string());
+                        };
+                        match parse_expiration(&memory, &flag, value, "set") {
+                            Ok(at) => options.expiration = SetExpiration::At(at),
+                            Err(err) => return err,
                         }
+                        has_expiration = true;
                     }
+                    _ => return RespValue::Error("syntax error".to_string()),
                 }
-                None => None,
+                i += 1;
+            }
+
+            match memory.set(command[1].clone(), request[2].clone(), options) {
+                Ok((_, previous)) if options.get => RespValue::BulkBytes(previous),
+                Ok((true, _)) => RespValue::SimpleString("OK".to_string()),
+                Ok((false, _)) => RespValue::Null,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SETNX" => {
+            if command.len() != 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'setnx' command".to_string(),
+                );
+            }
+
+            let options = SetOptions {
+                condition: Some(SetCondition::IfMissing),
+                ..Default::default()
+            };
+            match memory.set(command[1].clone(), request[2].clone(), options) {
+                Ok((written, _)) => RespValue::Integer(written as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SETEX" | "PSETEX" => {
+            let name = command[0].to_lowercase();
+            if command.len() != 4 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let unit = if name == "setex" { "EX" } else { "PX" };
+            let at = match parse_expiration(&memory, unit, &command[2], &name) {
+                Ok(at) => at,
+                Err(err) => return err,
+            };
+            let options = SetOptions {
+                expiration: SetExpiration::At(at),
+                ..Default::default()
             };
+            match memory.set(command[1].clone(), request[3].clone(), options) {
+                Ok(_) => RespValue::SimpleString("OK".to_string()),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GETSET" => {
+            if command.len() != 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'getset' command".to_string(),
+                );
+            }
 
-            memory.set(command[1].clone(), command[2].clone(), duration);
-            RespValue::SimpleString("OK".to_string())
+            let options = SetOptions {
+                get: true,
+                ..Default::default()
+            };
+            match memory.set(command[1].clone(), request[2].clone(), options) {
+                Ok((_, previous)) => RespValue::BulkBytes(previous),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GETDEL" => {
+            if command.len() != 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'getdel' command".to_string(),
+                );
+            }
+
+            match memory.getdel(&command[1]) {
+                Ok(value) => RespValue::BulkBytes(value),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GETEX" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'getex' command".to_string(),
+                );
+            }
+
+            let expiration = match command.get(2).map(|flag| flag.to_uppercase()).as_deref() {
+                None => SetExpiration::Keep,
+                Some("PERSIST") if command.len() == 3 => SetExpiration::Clear,
+                Some(unit @ ("EX" | "PX" | "EXAT" | "PXAT")) if command.len() == 4 => {
+                    match parse_expiration(&memory, unit, &command[3], "getex") {
+                        Ok(at) => SetExpiration::At(at),
+                        Err(err) => return err,
+                    }
+                }
+                Some(_) => return RespValue::Error("syntax error".to_string()),
+            };
+
+            match memory.getex(&command[1], expiration) {
+                Ok(value) => RespValue::BulkBytes(value),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "MGET" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'mget' command".to_string(),
+                );
+            }
+
+            RespValue::Array(
+                memory
+                    .mget(&command[1..])
+                    .into_iter()
+                    .map(RespValue::BulkBytes)
+                    .collect(),
+            )
+        }
+        "MSET" | "MSETNX" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 3 || command.len().is_multiple_of(2) {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let pairs = request[1..]
+                .chunks(2)
+                .map(|pair| {
+                    (
+                        String::from_utf8_lossy(&pair[0]).into_owned(),
+                        pair[1].clone(),
+                    )
+                })
+                .collect();
+
+            if name == "mset" {
+                memory.mset(pairs);
+                RespValue::SimpleString("OK".to_string())
+            } else {
+                RespValue::Integer(memory.msetnx(pairs) as i64)
+            }
         }
         "GET" => {
             if command.len() < 2 {
                 return RespValue::Error("wrong number of arguments for 'get' command".to_string());
             }
             match memory.get(&command[1]) {
-                Some(value) => RespValue::BulkString(Some(value)),
-                None => RespValue::Null,
+                Ok(value) => RespValue::BulkBytes(value),
+                Err(err) => RespValue::Error(err.to_string()),
             }
         }
-        "RPUSH" => {
-            if command.len() < 3 {
+        "APPEND" => {
+            if command.len() != 3 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'rpush' command".to_string(),
+                    "wrong number of arguments for 'append' command".to_string(),
                 );
             }
 
-            let mut len = 0;
-            for value in command.iter().skip(2) {
-                len = memory.rpush(command[1].clone(), value.clone()).await;
+            match memory.append(command[1].clone(), &request[2]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
             }
-
-            RespValue::Integer(len as i64)
         }
-        "LPUSH" => {
-            if command.len() < 3 {
+        "STRLEN" => {
+            if command.len() != 2 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'lpush' command".to_string(),
+                    "wrong number of arguments for 'strlen' command".to_string(),
                 );
             }
 
-            let mut len = 0;
-            for value in command.iter().skip(2) {
-                len = memory.lpush(command[1].clone(), value.clone()).await;
+            match memory.strlen(&command[1]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GETRANGE" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'getrange' command".to_string(),
+                );
             }
 
-            RespValue::Integer(len as i64)
+            let (Ok(start), Ok(end)) = (command[2].parse::<i64>(), command[3].parse::<i64>())
+            else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+
+            match memory.getrange(&command[1], start, end) {
+                Ok(value) => RespValue::BulkBytes(Some(value)),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
         }
-        "LPOP" => {
-            if command.len() < 2 {
+        "SETRANGE" => {
+            if command.len() != 4 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'lpop' command".to_string(),
+                    "wrong number of arguments for 'setrange' command".to_string(),
                 );
             }
 
-            let num_to_pop = if command.len() >= 3 {
-                match command[2].parse::<usize>() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        return RespValue::Error(
-                            "value is not an integer or out of range".to_string(),
-                        );
-                    }
+            let offset = match command[2].parse::<i64>() {
+                Ok(offset) if offset < 0 => {
+                    return RespValue::Error("offset is out of range".to_string());
+                }
+                Ok(offset) => offset as usize,
+                Err(_) => {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
                 }
-            } else {
-                1
             };
 
-            if num_to_pop > 1 {
-                let mut values = Vec::new();
-                for _ in 0..num_to_pop {
-                    match memory.lpop(&command[1]) {
-                        Some(value) => values.push(RespValue::BulkString(Some(value))),
-                        None => break,
+            match memory.setrange(command[1].clone(), offset, &request[3]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LCS" => {
+            if command.len() < 3 {
+                return RespValue::Error("wrong number of arguments for 'lcs' command".to_string());
+            }
+
+            let mut want_len = false;
+            let mut want_idx = false;
+            let mut with_match_len = false;
+            let mut min_match_len = 0;
+            let mut i = 3;
+            while i < command.len() {
+                match command[i].to_uppercase().as_str() {
+                    "LEN" => want_len = true,
+                    "IDX" => want_idx = true,
+                    "WITHMATCHLEN" => with_match_len = true,
+                    "MINMATCHLEN" if i + 1 < command.len() => {
+                        i += 1;
+                        match command[i].parse::<i64>() {
+                            Ok(len) => min_match_len = len.max(0) as usize,
+                            Err(_) => {
+                                return RespValue::Error(
+                                    "value is not an integer or out of range".to_string(),
+                                );
+                            }
+                        }
                     }
+                    _ => return RespValue::Error("syntax error".to_string()),
                 }
-                return RespValue::Array(values);
+                i += 1;
             }
 
-            match memory.lpop(&command[1]) {
-                Some(value) => RespValue::BulkString(Some(value)),
-                None => RespValue::Null,
-            }
-        }
-        "BLPOP" => {
-            if command.len() < 3 {
+            if want_len && want_idx {
                 return RespValue::Error(
-                    "wrong number of arguments for 'blpop' command".to_string(),
+                    "If you want both the length and indexes, please just use IDX.".to_string(),
                 );
             }
 
-            let timeout_secs = match command[2].parse::<f64>() {
-                Ok(num) => num,
-                Err(_) => {
-                    return RespValue::Error("timeout is not a float or out of range".to_string());
-                }
+            let lcs = match memory.lcs(&command[1], &command[2], min_match_len) {
+                Ok(lcs) => lcs,
+                Err(err) => return RespValue::Error(err.to_string()),
+            };
+
+            if want_len {
+                return RespValue::Integer(lcs.sequence.len() as i64);
+            }
+            if !want_idx {
+                return RespValue::BulkBytes(Some(lcs.sequence));
+            }
+
+            let range = |(start, end): (usize, usize)| {
+                RespValue::Array(vec![
+                    RespValue::Integer(start as i64),
+                    RespValue::Integer(end as i64),
+                ])
             };
+            let matches = lcs
+                .matches
+                .iter()
+                .map(|found| {
+                    let mut item = vec![range(found.a), range(found.b)];
+                    if with_match_len {
+                        item.push(RespValue::Integer(found.len() as i64));
+                    }
+                    RespValue::Array(item)
+                })
+                .collect();
+
+            RespValue::Array(vec![
+                RespValue::BulkString(Some("matches".to_string())),
+                RespValue::Array(matches),
+                RespValue::BulkString(Some("len".to_string())),
+                RespValue::Integer(lcs.sequence.len() as i64),
+            ])
+        }
+        "INCR" | "DECR" | "INCRBY" | "DECRBY" => {
+            let name = command[0].to_lowercase();
+            let expected = if name.ends_with("by") { 3 } else { 2 };
+            if command.len() != expected {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
 
-            let timeout = if timeout_secs == 0.0 {
-                None
+            let amount = match command.get(2).map(|n| n.parse::<i64>()) {
+                None => 1,
+                Some(Ok(n)) => n,
+                Some(Err(_)) => {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                }
+            };
+            let delta = if name.starts_with("decr") {
+                match amount.checked_neg() {
+                    Some(delta) => delta,
+                    None => return RespValue::Error("decrement would overflow".to_string()),
+                }
             } else {
-                Some(SystemTime::now() + Duration::from_secs_f64(timeout_secs))
+                amount
             };
 
-            match memory.blpop(&command[1], timeout).await {
-                Some((key, value)) => RespValue::Array(vec![
-                    RespValue::BulkString(Some(key)),
-                    RespValue::BulkString(Some(value)),
-                ]),
-                None => RespValue::NullArray,
+            match memory.incr_by(command[1].clone(), delta) {
+                Ok(value) => RespValue::Integer(value),
+                Err(err) => RespValue::Error(err.to_string()),
             }
         }
-        "LRANGE" => {
-            if command.len() < 4 {
+        "INCRBYFLOAT" => {
+            if command.len() != 3 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'lrange' command".to_string(),
+                    "wrong number of arguments for 'incrbyfloat' command".to_string(),
                 );
             }
 
-            let start = match command[2].parse::<isize>() {
-                Ok(num) => num,
-                Err(_) => {
-                    return RespValue::Error("value is not an integer or out of range".to_string());
-                }
+            let delta = match command[2].parse::<f64>() {
+                Ok(delta) if !delta.is_nan() => delta,
+                _ => return RespValue::Error("value is not a valid float".to_string()),
             };
 
-            let stop = match command[3].parse::<isize>() {
-                Ok(num) => num,
-                Err(_) => {
-                    return RespValue::Error("value is not an integer or out of range".to_string());
+            match memory.incr_by_float(command[1].clone(), delta) {
+                Ok(value) => RespValue::BulkString(Some(value)),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SETBIT" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'setbit' command".to_string(),
+                );
+            }
+
+            let offset = match parse_bit_offset(&command[2], 1) {
+                Ok(offset) => offset,
+                Err(err) => return err,
+            };
+            let bit = match command[3].as_str() {
+                "0" => false,
+                "1" => true,
+                _ => {
+                    return RespValue::Error("bit is not an integer or out of range".to_string());
                 }
             };
 
-            let values = memory.lrange(&command[1], start, stop);
-
-            RespValue::Array(
-                values
-                    .into_iter()
-                    .map(|v| RespValue::BulkString(Some(v)))
-                    .collect(),
-            )
+            match memory.setbit(command[1].clone(), offset, bit) {
+                Ok(previous) => RespValue::Integer(previous as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
         }
-        "LLEN" => {
-            if command.len() < 2 {
+        "GETBIT" => {
+            if command.len() != 3 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'llen' command".to_string(),
+                    "wrong number of arguments for 'getbit' command".to_string(),
                 );
             }
 
-            RespValue::Integer(memory.llen(&command[1]) as i64)
+            let offset = match parse_bit_offset(&command[2], 1) {
+                Ok(offset) => offset,
+                Err(err) => return err,
+            };
+
+            match memory.getbit(&command[1], offset) {
+                Ok(bit) => RespValue::Integer(bit as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
         }
-        "TYPE" => {
+        "BITCOUNT" => {
             if command.len() < 2 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'type' command".to_string(),
+                    "wrong number of arguments for 'bitcount' command".to_string(),
                 );
             }
 
-            RespValue::SimpleString(memory.type_of(&command[1]).into())
+            let range = match command.len() {
+                2 => None,
+                4 | 5 => {
+                    let (Ok(start), Ok(end)) =
+                        (command[2].parse::<i64>(), command[3].parse::<i64>())
+                    else {
+                        return RespValue::Error(
+                            "value is not an integer or out of range".to_string(),
+                        );
+                    };
+                    let unit = match parse_bit_unit(command.get(4)) {
+                        Ok(unit) => unit,
+                        Err(err) => return err,
+                    };
+                    Some((start, end, unit))
+                }
+                _ => return RespValue::Error("syntax error".to_string()),
+            };
+
+            match memory.bitcount(&command[1], range) {
+                Ok(count) => RespValue::Integer(count as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
         }
-        "XADD" => {
-            if command.len() < 4 {
+        "BITPOS" => {
+            if command.len() < 3 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'xadd' command".to_string(),
+                    "wrong number of arguments for 'bitpos' command".to_string(),
                 );
             }
+            if command.len() > 6 {
+                return RespValue::Error("syntax error".to_string());
+            }
 
-            let id = command[2].clone();
-            let mut map = HashMap::new();
-
-            let mut iter = command.iter().skip(3);
-            while let Some(key) = iter.next() {
-                if let Some(value) = iter.next() {
-                    map.insert(key.clone(), value.clone());
-                } else {
-                    return RespValue::Error("syntax error".to_string());
+            let bit = match command[2].as_str() {
+                "0" => false,
+                "1" => true,
+                _ => return RespValue::Error("The bit argument must be 1 or 0.".to_string()),
+            };
+            let mut bounds = [None, None];
+            for (bound, arg) in bounds.iter_mut().zip(command.iter().skip(3)) {
+                match arg.parse::<i64>() {
+                    Ok(n) => *bound = Some(n),
+                    Err(_) => {
+                        return RespValue::Error(
+                            "value is not an integer or out of range".to_string(),
+                        );
+                    }
                 }
             }
+            let unit = match parse_bit_unit(command.get(5)) {
+                Ok(unit) => unit,
+                Err(err) => return err,
+            };
 
-            match memory.xadd(command[1].clone(), id.clone(), map) {
-                Ok(id) => RespValue::BulkString(Some(id.into())),
+            match memory.bitpos(&command[1], bit, bounds[0], bounds[1], unit) {
+                Ok(pos) => RespValue::Integer(pos),
                 Err(err) => RespValue::Error(err.to_string()),
             }
         }
-        "XRANGE" => {
+        "BITOP" => {
             if command.len() < 4 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'xrange' command".to_string(),
+                    "wrong number of arguments for 'bitop' command".to_string(),
                 );
             }
 
-            let start = command[2].clone();
-            let end = command[3].clone();
+            let operation = match command[1].to_uppercase().as_str() {
+                "AND" => BitOperation::And,
+                "OR" => BitOperation::Or,
+                "XOR" => BitOperation::Xor,
+                "NOT" => BitOperation::Not,
+                "DIFF" => BitOperation::Diff,
+                _ => return RespValue::Error("syntax error".to_string()),
+            };
+            let keys = &command[3..];
+            if operation == BitOperation::Not && keys.len() != 1 {
+                return RespValue::Error(
+                    "BITOP NOT must be called with a single source key.".to_string(),
+                );
+            }
+            if operation == BitOperation::Diff && keys.len() < 2 {
+                return RespValue::Error(
+                    "BITOP DIFF must be called with at least two source keys.".to_string(),
+                );
+            }
 
-            match memory.xrange(&command[1], start, end) {
-                Ok(stream_value) => {
-                    let mut result = Vec::new();
-                    for (id, fields) in stream_value.0 {
-                        let mut entry = Vec::new();
-                        entry.push(RespValue::BulkString(Some(id)));
-                        let mut field_values = Vec::new();
-                        for (field, value) in fields {
-                            field_values.push(RespValue::BulkString(Some(field)));
-                            field_values.push(RespValue::BulkString(Some(value)));
+            match memory.bitop(operation, command[2].clone(), keys) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "BITFIELD" | "BITFIELD_RO" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 2 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let read_only = name == "bitfield_ro";
+            let mut overflow = BitfieldOverflow::default();
+            let mut ops = Vec::new();
+            let mut i = 2;
+            while i < command.len() {
+                let subcommand = command[i].to_uppercase();
+                if read_only && subcommand != "GET" {
+                    return RespValue::Error(
+                        "BITFIELD_RO only supports the GET subcommand".to_string(),
+                    );
+                }
+
+                let arity = match subcommand.as_str() {
+                    "GET" => 3,
+                    "SET" | "INCRBY" => 4,
+                    "OVERFLOW" => 2,
+                    _ => return RespValue::Error("syntax error".to_string()),
+                };
+                if i + arity > command.len() {
+                    return RespValue::Error("syntax error".to_string());
+                }
+                let args = &command[i + 1..i + arity];
+                i += arity;
+
+                if subcommand == "OVERFLOW" {
+                    overflow = match args[0].to_uppercase().as_str() {
+                        "WRAP" => BitfieldOverflow::Wrap,
+                        "SAT" => BitfieldOverflow::Sat,
+                        "FAIL" => BitfieldOverflow::Fail,
+                        _ => {
+                            return RespValue::Error("Invalid OVERFLOW type specified".to_string());
                         }
-                        entry.push(RespValue::Array(field_values));
-                        result.push(RespValue::Array(entry));
-                    }
-                    RespValue::Array(result)
+                    };
+                    continue;
+                }
+
+                let ty = match parse_bitfield_type(&args[0]) {
+                    Ok(ty) => ty,
+                    Err(err) => return err,
+                };
+                let offset = match parse_bitfield_offset(&args[1], ty) {
+                    Ok(offset) => offset,
+                    Err(err) => return err,
+                };
+                if subcommand == "GET" {
+                    ops.push(BitfieldOp::Get(ty, offset));
+                    continue;
                 }
+
+                let Ok(value) = args[2].parse::<i64>() else {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                };
+                ops.push(if subcommand == "SET" {
+                    BitfieldOp::Set(ty, offset, value, overflow)
+                } else {
+                    BitfieldOp::IncrBy(ty, offset, value, overflow)
+                });
+            }
+
+            match memory.bitfield(command[1].clone(), &ops) {
+                Ok(results) => RespValue::Array(
+                    results
+                        .into_iter()
+                        .map(|result| result.map_or(RespValue::Null, RespValue::Integer))
+                        .collect(),
+                ),
                 Err(err) => RespValue::Error(err.to_string()),
             }
         }
-        "XREAD" => {
-            if command.len() < 4 {
+        "PFADD" => {
+            if command.len() < 2 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'xread' command".to_string(),
+                    "wrong number of arguments for 'pfadd' command".to_string(),
                 );
             }
 
-            let streams_idx = command.iter().position(|s| s.to_uppercase() == "STREAMS");
-            if streams_idx.is_none() {
-                return RespValue::Error("syntax error".to_string());
+            match memory.pfadd(command[1].clone(), &request[2..]) {
+                Ok(updated) => RespValue::Integer(updated as i64),
+                Err(err) => RespValue::Error(err.to_string()),
             }
-            let streams_idx = streams_idx.unwrap();
-
-            let args = &command[streams_idx + 1..];
-            if args.len() % 2 != 0 {
+        }
+        "PFCOUNT" => {
+            if command.len() < 2 {
                 return RespValue::Error(
-                    "wrong number of arguments for 'xread' command".to_string(),
+                    "wrong number of arguments for 'pfcount' command".to_string(),
                 );
             }
 
-            let num_streams = args.len() / 2;
-            let keys = &args[0..num_streams];
-            let ids = &args[num_streams..];
-
-            let mut key_id = Vec::new();
-            for i in 0..num_streams {
-                key_id.push((keys[i].clone(), ids[i].clone()));
+            match memory.pfcount(&command[1..]) {
+                Ok(cardinality) => RespValue::Integer(cardinality as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "PFMERGE" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'pfmerge' command".to_string(),
+                );
             }
 
-            match memory.xread(key_id.clone()) {
-                Ok(stream_values) => {
-                    let mut response = Vec::new();
-                    for (i, stream_value) in stream_values.iter().enumerate() {
-                        let mut entries = Vec::new();
-                        for (id, fields) in &stream_value.0 {
-                            let mut entry = Vec::new();
-                            entry.push(RespValue::BulkString(Some(id.clone())));
-                            let mut field_values = Vec::new();
-                            for (field, value) in fields {
-                                field_values.push(RespValue::BulkString(Some(field.clone())));
-                                field_values.push(RespValue::BulkString(Some(value.clone())));
-                            }
-                            entry.push(RespValue::Array(field_values));
-                            entries.push(RespValue::Array(entry));
-                        }
-                        let stream_response = vec![
-                            RespValue::BulkString(Some(key_id[i].0.clone())),
-                            RespValue::Array(entries),
-                        ];
-
-                        response.push(RespValue::Array(stream_response));
-                    }
-                    RespValue::Array(response)
-                }
+            match memory.pfmerge(command[1].clone(), &command[2..]) {
+                Ok(()) => RespValue::SimpleString("OK".to_string()),
                 Err(err) => RespValue::Error(err.to_string()),
             }
         }
-        _ => RespValue::Error("unknown command".to_string()),
+        "DEL" => {
+            if command.len() < 2 {
+                return RespValue::Error("wrong number of arguments for 'del' command".to_string());
+            }
+
+            RespValue::Integer(memory.del(&command[1..]) as i64)
+        }
+        "EXISTS" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'exists' command".to_string(),
+                );
+            }
+
+            RespValue::Integer(memory.exists(&command[1..]) as i64)
+        }
+        "RPUSH" | "LPUSH" | "RPUSHX" | "LPUSHX" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 3 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let end = if name.starts_with('l') {
+                ListEnd::Left
+            } else {
+                ListEnd::Right
+            };
+            let only_if_exists = name.ends_with('x');
+            match memory.push(
+                command[1].clone(),
+                end,
+                request[2..].to_vec(),
+                only_if_exists,
+            ) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LPOP" | "RPOP" => {
+            let name = command[0].to_lowercase();
+            if !(2..=3).contains(&command.len()) {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let count = match command.get(2).map(|count| count.parse::<usize>()) {
+                None => None,
+                Some(Ok(count)) => Some(count),
+                Some(Err(_)) => {
+                    return RespValue::Error("value is out of range, must be positive".to_string());
+                }
+            };
+            let end = if name == "lpop" {
+                ListEnd::Left
+            } else {
+                ListEnd::Right
+            };
+
+            match (memory.pop(&command[1], end, count.unwrap_or(1)), count) {
+                (Ok(None), None) => RespValue::Null,
+                (Ok(None), Some(_)) => RespValue::NullArray,
+                (Ok(Some(values)), None) => values
+                    .into_iter()
+                    .next()
+                    .map_or(RespValue::Null, |value| RespValue::BulkBytes(Some(value))),
+                (Ok(Some(values)), Some(_)) => RespValue::Array(
+                    values
+                        .into_iter()
+                        .map(|v| RespValue::BulkBytes(Some(v)))
+                        .collect(),
+                ),
+                (Err(err), _) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LMOVE" | "RPOPLPUSH" | "BLMOVE" | "BRPOPLPUSH" => {
+            let name = command[0].to_lowercase();
+            let arity = match name.as_str() {
+                "rpoplpush" => 3,
+                "brpoplpush" => 4,
+                "lmove" => 5,
+                _ => 6,
+            };
+            if command.len() != arity {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let (from, to) = if name.ends_with("rpoplpush") {
+                (ListEnd::Right, ListEnd::Left)
+            } else {
+                match (parse_list_end(&command[3]), parse_list_end(&command[4])) {
+                    (Ok(from), Ok(to)) => (from, to),
+                    (Err(err), _) | (_, Err(err)) => return err,
+                }
+            };
+
+            let result = if name.starts_with('b') {
+                let timeout = match parse_timeout(&command[arity - 1]) {
+                    Ok(timeout) => timeout,
+                    Err(err) => return err,
+                };
+                memory
+                    .blmove(
+                        client.id,
+                        command[1].clone(),
+                        command[2].clone(),
+                        from,
+                        to,
+                        timeout,
+                    )
+                    .await
+            } else {
+                memory.lmove(&command[1], &command[2], from, to)
+            };
+
+            match result {
+                Ok(value) => RespValue::BulkBytes(value),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LINDEX" => {
+            if command.len() != 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'lindex' command".to_string(),
+                );
+            }
+
+            let Ok(index) = command[2].parse::<i64>() else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+
+            match memory.lindex(&command[1], index) {
+                Ok(value) => RespValue::BulkBytes(value),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LSET" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'lset' command".to_string(),
+                );
+            }
+
+            let Ok(index) = command[2].parse::<i64>() else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+
+            match memory.lset(&command[1], index, request[3].clone()) {
+                Ok(()) => RespValue::SimpleString("OK".to_string()),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LINSERT" => {
+            if command.len() != 5 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'linsert' command".to_string(),
+                );
+            }
+
+            let position = match command[2].to_uppercase().as_str() {
+                "BEFORE" => InsertPosition::Before,
+                "AFTER" => InsertPosition::After,
+                _ => return RespValue::Error("syntax error".to_string()),
+            };
+
+            match memory.linsert(&command[1], position, &request[3], request[4].clone()) {
+                Ok(len) => RespValue::Integer(len),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LREM" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'lrem' command".to_string(),
+                );
+            }
+
+            let Ok(count) = command[2].parse::<i64>() else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+
+            match memory.lrem(&command[1], count, &request[3]) {
+                Ok(removed) => RespValue::Integer(removed as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LTRIM" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'ltrim' command".to_string(),
+                );
+            }
+
+            let (Ok(start), Ok(stop)) = (command[2].parse::<i64>(), command[3].parse::<i64>())
+            else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+
+            match memory.ltrim(&command[1], start, stop) {
+                Ok(()) => RespValue::SimpleString("OK".to_string()),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LPOS" => {
+            if command.len() < 3 || command.len().is_multiple_of(2) {
+                return RespValue::Error(if command.len() < 3 {
+                    "wrong number of arguments for 'lpos' command".to_string()
+                } else {
+                    "syntax error".to_string()
+                });
+            }
+
+            let (mut rank, mut count, mut maxlen) = (1, None, 0);
+            for option in command[3..].chunks(2) {
+                let Ok(value) = option[1].parse::<i64>() else {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                };
+                match option[0].to_uppercase().as_str() {
+                    "RANK" if value == 0 => {
+                        return RespValue::Error(
+                            "RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the last match"
+                                .to_string(),
+                        );
+                    }
+                    "RANK" if value == i64::MIN => {
+                        return RespValue::Error(format!(
+                            "value is out of range, value must between {} and {}",
+                            -i64::MAX,
+                            i64::MAX
+                        ));
+                    }
+                    "RANK" => rank = value,
+                    "COUNT" if value < 0 => {
+                        return RespValue::Error("COUNT can't be negative".to_string());
+                    }
+                    "COUNT" => count = Some(value as usize),
+                    "MAXLEN" if value < 0 => {
+                        return RespValue::Error("MAXLEN can't be negative".to_string());
+                    }
+                    "MAXLEN" => maxlen = value as usize,
+                    _ => return RespValue::Error("syntax error".to_string()),
+                }
+            }
+
+            match memory.lpos(&command[1], &request[2], rank, count.unwrap_or(1), maxlen) {
+                Ok(positions) if count.is_some() => RespValue::Array(
+                    positions
+                        .into_iter()
+                        .map(|position| RespValue::Integer(position as i64))
+                        .collect(),
+                ),
+                Ok(positions) => positions.first().map_or(RespValue::Null, |&position| {
+                    RespValue::Integer(position as i64)
+                }),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "BLPOP" | "BRPOP" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 3 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let timeout = match parse_timeout(&command[command.len() - 1]) {
+                Ok(timeout) => timeout,
+                Err(err) => return err,
+            };
+            let end = if name == "blpop" {
+                ListEnd::Left
+            } else {
+                ListEnd::Right
+            };
+
+            let keys = command[1..command.len() - 1].to_vec();
+            match memory.bmpop(client.id, keys, end, 1, timeout).await {
+                Ok(Some((key, mut values))) => RespValue::Array(vec![
+                    RespValue::BulkString(Some(key)),
+                    RespValue::BulkBytes(values.pop()),
+                ]),
+                Ok(None) => RespValue::NullArray,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LMPOP" | "BLMPOP" => {
+            let name = command[0].to_lowercase();
+            let blocking = name == "blmpop";
+            let first_arg = if blocking { 2 } else { 1 };
+            if command.len() < first_arg + 3 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let (keys, end, count) = match parse_mpop_args(&command[first_arg..]) {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            let end = match parse_list_end(end) {
+                Ok(end) => end,
+                Err(err) => return err,
+            };
+
+            let result = if blocking {
+                let timeout = match parse_timeout(&command[1]) {
+                    Ok(timeout) => timeout,
+                    Err(err) => return err,
+                };
+                memory
+                    .bmpop(client.id, keys.to_vec(), end, count, timeout)
+                    .await
+            } else {
+                memory.mpop(keys, end, count)
+            };
+
+            match result {
+                Ok(Some((key, values))) => RespValue::Array(vec![
+                    RespValue::BulkString(Some(key)),
+                    RespValue::Array(
+                        values
+                            .into_iter()
+                            .map(|v| RespValue::BulkBytes(Some(v)))
+                            .collect(),
+                    ),
+                ]),
+                Ok(None) => RespValue::NullArray,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LRANGE" => {
+            if command.len() < 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'lrange' command".to_string(),
+                );
+            }
+
+            let start = match command[2].parse::<i64>() {
+                Ok(num) => num,
+                Err(_) => {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                }
+            };
+
+            let stop = match command[3].parse::<i64>() {
+                Ok(num) => num,
+                Err(_) => {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                }
+            };
+
+            match memory.lrange(&command[1], start, stop) {
+                Ok(values) => RespValue::Array(
+                    values
+                        .into_iter()
+                        .map(|v| RespValue::BulkBytes(Some(v)))
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "LLEN" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'llen' command".to_string(),
+                );
+            }
+
+            match memory.llen(&command[1]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HSET" | "HMSET" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 4 || !command.len().is_multiple_of(2) {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let pairs = request[2..]
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            match memory.hset(&command[1], pairs) {
+                Ok(_) if name == "hmset" => RespValue::SimpleString("OK".to_string()),
+                Ok(added) => RespValue::Integer(added as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HSETNX" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hsetnx' command".to_string(),
+                );
+            }
+
+            match memory.hsetnx(&command[1], request[2].clone(), request[3].clone()) {
+                Ok(set) => RespValue::Integer(set as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HGET" => {
+            if command.len() != 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hget' command".to_string(),
+                );
+            }
+
+            match memory.hget(&command[1], &request[2]) {
+                Ok(value) => RespValue::BulkBytes(value),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HMGET" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hmget' command".to_string(),
+                );
+            }
+
+            match memory.hmget(&command[1], &request[2..]) {
+                Ok(values) => {
+                    RespValue::Array(values.into_iter().map(RespValue::BulkBytes).collect())
+                }
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HDEL" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hdel' command".to_string(),
+                );
+            }
+
+            match memory.hdel(&command[1], &request[2..]) {
+                Ok(removed) => RespValue::Integer(removed as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HGETALL" => {
+            if command.len() != 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hgetall' command".to_string(),
+                );
+            }
+
+            match memory.hgetall(&command[1]) {
+                Ok(pairs) => field_value_array(pairs, true),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HEXISTS" => {
+            if command.len() != 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hexists' command".to_string(),
+                );
+            }
+
+            match memory.hexists(&command[1], &request[2]) {
+                Ok(exists) => RespValue::Integer(exists as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HLEN" => {
+            if command.len() != 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hlen' command".to_string(),
+                );
+            }
+
+            match memory.hlen(&command[1]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HKEYS" | "HVALS" => {
+            let name = command[0].to_lowercase();
+            if command.len() != 2 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let values = if name == "hkeys" {
+                memory.hkeys(&command[1])
+            } else {
+                memory.hvals(&command[1])
+            };
+            match values {
+                Ok(values) => RespValue::Array(
+                    values
+                        .into_iter()
+                        .map(|value| RespValue::BulkBytes(Some(value)))
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HSTRLEN" => {
+            if command.len() != 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hstrlen' command".to_string(),
+                );
+            }
+
+            match memory.hstrlen(&command[1], &request[2]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HINCRBY" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hincrby' command".to_string(),
+                );
+            }
+
+            let Ok(delta) = command[3].parse::<i64>() else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+
+            match memory.hincrby(&command[1], request[2].clone(), delta) {
+                Ok(value) => RespValue::Integer(value),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HINCRBYFLOAT" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hincrbyfloat' command".to_string(),
+                );
+            }
+
+            let delta = match command[3].parse::<f64>() {
+                Ok(delta) if !delta.is_nan() => delta,
+                _ => return RespValue::Error("value is not a valid float".to_string()),
+            };
+
+            match memory.hincrbyfloat(&command[1], request[2].clone(), delta) {
+                Ok(value) => RespValue::BulkString(Some(value)),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HRANDFIELD" => {
+            if !(2..=4).contains(&command.len()) {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hrandfield' command".to_string(),
+                );
+            }
+
+            let Some(count) = command.get(2) else {
+                return match memory.hrandfield(&command[1], -1) {
+                    Ok(pairs) => RespValue::BulkBytes(
+                        pairs
+                            .and_then(|pairs| pairs.into_iter().next())
+                            .map(|(field, _)| field),
+                    ),
+                    Err(err) => RespValue::Error(err.to_string()),
+                };
+            };
+            let Ok(count) = count.parse::<i64>() else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+            let with_values = match command
+                .get(3)
+                .map(|option| option.to_uppercase())
+                .as_deref()
+            {
+                None => false,
+                Some("WITHVALUES") => true,
+                Some(_) => return RespValue::Error("syntax error".to_string()),
+            };
+            // The reply holds two entries per field, which must not overflow.
+            if with_values && count < -(i64::MAX / 2) {
+                return RespValue::Error("value is out of range".to_string());
+            }
+
+            match memory.hrandfield(&command[1], count) {
+                Ok(pairs) => field_value_array(pairs.unwrap_or_default(), with_values),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HSCAN" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hscan' command".to_string(),
+                );
+            }
+
+            let Ok(cursor) = command[2].parse::<u64>() else {
+                return RespValue::Error("invalid cursor".to_string());
+            };
+            let (pattern, count, no_values) = match parse_scan_options(&command[3..], &request[3..])
+            {
+                Ok(options) => options,
+                Err(err) => return err,
+            };
+
+            match memory.hscan(&command[1], cursor, pattern, count) {
+                Ok((cursor, pairs)) => RespValue::Array(vec![
+                    RespValue::BulkString(Some(cursor.to_string())),
+                    field_value_array(pairs, !no_values),
+                ]),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HEXPIRE" | "HPEXPIRE" | "HEXPIREAT" | "HPEXPIREAT" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 6 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let Ok(amount) = command[2].parse::<i64>() else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+            if amount < 0 {
+                return RespValue::Error("invalid expire time, must be >= 0".to_string());
+            }
+            let now = memory.clock().now_ms() as i64;
+            let at = match name.as_str() {
+                "hexpire" => amount.checked_mul(1000).and_then(|ms| ms.checked_add(now)),
+                "hpexpire" => amount.checked_add(now),
+                "hexpireat" => amount.checked_mul(1000),
+                _ => Some(amount),
+            };
+            let Some(at) = at.filter(|&at| at <= MAX_FIELD_EXPIRATION_MS) else {
+                return RespValue::Error(format!("invalid expire time in '{name}' command"));
+            };
+
+            let (condition, first) = match command[3].to_uppercase().as_str() {
+                "NX" => (Some(ExpireCondition::IfNone), 4),
+                "XX" => (Some(ExpireCondition::IfSome), 4),
+                "GT" => (Some(ExpireCondition::Greater), 4),
+                "LT" => (Some(ExpireCondition::Less), 4),
+                _ => (None, 3),
+            };
+            let fields = match parse_fields(&command[first..], &request[first..], 1) {
+                Ok(fields) => fields,
+                Err(err) => return err,
+            };
+
+            match memory.hexpire(&command[1], at as u64, condition, fields) {
+                Ok(replies) => {
+                    RespValue::Array(replies.into_iter().map(RespValue::Integer).collect())
+                }
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HTTL" | "HPTTL" | "HEXPIRETIME" | "HPEXPIRETIME" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 5 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let fields = match parse_fields(&command[2..], &request[2..], 1) {
+                Ok(fields) => fields,
+                Err(err) => return err,
+            };
+
+            let now = memory.clock().now_ms() as i64;
+            match memory.hexpiretime(&command[1], fields) {
+                Ok(times) => RespValue::Array(
+                    times
+                        .into_iter()
+                        .map(|at| match name.as_str() {
+                            _ if at < 0 => at,
+                            "httl" => (at - now + 999) / 1000,
+                            "hpttl" => at - now,
+                            "hexpiretime" => (at + 999) / 1000,
+                            _ => at,
+                        })
+                        .map(RespValue::Integer)
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HPERSIST" => {
+            if command.len() < 5 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hpersist' command".to_string(),
+                );
+            }
+
+            let fields = match parse_fields(&command[2..], &request[2..], 1) {
+                Ok(fields) => fields,
+                Err(err) => return err,
+            };
+
+            match memory.hpersist(&command[1], fields) {
+                Ok(replies) => {
+                    RespValue::Array(replies.into_iter().map(RespValue::Integer).collect())
+                }
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HGETEX" => {
+            if command.len() < 5 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hgetex' command".to_string(),
+                );
+            }
+
+            let (expiration, first) = match command[2].to_uppercase().as_str() {
+                "PERSIST" => (SetExpiration::Clear, 3),
+                unit @ ("EX" | "PX" | "EXAT" | "PXAT") => {
+                    match parse_expiration(&memory, unit, &command[3], "hgetex") {
+                        Ok(at) => (SetExpiration::At(at), 4),
+                        Err(err) => return err,
+                    }
+                }
+                _ => (SetExpiration::Keep, 2),
+            };
+            let fields = match parse_fields(&command[first..], &request[first..], 1) {
+                Ok(fields) => fields,
+                Err(err) => return err,
+            };
+
+            match memory.hgetex(&command[1], fields, expiration) {
+                Ok(values) => {
+                    RespValue::Array(values.into_iter().map(RespValue::BulkBytes).collect())
+                }
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "HSETEX" => {
+            if command.len() < 6 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'hsetex' command".to_string(),
+                );
+            }
+
+            let (mut condition, mut expiration) = (None, None);
+            let mut rest = &command[2..];
+            while let Some(option) = rest.first() {
+                match option.to_uppercase().as_str() {
+                    "FNX" if condition.is_none() => condition = Some(SetCondition::IfMissing),
+                    "FXX" if condition.is_none() => condition = Some(SetCondition::IfExists),
+                    "KEEPTTL" if expiration.is_none() => expiration = Some(SetExpiration::Keep),
+                    unit @ ("EX" | "PX" | "EXAT" | "PXAT")
+                        if expiration.is_none() && rest.len() > 1 =>
+                    {
+                        match parse_expiration(&memory, unit, &rest[1], "hsetex") {
+                            Ok(at) => expiration = Some(SetExpiration::At(at)),
+                            Err(err) => return err,
+                        }
+                        rest = &rest[1..];
+                    }
+                    "FIELDS" => break,
+                    _ => return RespValue::Error("syntax error".to_string()),
+                }
+                rest = &rest[1..];
+            }
+            let first = command.len() - rest.len();
+            let fields = match parse_fields(rest, &request[first..], 2) {
+                Ok(fields) => fields,
+                Err(err) => return err,
+            };
+
+            let pairs = fields
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            match memory.hsetex(
+                &command[1],
+                pairs,
+                condition,
+                expiration.unwrap_or_default(),
+            ) {
+                Ok(set) => RespValue::Integer(set as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SADD" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'sadd' command".to_string(),
+                );
+            }
+
+            match memory.sadd(&command[1], request[2..].to_vec()) {
+                Ok(added) => RespValue::Integer(added as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SREM" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'srem' command".to_string(),
+                );
+            }
+
+            match memory.srem(&command[1], &request[2..]) {
+                Ok(removed) => RespValue::Integer(removed as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SMEMBERS" => {
+            if command.len() != 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'smembers' command".to_string(),
+                );
+            }
+
+            match memory.smembers(&command[1]) {
+                Ok(members) => RespValue::Array(
+                    members
+                        .into_iter()
+                        .map(|member| RespValue::BulkBytes(Some(member)))
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SISMEMBER" => {
+            if command.len() != 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'sismember' command".to_string(),
+                );
+            }
+
+            match memory.sismember(&command[1], &request[2]) {
+                Ok(present) => RespValue::Integer(present as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SMISMEMBER" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'smismember' command".to_string(),
+                );
+            }
+
+            match memory.smismember(&command[1], &request[2..]) {
+                Ok(present) => RespValue::Array(
+                    present
+                        .into_iter()
+                        .map(|present| RespValue::Integer(present as i64))
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SCARD" => {
+            if command.len() != 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'scard' command".to_string(),
+                );
+            }
+
+            match memory.scard(&command[1]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SPOP" => {
+            if !(2..=3).contains(&command.len()) {
+                return RespValue::Error(
+                    "wrong number of arguments for 'spop' command".to_string(),
+                );
+            }
+
+            let Some(count) = command.get(2) else {
+                return match memory.spop(&command[1], 1) {
+                    Ok(members) => {
+                        RespValue::BulkBytes(members.and_then(|members| members.into_iter().next()))
+                    }
+                    Err(err) => RespValue::Error(err.to_string()),
+                };
+            };
+            let count = match count.parse::<i64>() {
+                Ok(count) if count >= 0 => count as usize,
+                Ok(_) => {
+                    return RespValue::Error("value is out of range, must be positive".to_string());
+                }
+                Err(_) => {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                }
+            };
+
+            match memory.spop(&command[1], count) {
+                Ok(members) => RespValue::Array(
+                    members
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|member| RespValue::BulkBytes(Some(member)))
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SRANDMEMBER" => {
+            if !(2..=3).contains(&command.len()) {
+                return RespValue::Error(
+                    "wrong number of arguments for 'srandmember' command".to_string(),
+                );
+            }
+
+            let Some(count) = command.get(2) else {
+                return match memory.srandmember(&command[1], 1) {
+                    Ok(members) => {
+                        RespValue::BulkBytes(members.and_then(|members| members.into_iter().next()))
+                    }
+                    Err(err) => RespValue::Error(err.to_string()),
+                };
+            };
+            let Ok(count) = count.parse::<i64>() else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+            if count == i64::MIN {
+                return RespValue::Error("value is out of range".to_string());
+            }
+
+            match memory.srandmember(&command[1], count) {
+                Ok(members) => RespValue::Array(
+                    members
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|member| RespValue::BulkBytes(Some(member)))
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SMOVE" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'smove' command".to_string(),
+                );
+            }
+
+            match memory.smove(&command[1], &command[2], request[3].clone()) {
+                Ok(moved) => RespValue::Integer(moved as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SINTER" | "SUNION" | "SDIFF" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 2 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let operation = match name.as_str() {
+                "sinter" => SetOperation::Inter,
+                "sunion" => SetOperation::Union,
+                _ => SetOperation::Diff,
+            };
+            match memory.set_operation(operation, &command[1..]) {
+                Ok(members) => RespValue::Array(
+                    members
+                        .into_iter()
+                        .map(|member| RespValue::BulkBytes(Some(member)))
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SINTERSTORE" | "SUNIONSTORE" | "SDIFFSTORE" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 3 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let operation = match name.as_str() {
+                "sinterstore" => SetOperation::Inter,
+                "sunionstore" => SetOperation::Union,
+                _ => SetOperation::Diff,
+            };
+            match memory.set_operation_store(operation, &command[1], &command[2..]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SINTERCARD" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'sintercard' command".to_string(),
+                );
+            }
+
+            let (keys, limit) = match parse_intercard_args(&command[1..]) {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+
+            match memory.sintercard(keys, limit) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SSCAN" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'sscan' command".to_string(),
+                );
+            }
+
+            let Ok(cursor) = command[2].parse::<u64>() else {
+                return RespValue::Error("invalid cursor".to_string());
+            };
+            let (pattern, count, no_values) = match parse_scan_options(&command[3..], &request[3..])
+            {
+                Ok(options) => options,
+                Err(err) => return err,
+            };
+            if no_values {
+                return RespValue::Error("NOVALUES option can only be used in HSCAN".to_string());
+            }
+
+            match memory.sscan(&command[1], cursor, pattern, count) {
+                Ok((cursor, members)) => RespValue::Array(vec![
+                    RespValue::BulkString(Some(cursor.to_string())),
+                    RespValue::Array(
+                        members
+                            .into_iter()
+                            .map(|member| RespValue::BulkBytes(Some(member)))
+                            .collect(),
+                    ),
+                ]),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZADD" => {
+            if command.len() < 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zadd' command".to_string(),
+                );
+            }
+
+            let mut options = ZAddOptions::default();
+            let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+            let mut rest = &command[2..];
+            while let Some(option) = rest.first() {
+                match option.to_uppercase().as_str() {
+                    "NX" => nx = true,
+                    "XX" => xx = true,
+                    "GT" => gt = true,
+                    "LT" => lt = true,
+                    "CH" => options.changed = true,
+                    "INCR" => options.increment = true,
+                    _ => break,
+                }
+                rest = &rest[1..];
+            }
+            if rest.is_empty() || !rest.len().is_multiple_of(2) {
+                return RespValue::Error("syntax error".to_string());
+            }
+            if nx && xx {
+                return RespValue::Error(
+                    "XX and NX options at the same time are not compatible".to_string(),
+                );
+            }
+            if (gt && lt) || (nx && (gt || lt)) {
+                return RespValue::Error(
+                    "GT, LT, and/or NX options at the same time are not compatible".to_string(),
+                );
+            }
+            if options.increment && rest.len() > 2 {
+                return RespValue::Error(
+                    "INCR option supports a single increment-element pair".to_string(),
+                );
+            }
+            options.condition = match (nx, xx) {
+                (true, _) => Some(SetCondition::IfMissing),
+                (_, true) => Some(SetCondition::IfExists),
+                _ => None,
+            };
+            options.comparison = match (gt, lt) {
+                (true, _) => Some(ScoreComparison::Greater),
+                (_, true) => Some(ScoreComparison::Less),
+                _ => None,
+            };
+
+            let mut pairs = Vec::with_capacity(rest.len() / 2);
+            for pair in rest.chunks(2) {
+                let Some(score) = parse_score(&pair[0]) else {
+                    return RespValue::Error("value is not a valid float".to_string());
+                };
+                pairs.push((score, pair[1].clone()));
+            }
+
+            match memory.zadd(&command[1], pairs, options) {
+                Ok((_, score)) if options.increment => {
+                    RespValue::BulkString(score.map(format_score))
+                }
+                Ok((count, _)) => RespValue::Integer(count as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZINCRBY" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zincrby' command".to_string(),
+                );
+            }
+
+            let Some(delta) = parse_score(&command[2]) else {
+                return RespValue::Error("value is not a valid float".to_string());
+            };
+            let options = ZAddOptions {
+                increment: true,
+                ..Default::default()
+            };
+            match memory.zadd(&command[1], vec![(delta, command[3].clone())], options) {
+                Ok((_, score)) => RespValue::BulkString(score.map(format_score)),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZREM" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zrem' command".to_string(),
+                );
+            }
+
+            match memory.zrem(&command[1], &command[2..]) {
+                Ok(removed) => RespValue::Integer(removed as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZSCORE" => {
+            if command.len() != 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zscore' command".to_string(),
+                );
+            }
+
+            match memory.zscore(&command[1], &command[2]) {
+                Ok(score) => RespValue::BulkString(score.map(format_score)),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZMSCORE" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zmscore' command".to_string(),
+                );
+            }
+
+            match memory.zmscore(&command[1], &command[2..]) {
+                Ok(scores) => RespValue::Array(
+                    scores
+                        .into_iter()
+                        .map(|score| RespValue::BulkString(score.map(format_score)))
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZRANK" | "ZREVRANK" => {
+            let name = command[0].to_lowercase();
+            if !(3..=4).contains(&command.len()) {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let with_score = match command.get(3) {
+                None => false,
+                Some(option) if option.eq_ignore_ascii_case("WITHSCORE") => true,
+                Some(_) => return RespValue::Error("syntax error".to_string()),
+            };
+            match memory.zrank(&command[1], &command[2], name == "zrevrank") {
+                Ok(Some((rank, score))) if with_score => RespValue::Array(vec![
+                    RespValue::Integer(rank as i64),
+                    RespValue::BulkString(Some(format_score(score))),
+                ]),
+                Ok(Some((rank, _))) => RespValue::Integer(rank as i64),
+                Ok(None) if with_score => RespValue::NullArray,
+                Ok(None) => RespValue::BulkString(None),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZCARD" => {
+            if command.len() != 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zcard' command".to_string(),
+                );
+            }
+
+            match memory.zcard(&command[1]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZCOUNT" => {
+            if command.len() != 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zcount' command".to_string(),
+                );
+            }
+
+            let range = match parse_score_range(&command[2], &command[3]) {
+                Ok(range) => range,
+                Err(err) => return err,
+            };
+            match memory.zcount(&command[1], &range) {
+                Ok(count) => RespValue::Integer(count as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZRANGE" => {
+            if command.len() < 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zrange' command".to_string(),
+                );
+            }
+
+            let (spec, with_scores) = match parse_zrange(&command[2..], true) {
+                Ok(parsed) => parsed,
+                Err(err) => return err,
+            };
+            match memory.zrange(&command[1], &spec) {
+                Ok(elements) => member_score_array(elements, with_scores),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZRANGESTORE" => {
+            if command.len() < 5 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zrangestore' command".to_string(),
+                );
+            }
+
+            let (spec, _) = match parse_zrange(&command[3..], false) {
+                Ok(parsed) => parsed,
+                Err(err) => return err,
+            };
+            match memory.zrangestore(&command[1], &command[2], &spec) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZUNION" | "ZINTER" | "ZDIFF" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 3 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let operation = match name.as_str() {
+                "zunion" => SetOperation::Union,
+                "zinter" => SetOperation::Inter,
+                _ => SetOperation::Diff,
+            };
+            let args = match parse_zset_operation_args(&command[1..], operation, true, &name) {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            match memory.zset_operation(operation, args.keys, &args.weights, args.aggregate) {
+                Ok(elements) => member_score_array(elements, args.with_scores),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZUNIONSTORE" | "ZINTERSTORE" | "ZDIFFSTORE" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 4 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let operation = match name.as_str() {
+                "zunionstore" => SetOperation::Union,
+                "zinterstore" => SetOperation::Inter,
+                _ => SetOperation::Diff,
+            };
+            let args = match parse_zset_operation_args(&command[2..], operation, false, &name) {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            match memory.zset_operation_store(
+                operation,
+                &command[1],
+                args.keys,
+                &args.weights,
+                args.aggregate,
+            ) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZINTERCARD" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'zintercard' command".to_string(),
+                );
+            }
+
+            let (keys, limit) = match parse_intercard_args(&command[1..]) {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            match memory.zintercard(keys, limit) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZPOPMIN" | "ZPOPMAX" => {
+            let name = command[0].to_lowercase();
+            if !(2..=3).contains(&command.len()) {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let count = match command.get(2).map(|count| count.parse::<i64>()) {
+                None => 1,
+                Some(Ok(count)) if count >= 0 => count as usize,
+                Some(Ok(_)) => {
+                    return RespValue::Error("value is out of range, must be positive".to_string());
+                }
+                Some(Err(_)) => {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                }
+            };
+            let end = if name == "zpopmin" {
+                ScoreEnd::Min
+            } else {
+                ScoreEnd::Max
+            };
+            match memory.zpop(&command[1], end, count) {
+                Ok(elements) => member_score_array(elements.unwrap_or_default(), true),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "BZPOPMIN" | "BZPOPMAX" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 3 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let timeout = match parse_timeout(&command[command.len() - 1]) {
+                Ok(timeout) => timeout,
+                Err(err) => return err,
+            };
+            let end = if name == "bzpopmin" {
+                ScoreEnd::Min
+            } else {
+                ScoreEnd::Max
+            };
+
+            let keys = command[1..command.len() - 1].to_vec();
+            match memory.bzmpop(client.id, keys, end, 1, timeout).await {
+                Ok(Some((key, mut elements))) => {
+                    let (member, score) = elements.pop().expect("pops return an element");
+                    RespValue::Array(vec![
+                        RespValue::BulkString(Some(key)),
+                        RespValue::BulkString(Some(member)),
+                        RespValue::BulkString(Some(format_score(score))),
+                    ])
+                }
+                Ok(None) => RespValue::NullArray,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "ZMPOP" | "BZMPOP" => {
+            let name = command[0].to_lowercase();
+            let blocking = name == "bzmpop";
+            let first_arg = if blocking { 2 } else { 1 };
+            if command.len() < first_arg + 3 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let (keys, end, count) = match parse_mpop_args(&command[first_arg..]) {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            let end = match parse_score_end(end) {
+                Ok(end) => end,
+                Err(err) => return err,
+            };
+
+            let result = if blocking {
+                let timeout = match parse_timeout(&command[1]) {
+                    Ok(timeout) => timeout,
+                    Err(err) => return err,
+                };
+                memory
+                    .bzmpop(client.id, keys.to_vec(), end, count, timeout)
+                    .await
+            } else {
+                memory.zmpop(keys, end, count)
+            };
+
+            match result {
+                Ok(Some((key, elements))) => RespValue::Array(vec![
+                    RespValue::BulkString(Some(key)),
+                    RespValue::Array(
+                        elements
+                            .into_iter()
+                            .map(|(member, score)| {
+                                RespValue::Array(vec![
+                                    RespValue::BulkString(Some(member)),
+                                    RespValue::BulkString(Some(format_score(score))),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                ]),
+                Ok(None) => RespValue::NullArray,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GEOADD" => {
+            if command.len() < 5 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'geoadd' command".to_string(),
+                );
+            }
+
+            let mut options = ZAddOptions::default();
+            let (mut nx, mut xx) = (false, false);
+            let mut rest = &command[2..];
+            while let Some(option) = rest.first() {
+                match option.to_uppercase().as_str() {
+                    "NX" => nx = true,
+                    "XX" => xx = true,
+                    "CH" => options.changed = true,
+                    _ => break,
+                }
+                rest = &rest[1..];
+            }
+            if rest.is_empty() || !rest.len().is_multiple_of(3) || (nx && xx) {
+                return RespValue::Error("syntax error".to_string());
+            }
+            options.condition = match (nx, xx) {
+                (true, _) => Some(SetCondition::IfMissing),
+                (_, true) => Some(SetCondition::IfExists),
+                _ => None,
+            };
+
+            let mut positions = Vec::with_capacity(rest.len() / 3);
+            for triple in rest.chunks(3) {
+                let (longitude, latitude) = match parse_geo_position(&triple[0], &triple[1]) {
+                    Ok(position) => position,
+                    Err(err) => return err,
+                };
+                positions.push((longitude, latitude, triple[2].clone()));
+            }
+
+            match memory.geoadd(&command[1], positions, options) {
+                Ok(count) => RespValue::Integer(count as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GEOPOS" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'geopos' command".to_string(),
+                );
+            }
+
+            match memory.geopos(&command[1], &command[2..]) {
+                Ok(positions) => RespValue::Array(
+                    positions
+                        .into_iter()
+                        .map(|position| match position {
+                            Some((longitude, latitude)) => RespValue::Array(vec![
+                                RespValue::BulkString(Some(format_coordinate(longitude))),
+                                RespValue::BulkString(Some(format_coordinate(latitude))),
+                            ]),
+                            None => RespValue::NullArray,
+                        })
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GEODIST" => {
+            if !(4..=5).contains(&command.len()) {
+                return RespValue::Error(
+                    "wrong number of arguments for 'geodist' command".to_string(),
+                );
+            }
+
+            let unit = match command
+                .get(4)
+                .map_or(Ok(GeoUnit::Meters), |unit| parse_geo_unit(unit))
+            {
+                Ok(unit) => unit,
+                Err(err) => return err,
+            };
+            match memory.geodist(&command[1], &command[2], &command[3]) {
+                Ok(distance) => RespValue::BulkString(
+                    distance.map(|distance| format_distance(distance / unit.meters())),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GEOHASH" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'geohash' command".to_string(),
+                );
+            }
+
+            match memory.geohash(&command[1], &command[2..]) {
+                Ok(hashes) => {
+                    RespValue::Array(hashes.into_iter().map(RespValue::BulkString).collect())
+                }
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GEOSEARCH" => {
+            if command.len() < 7 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'geosearch' command".to_string(),
+                );
+            }
+
+            let args = match parse_geosearch_args(&command[2..], "geosearch") {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            let with_any = args.with_distance || args.with_hash || args.with_coordinates;
+            match memory.geosearch(&command[1], &args.search) {
+                Ok(matches) => RespValue::Array(
+                    matches
+                        .into_iter()
+                        .map(|found| {
+                            if !with_any {
+                                return RespValue::BulkString(Some(found.member));
+                            }
+                            let mut fields = vec![RespValue::BulkString(Some(found.member))];
+                            if args.with_distance {
+                                fields.push(RespValue::BulkString(Some(format_distance(
+                                    found.distance,
+                                ))));
+                            }
+                            if args.with_hash {
+                                fields.push(RespValue::Integer(found.score as i64));
+                            }
+                            if args.with_coordinates {
+                                fields.push(RespValue::Array(vec![
+                                    RespValue::BulkString(Some(format_coordinate(found.longitude))),
+                                    RespValue::BulkString(Some(format_coordinate(found.latitude))),
+                                ]));
+                            }
+                            RespValue::Array(fields)
+                        })
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "GEOSEARCHSTORE" => {
+            if command.len() < 8 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'geosearchstore' command".to_string(),
+                );
+            }
+
+            let args = match parse_geosearch_args(&command[3..], "geosearchstore") {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            match memory.geosearchstore(&command[1], &command[2], &args.search, args.store_distance)
+            {
+                Ok(count) => RespValue::Integer(count as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "SORT" | "SORT_RO" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 2 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            let mut options = SortOptions::default();
+            let mut destination = None;
+            let mut args = command[2..].iter();
+            while let Some(option) = args.next() {
+                match (option.to_uppercase().as_str(), args.len()) {
+                    ("ASC", _) => options.descending = false,
+                    ("DESC", _) => options.descending = true,
+                    ("ALPHA", _) => options.alpha = true,
+                    ("LIMIT", 2..) => {
+                        let mut value = || args.next().and_then(|value| value.parse::<i64>().ok());
+                        let (Some(offset), Some(count)) = (value(), value()) else {
+                            return RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            );
+                        };
+                        options.limit = Some((offset, count));
+                    }
+                    ("STORE", 1..) if name == "sort" => destination = args.next(),
+                    ("BY", 1..) => options.by = args.next().cloned(),
+                    ("GET", 1..) => options.get.extend(args.next().cloned()),
+                    _ => return RespValue::Error("syntax error".to_string()),
+                }
+            }
+
+            match destination {
+                Some(destination) => match memory.sort_store(&command[1], &options, destination) {
+                    Ok(len) => RespValue::Integer(len as i64),
+                    Err(err) => RespValue::Error(err.to_string()),
+                },
+                None => match memory.sort(&command[1], &options) {
+                    Ok(values) => {
+                        RespValue::Array(values.into_iter().map(RespValue::BulkString).collect())
+                    }
+                    Err(err) => RespValue::Error(err.to_string()),
+                },
+            }
+        }
+        "TYPE" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'type' command".to_string(),
+                );
+            }
+
+            RespValue::SimpleString(memory.type_of(&command[1]).into())
+        }
+        "OBJECT" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'object' command".to_string(),
+                );
+            }
+
+            match command[1].to_uppercase().as_str() {
+                "ENCODING" => {
+                    if command.len() != 3 {
+                        return RespValue::Error(
+                            "wrong number of arguments for 'object|encoding' command".to_string(),
+                        );
+                    }
+
+                    match memory.object_encoding(&command[2]) {
+                        Some(encoding) => RespValue::BulkString(Some(encoding.to_string())),
+                        None => RespValue::Null,
+                    }
+                }
+                _ => RespValue::Error(format!(
+                    "unknown subcommand '{}'. Try OBJECT HELP.",
+                    command[1]
+                )),
+            }
+        }
+        "XADD" => {
+            if command.len() < 5 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xadd' command".to_string(),
+                );
+            }
+
+            // Options run up to the ID, the first argument that isn't one.
+            let mut no_mkstream = false;
+            let mut trim = StreamTrimArgs::default();
+            let mut i = 2;
+            while i < command.len() {
+                if command[i].eq_ignore_ascii_case("NOMKSTREAM") {
+                    no_mkstream = true;
+                    i += 1;
+                    continue;
+                }
+                match trim.parse_option(&command[i..]) {
+                    Ok(0) => break,
+                    Ok(taken) => i += taken,
+                    Err(err) => return err,
+                }
+            }
+            let trim = match trim.finish() {
+                Ok(trim) => trim,
+                Err(err) => return err,
+            };
+
+            let fields = command.get(i + 1..).unwrap_or_default();
+            if fields.is_empty() || !fields.len().is_multiple_of(2) {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xadd' command".to_string(),
+                );
+            }
+            let fields = fields
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+
+            match memory.xadd(
+                command[1].clone(),
+                command[i].clone(),
+                fields,
+                no_mkstream,
+                trim.as_ref(),
+            ) {
+                Ok(Some(id)) => RespValue::BulkString(Some(id.into())),
+                Ok(None) => RespValue::Null,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XLEN" => {
+            if command.len() != 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xlen' command".to_string(),
+                );
+            }
+
+            match memory.xlen(&command[1]) {
+                Ok(len) => RespValue::Integer(len as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XDEL" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xdel' command".to_string(),
+                );
+            }
+
+            let mut ids = Vec::with_capacity(command.len() - 2);
+            for id in &command[2..] {
+                match StreamEntryID::parse(id, 0) {
+                    Ok(id) => ids.push(id),
+                    Err(err) => return RespValue::Error(err.to_string()),
+                }
+            }
+
+            match memory.xdel(&command[1], &ids) {
+                Ok(deleted) => RespValue::Integer(deleted as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XTRIM" => {
+            if command.len() < 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xtrim' command".to_string(),
+                );
+            }
+
+            let mut trim = StreamTrimArgs::default();
+            let mut i = 2;
+            while i < command.len() {
+                match trim.parse_option(&command[i..]) {
+                    Ok(0) => return RespValue::Error("syntax error".to_string()),
+                    Ok(taken) => i += taken,
+                    Err(err) => return err,
+                }
+            }
+            let trim = match trim.finish() {
+                Ok(Some(trim)) => trim,
+                Ok(None) => {
+                    return RespValue::Error(
+                        "syntax error, XTRIM must be called with a trimming strategy".to_string(),
+                    );
+                }
+                Err(err) => return err,
+            };
+
+            match memory.xtrim(&command[1], &trim) {
+                Ok(trimmed) => RespValue::Integer(trimmed as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XRANGE" | "XREVRANGE" => {
+            let name = command[0].to_lowercase();
+            if command.len() < 4 {
+                return RespValue::Error(format!("wrong number of arguments for '{name}' command"));
+            }
+
+            // XREVRANGE takes the end of the range first.
+            let reverse = name == "xrevrange";
+            let (start, end) = if reverse {
+                (&command[3], &command[2])
+            } else {
+                (&command[2], &command[3])
+            };
+            let (start, end) = match parse_stream_range(start, end) {
+                Ok(range) => range,
+                Err(err) => return err,
+            };
+
+            let mut count = None;
+            let mut options = command[4..].iter();
+            while let Some(option) = options.next() {
+                match (option.to_uppercase().as_str(), options.len()) {
+                    ("COUNT", 1..) => {
+                        let value = options.next().expect("length checked");
+                        let Ok(value) = value.parse::<i64>() else {
+                            return RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            );
+                        };
+                        count = Some(value.max(0) as usize);
+                    }
+                    _ => return RespValue::Error("syntax error".to_string()),
+                }
+            }
+            if count == Some(0) {
+                return RespValue::NullArray;
+            }
+
+            match memory.xrange(&command[1], &start, &end, reverse, count) {
+                Ok(stream_value) => stream_entries_array(stream_value),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XREAD" => {
+            if command.len() < 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xread' command".to_string(),
+                );
+            }
+
+            let (mut block, mut count) = (None, None);
+            let mut streams = None;
+            let mut args = command[1..].iter().enumerate();
+            while let Some((i, option)) = args.next() {
+                match (option.to_uppercase().as_str(), args.len()) {
+                    ("BLOCK", 1..) => {
+                        let (_, timeout) = args.next().expect("length checked");
+                        block = match parse_block_timeout(timeout) {
+                            Ok(timeout) => Some(timeout),
+                            Err(err) => return err,
+                        };
+                    }
+                    ("COUNT", 1..) => {
+                        let (_, value) = args.next().expect("length checked");
+                        let Ok(value) = value.parse::<i64>() else {
+                            return RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            );
+                        };
+                        // A count of 0 or less means no limit.
+                        count = (value > 0).then_some(value as usize);
+                    }
+                    ("STREAMS", 1..) => {
+                        streams = Some(&command[i + 2..]);
+                        break;
+                    }
+                    _ => return RespValue::Error("syntax error".to_string()),
+                }
+            }
+            let Some(streams) = streams else {
+                return RespValue::Error("syntax error".to_string());
+            };
+            if !streams.len().is_multiple_of(2) {
+                return RespValue::Error(
+                    "Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified."
+                        .to_string(),
+                );
+            }
+
+            let (keys, ids) = streams.split_at(streams.len() / 2);
+            let mut key_from = Vec::with_capacity(keys.len());
+            for (key, id) in keys.iter().zip(ids) {
+                let from = match id.as_str() {
+                    "$" => ReadFrom::New,
+                    "+" => ReadFrom::Last,
+                    ">" => {
+                        return RespValue::Error(
+                            "The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option."
+                                .to_string(),
+                        );
+                    }
+                    id => match StreamEntryID::parse(id, 0) {
+                        Ok(id) => ReadFrom::After(id),
+                        Err(err) => return RespValue::Error(err.to_string()),
+                    },
+                };
+                key_from.push((key.clone(), from));
+            }
+
+            let result = match block {
+                Some(timeout) => memory.bxread(client.id, key_from, count, timeout).await,
+                None => memory
+                    .xread(&key_from, count)
+                    .map(|streams| (!streams.is_empty()).then_some(streams)),
+            };
+            match result {
+                Ok(Some(streams)) => RespValue::Array(
+                    streams
+                        .into_iter()
+                        .map(|(key, entries)| {
+                            RespValue::Array(vec![
+                                RespValue::BulkString(Some(key)),
+                                stream_entries_array(entries),
+                            ])
+                        })
+                        .collect(),
+                ),
+                Ok(None) => RespValue::NullArray,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XGROUP" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xgroup' command".to_string(),
+                );
+            }
+
+            let subcommand = command[1].to_lowercase();
+            let arity_ok = match subcommand.as_str() {
+                "create" => (5..=8).contains(&command.len()),
+                "setid" => command.len() == 5 || command.len() == 7,
+                "createconsumer" | "delconsumer" => command.len() == 5,
+                "destroy" => command.len() == 4,
+                _ => {
+                    return RespValue::Error(format!(
+                        "unknown subcommand '{}'. Try XGROUP HELP.",
+                        command[1]
+                    ));
+                }
+            };
+            if !arity_ok {
+                return RespValue::Error(format!(
+                    "wrong number of arguments for 'xgroup|{subcommand}' command"
+                ));
+            }
+
+            let (key, group) = (&command[2], &command[3]);
+            // `$` stands for the last ID of the stream.
+            let parse_start = |id: &str| match id {
+                "$" => Ok(None),
+                id => StreamEntryID::parse(id, 0)
+                    .map(Some)
+                    .map_err(|err| RespValue::Error(err.to_string())),
+            };
+            let result = match subcommand.as_str() {
+                "create" | "setid" => {
+                    let start = match parse_start(&command[4]) {
+                        Ok(start) => start,
+                        Err(err) => return err,
+                    };
+                    let (mut make_stream, mut entries_read) = (false, None);
+                    let mut options = command[5..].iter();
+                    while let Some(option) = options.next() {
+                        match (option.to_uppercase().as_str(), options.len()) {
+                            ("MKSTREAM", _) if subcommand == "create" => make_stream = true,
+                            ("ENTRIESREAD", 1..) => {
+                                let value = options.next().expect("length checked");
+                                entries_read = match value.parse::<i64>() {
+                                    Ok(-1) => None,
+                                    Ok(read) if read >= 0 => Some(read as u64),
+                                    Ok(_) => {
+                                        return RespValue::Error(
+                                            "value for ENTRIESREAD must be positive or -1"
+                                                .to_string(),
+                                        );
+                                    }
+                                    Err(_) => {
+                                        return RespValue::Error(
+                                            "value is not an integer or out of range".to_string(),
+                                        );
+                                    }
+                                };
+                            }
+                            _ => return RespValue::Error("syntax error".to_string()),
+                        }
+                    }
+
+                    let result = if subcommand == "create" {
+                        memory.xgroup_create(key, group, start, entries_read, make_stream)
+                    } else {
+                        memory.xgroup_setid(key, group, start, entries_read)
+                    };
+                    result.map(|()| RespValue::SimpleString("OK".to_string()))
+                }
+                "destroy" => memory
+                    .xgroup_destroy(key, group)
+                    .map(|destroyed| RespValue::Integer(destroyed as i64)),
+                "createconsumer" => memory
+                    .xgroup_createconsumer(key, group, &command[4])
+                    .map(|created| RespValue::Integer(created as i64)),
+                _ => memory
+                    .xgroup_delconsumer(key, group, &command[4])
+                    .map(|pending| RespValue::Integer(pending as i64)),
+            };
+            match result {
+                Ok(reply) => reply,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XREADGROUP" => {
+            if command.len() < 7 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xreadgroup' command".to_string(),
+                );
+            }
+
+            let (mut block, mut group) = (None, None);
+            let mut options = GroupReadOptions::default();
+            let mut streams = None;
+            let mut args = command[1..].iter().enumerate();
+            while let Some((i, option)) = args.next() {
+                match (option.to_uppercase().as_str(), args.len()) {
+                    ("GROUP", 2..) => {
+                        let (_, name) = args.next().expect("length checked");
+                        let (_, consumer) = args.next().expect("length checked");
+                        group = Some((name.clone(), consumer.clone()));
+                    }
+                    ("BLOCK", 1..) => {
+                        let (_, timeout) = args.next().expect("length checked");
+                        block = match parse_block_timeout(timeout) {
+                            Ok(timeout) => Some(timeout),
+                            Err(err) => return err,
+                        };
+                    }
+                    ("COUNT", 1..) => {
+                        let (_, value) = args.next().expect("length checked");
+                        let Ok(value) = value.parse::<i64>() else {
+                            return RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            );
+                        };
+                        // A count of 0 or less means no limit.
+                        options.count = (value > 0).then_some(value as usize);
+                    }
+                    ("NOACK", _) => options.no_ack = true,
+                    ("STREAMS", 1..) => {
+                        streams = Some(&command[i + 2..]);
+                        break;
+                    }
+                    _ => return RespValue::Error("syntax error".to_string()),
+                }
+            }
+            let Some(streams) = streams else {
+                return RespValue::Error("syntax error".to_string());
+            };
+            let Some((group, consumer)) = group else {
+                return RespValue::Error("Missing GROUP option for XREADGROUP".to_string());
+            };
+            if !streams.len().is_multiple_of(2) {
+                return RespValue::Error(
+                    "Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified."
+                        .to_string(),
+                );
+            }
+
+            let (keys, ids) = streams.split_at(streams.len() / 2);
+            let mut key_from = Vec::with_capacity(keys.len());
+            for (key, id) in keys.iter().zip(ids) {
+                let from = match id.as_str() {
+                    ">" => GroupReadFrom::Undelivered,
+                    "$" => {
+                        return RespValue::Error(
+                            "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set."
+                                .to_string(),
+                        );
+                    }
+                    id => match StreamEntryID::parse(id, 0) {
+                        Ok(id) => GroupReadFrom::Pending(id),
+                        Err(err) => return RespValue::Error(err.to_string()),
+                    },
+                };
+                key_from.push((key.clone(), from));
+            }
+
+            let result = match block {
+                Some(timeout) => {
+                    memory
+                        .bxreadgroup(client.id, group, consumer, key_from, options, timeout)
+                        .await
+                }
+                None => memory.xreadgroup(&group, &consumer, &key_from, options),
+            };
+            match result {
+                Ok(Some(streams)) => RespValue::Array(
+                    streams
+                        .into_iter()
+                        .map(|(key, entries)| {
+                            RespValue::Array(vec![
+                                RespValue::BulkString(Some(key)),
+                                RespValue::Array(
+                                    entries
+                                        .into_iter()
+                                        .map(|(id, fields)| stream_entry(id, fields))
+                                        .collect(),
+                                ),
+                            ])
+                        })
+                        .collect(),
+                ),
+                Ok(None) => RespValue::NullArray,
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XACK" => {
+            if command.len() < 4 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xack' command".to_string(),
+                );
+            }
+
+            let mut ids = Vec::with_capacity(command.len() - 3);
+            for id in &command[3..] {
+                match StreamEntryID::parse(id, 0) {
+                    Ok(id) => ids.push(id),
+                    Err(err) => return RespValue::Error(err.to_string()),
+                }
+            }
+
+            match memory.xack(&command[1], &command[2], &ids) {
+                Ok(acked) => RespValue::Integer(acked as i64),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XPENDING" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xpending' command".to_string(),
+                );
+            }
+            let (key, group) = (&command[1], &command[2]);
+
+            if command.len() == 3 {
+                let summary = match memory.xpending_summary(key, group) {
+                    Ok(summary) => summary,
+                    Err(err) => return RespValue::Error(err.to_string()),
+                };
+                let (first, last) = match summary.range {
+                    Some((first, last)) => (
+                        RespValue::BulkString(Some(first.into())),
+                        RespValue::BulkString(Some(last.into())),
+                    ),
+                    None => (RespValue::Null, RespValue::Null),
+                };
+                let consumers = if summary.consumers.is_empty() {
+                    RespValue::NullArray
+                } else {
+                    RespValue::Array(
+                        summary
+                            .consumers
+                            .into_iter()
+                            .map(|(consumer, count)| {
+                                RespValue::Array(vec![
+                                    RespValue::BulkString(Some(consumer)),
+                                    RespValue::BulkString(Some(count.to_string())),
+                                ])
+                            })
+                            .collect(),
+                    )
+                };
+                return RespValue::Array(vec![
+                    RespValue::Integer(summary.count as i64),
+                    first,
+                    last,
+                    consumers,
+                ]);
+            }
+
+            let mut args = &command[3..];
+            let mut min_idle = None;
+            if args[0].eq_ignore_ascii_case("IDLE") && args.len() >= 2 {
+                let Ok(idle) = args[1].parse::<i64>() else {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                };
+                min_idle = Some(idle.max(0) as u64);
+                args = &args[2..];
+            }
+            if !(3..=4).contains(&args.len()) {
+                return RespValue::Error("syntax error".to_string());
+            }
+            let (start, end) = match parse_stream_range(&args[0], &args[1]) {
+                Ok(range) => range,
+                Err(err) => return err,
+            };
+            let Ok(count) = args[2].parse::<i64>() else {
+                return RespValue::Error("value is not an integer or out of range".to_string());
+            };
+
+            let filter = PendingFilter {
+                min_idle,
+                start,
+                end,
+                count: count.max(0) as usize,
+                consumer: args.get(3).cloned(),
+            };
+            match memory.xpending(key, group, &filter) {
+                Ok(pending) => RespValue::Array(
+                    pending
+                        .into_iter()
+                        .map(|info| {
+                            RespValue::Array(vec![
+                                RespValue::BulkString(Some(info.id.into())),
+                                RespValue::BulkString(Some(info.consumer)),
+                                RespValue::Integer(info.idle as i64),
+                                RespValue::Integer(info.delivery_count as i64),
+                            ])
+                        })
+                        .collect(),
+                ),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XCLAIM" => {
+            if command.len() < 6 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xclaim' command".to_string(),
+                );
+            }
+
+            let Ok(min_idle) = command[4].parse::<i64>() else {
+                return RespValue::Error("Invalid min-idle-time argument for XCLAIM".to_string());
+            };
+
+            // The IDs run up to the first argument that isn't one.
+            let mut ids = Vec::new();
+            let mut args = command[5..].iter().peekable();
+            while let Some(id) = args.peek() {
+                match StreamEntryID::parse(id, 0) {
+                    Ok(id) => ids.push(id),
+                    Err(err) if ids.is_empty() => return RespValue::Error(err.to_string()),
+                    Err(_) => break,
+                }
+                args.next();
+            }
+
+            let now = memory.clock().now_ms();
+            let mut options = ClaimOptions::default();
+            let parse_millis = |value: &str| match value.parse::<i64>() {
+                Ok(value) => Ok(value.max(0) as u64),
+                Err(_) => Err(RespValue::Error(
+                    "value is not an integer or out of range".to_string(),
+                )),
+            };
+            while let Some(option) = args.next() {
+                match (option.to_uppercase().as_str(), args.len()) {
+                    ("IDLE", 1..) => match parse_millis(args.next().expect("length checked")) {
+                        Ok(idle) => options.delivery_time = Some(now.saturating_sub(idle)),
+                        Err(err) => return err,
+                    },
+                    ("TIME", 1..) => match parse_millis(args.next().expect("length checked")) {
+                        Ok(time) => options.delivery_time = Some(time),
+                        Err(err) => return err,
+                    },
+                    ("RETRYCOUNT", 1..) => {
+                        match parse_millis(args.next().expect("length checked")) {
+                            Ok(count) => options.retry_count = Some(count),
+                            Err(err) => return err,
+                        }
+                    }
+                    ("FORCE", _) => options.force = true,
+                    ("JUSTID", _) => options.just_id = true,
+                    ("LASTID", 1..) => {
+                        let id = args.next().expect("length checked");
+                        match StreamEntryID::parse(id, 0) {
+                            Ok(id) => options.last_id = Some(id),
+                            Err(err) => return RespValue::Error(err.to_string()),
+                        }
+                    }
+                    _ => {
+                        return RespValue::Error(format!("Unrecognized XCLAIM option '{option}'"));
+                    }
+                }
+            }
+
+            let min_idle = min_idle.max(0) as u64;
+            match memory.xclaim(
+                &command[1],
+                &command[2],
+                &command[3],
+                min_idle,
+                &ids,
+                &options,
+            ) {
+                Ok(claimed) if options.just_id => {
+                    stream_ids_array(claimed.0.into_iter().map(|(id, _)| id))
+                }
+                Ok(claimed) => stream_entries_array(claimed),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XAUTOCLAIM" => {
+            if command.len() < 6 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xautoclaim' command".to_string(),
+                );
+            }
+
+            let Ok(min_idle) = command[4].parse::<i64>() else {
+                return RespValue::Error(
+                    "Invalid min-idle-time argument for XAUTOCLAIM".to_string(),
+                );
+            };
+            let start = match parse_stream_range(&command[5], "+") {
+                Ok((start, _)) => start,
+                Err(err) => return err,
+            };
+
+            let (mut count, mut just_id) = (100, false);
+            let mut options = command[6..].iter();
+            while let Some(option) = options.next() {
+                match (option.to_uppercase().as_str(), options.len()) {
+                    ("COUNT", 1..) => {
+                        let value = options.next().expect("length checked");
+                        let Ok(value) = value.parse::<i64>() else {
+                            return RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            );
+                        };
+                        if value < 1 {
+                            return RespValue::Error("COUNT must be > 0".to_string());
+                        }
+                        count = value as usize;
+                    }
+                    ("JUSTID", _) => just_id = true,
+                    _ => return RespValue::Error("syntax error".to_string()),
+                }
+            }
+
+            let min_idle = min_idle.max(0) as u64;
+            match memory.xautoclaim(
+                &command[1],
+                &command[2],
+                &command[3],
+                min_idle,
+                (&start, count),
+                just_id,
+            ) {
+                Ok((next, claimed, deleted)) => RespValue::Array(vec![
+                    RespValue::BulkString(Some(next.into())),
+                    if just_id {
+                        stream_ids_array(claimed.0.into_iter().map(|(id, _)| id))
+                    } else {
+                        stream_entries_array(claimed)
+                    },
+                    stream_ids_array(deleted),
+                ]),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XSETID" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xsetid' command".to_string(),
+                );
+            }
+
+            let last_id = match StreamEntryID::parse(&command[2], 0) {
+                Ok(id) => id,
+                Err(err) => return RespValue::Error(err.to_string()),
+            };
+            let (mut entries_added, mut max_deleted_id) = (None, None);
+            let mut options = command[3..].iter();
+            while let Some(option) = options.next() {
+                match (option.to_uppercase().as_str(), options.len()) {
+                    ("ENTRIESADDED", 1..) => {
+                        let value = options.next().expect("length checked");
+                        let Ok(value) = value.parse::<i64>() else {
+                            return RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            );
+                        };
+                        if value < 0 {
+                            return RespValue::Error("entries_added must be positive".to_string());
+                        }
+                        entries_added = Some(value as u64);
+                    }
+                    ("MAXDELETEDID", 1..) => {
+                        let value = options.next().expect("length checked");
+                        let id = match StreamEntryID::parse(value, 0) {
+                            Ok(id) => id,
+                            Err(err) => return RespValue::Error(err.to_string()),
+                        };
+                        if last_id < id {
+                            return RespValue::Error(
+                                "The ID specified in XSETID is smaller than the provided max_deleted_entry_id"
+                                    .to_string(),
+                            );
+                        }
+                        max_deleted_id = Some(id);
+                    }
+                    _ => return RespValue::Error("syntax error".to_string()),
+                }
+            }
+
+            match memory.xsetid(&command[1], last_id, entries_added, max_deleted_id) {
+                Ok(()) => RespValue::SimpleString("OK".to_string()),
+                Err(err) => RespValue::Error(err.to_string()),
+            }
+        }
+        "XINFO" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'xinfo' command".to_string(),
+                );
+            }
+
+            let subcommand = command[1].to_lowercase();
+            let arity_ok = match subcommand.as_str() {
+                "stream" => command.len() >= 3,
+                "groups" => command.len() == 3,
+                "consumers" => command.len() == 4,
+                _ => {
+                    return RespValue::Error(format!(
+                        "unknown subcommand '{}'. Try XINFO HELP.",
+                        command[1]
+                    ));
+                }
+            };
+            if !arity_ok {
+                return RespValue::Error(format!(
+                    "wrong number of arguments for 'xinfo|{subcommand}' command"
+                ));
+            }
+
+            let key = &command[2];
+            match subcommand.as_str() {
+                "stream" => {
+                    let full = match command[3..] {
+                        [] => None,
+                        [ref full] if full.eq_ignore_ascii_case("FULL") => Some(10),
+                        [ref full, ref option, ref count]
+                            if full.eq_ignore_ascii_case("FULL")
+                                && option.eq_ignore_ascii_case("COUNT") =>
+                        {
+                            let Ok(count) = count.parse::<i64>() else {
+                                return RespValue::Error(
+                                    "value is not an integer or out of range".to_string(),
+                                );
+                            };
+                            Some(count.max(0) as usize)
+                        }
+                        _ => return RespValue::Error("syntax error".to_string()),
+                    };
+
+                    let Some(count) = full else {
+                        return match memory.xinfo_stream(key) {
+                            Ok(mut info) => {
+                                let entry = |entry: Option<(StreamEntryID, _)>| match entry {
+                                    Some((id, fields)) => stream_entry(id, Some(fields)),
+                                    None => RespValue::Null,
+                                };
+                                let rest = [
+                                    ("groups", RespValue::Integer(info.groups as i64)),
+                                    ("first-entry", entry(info.first_entry.take())),
+                                    ("last-entry", entry(info.last_entry.take())),
+                                ];
+                                let mut pairs = stream_info_pairs(info);
+                                pairs.extend(rest);
+                                info_array(pairs)
+                            }
+                            Err(err) => RespValue::Error(err.to_string()),
+                        };
+                    };
+
+                    let full = match memory.xinfo_stream_full(key, count) {
+                        Ok(full) => full,
+                        Err(err) => return RespValue::Error(err.to_string()),
+                    };
+                    let groups = full
+                        .groups
+                        .into_iter()
+                        .map(|group| {
+                            let pending = group
+                                .pending
+                                .into_iter()
+                                .map(|(id, pending)| {
+                                    RespValue::Array(vec![
+                                        RespValue::BulkString(Some(id.into())),
+                                        RespValue::BulkString(Some(pending.consumer)),
+                                        RespValue::Integer(pending.delivery_time as i64),
+                                        RespValue::Integer(pending.delivery_count as i64),
+                                    ])
+                                })
+                                .collect();
+                            let consumers = group
+                                .consumers
+                                .into_iter()
+                                .map(|consumer| {
+                                    let pending = consumer
+                                        .pending
+                                        .into_iter()
+                                        .map(|(id, pending)| {
+                                            RespValue::Array(vec![
+                                                RespValue::BulkString(Some(id.into())),
+                                                RespValue::Integer(pending.delivery_time as i64),
+                                                RespValue::Integer(pending.delivery_count as i64),
+                                            ])
+                                        })
+                                        .collect();
+                                    let active_time =
+                                        consumer.active_time.map_or(-1, |time| time as i64);
+                                    info_array(vec![
+                                        ("name", RespValue::BulkString(Some(consumer.name))),
+                                        (
+                                            "seen-time",
+                                            RespValue::Integer(consumer.seen_time as i64),
+                                        ),
+                                        ("active-time", RespValue::Integer(active_time)),
+                                        (
+                                            "pel-count",
+                                            RespValue::Integer(consumer.pending_count as i64),
+                                        ),
+                                        ("pending", RespValue::Array(pending)),
+                                    ])
+                                })
+                                .collect();
+                            info_array(vec![
+                                ("name", RespValue::BulkString(Some(group.info.name))),
+                                (
+                                    "last-delivered-id",
+                                    RespValue::BulkString(Some(group.info.last_delivered.into())),
+                                ),
+                                ("entries-read", optional_integer(group.info.entries_read)),
+                                ("lag", optional_integer(group.info.lag)),
+                                ("pel-count", RespValue::Integer(group.info.pending as i64)),
+                                ("pending", RespValue::Array(pending)),
+                                ("consumers", RespValue::Array(consumers)),
+                            ])
+                        })
+                        .collect();
+                    let mut pairs = stream_info_pairs(full.info);
+                    pairs.extend([
+                        ("entries", stream_entries_array(full.entries)),
+                        ("groups", RespValue::Array(groups)),
+                    ]);
+                    info_array(pairs)
+                }
+                "groups" => match memory.xinfo_groups(key) {
+                    Ok(groups) => RespValue::Array(
+                        groups
+                            .into_iter()
+                            .map(|group| {
+                                info_array(vec![
+                                    ("name", RespValue::BulkString(Some(group.name))),
+                                    ("consumers", RespValue::Integer(group.consumers as i64)),
+                                    ("pending", RespValue::Integer(group.pending as i64)),
+                                    (
+                                        "last-delivered-id",
+                                        RespValue::BulkString(Some(group.last_delivered.into())),
+                                    ),
+                                    ("entries-read", optional_integer(group.entries_read)),
+                                    ("lag", optional_integer(group.lag)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                    Err(err) => RespValue::Error(err.to_string()),
+                },
+                _ => match memory.xinfo_consumers(key, &command[3]) {
+                    Ok(consumers) => RespValue::Array(
+                        consumers
+                            .into_iter()
+                            .map(|consumer| {
+                                let inactive = consumer.inactive.map_or(-1, |idle| idle as i64);
+                                info_array(vec![
+                                    ("name", RespValue::BulkString(Some(consumer.name))),
+                                    ("pending", RespValue::Integer(consumer.pending as i64)),
+                                    ("idle", RespValue::Integer(consumer.idle as i64)),
+                                    ("inactive", RespValue::Integer(inactive)),
+                                ])
+                            })
+                            .collect(),
+                    ),
+                    Err(err) => RespValue::Error(err.to_string()),
+                },
+            }
+        }
+        "CONFIG" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'config' command".to_string(),
+                );
+            }
+
+            match command[1].to_uppercase().as_str() {
+                "GET" => {
+                    if command.len() < 3 {
+                        return RespValue::Error(
+                            "wrong number of arguments for 'config|get' command".to_string(),
+                        );
+                    }
+
+                    let mut result = Vec::new();
+                    for pattern in &command[2..] {
+                        for (name, value) in memory.config().get(pattern) {
+                            result.push(RespValue::BulkString(Some(name)));
+                            result.push(RespValue::BulkString(Some(value)));
+                        }
+                    }
+                    RespValue::Array(result)
+                }
+                "SET" => {
+                    if command.len() < 4 || !command.len().is_multiple_of(2) {
+                        return RespValue::Error(
+                            "wrong number of arguments for 'config|set' command".to_string(),
+                        );
+                    }
+
+                    for pair in command[2..].chunks(2) {
+                        if let Err(err) = memory.config().set(&pair[0], &pair[1]) {
+                            return RespValue::Error(err.to_string());
+                        }
+                    }
+                    RespValue::SimpleString("OK".to_string())
+                }
+                _ => RespValue::Error(format!(
+                    "unknown subcommand '{}'. Try CONFIG HELP.",
+                    command[1]
+                )),
+            }
+        }
+        "CLIENT" => {
+            if command.len() < 2 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'client' command".to_string(),
+                );
+            }
+
+            match command[1].to_uppercase().as_str() {
+                "ID" => {
+                    if command.len() != 2 {
+                        return RespValue::Error(
+                            "wrong number of arguments for 'client|id' command".to_string(),
+                        );
+                    }
+
+                    RespValue::Integer(client.id as i64)
+                }
+                "UNBLOCK" => {
+                    if !(3..=4).contains(&command.len()) {
+                        return RespValue::Error(
+                            "wrong number of arguments for 'client|unblock' command".to_string(),
+                        );
+                    }
+
+                    let Ok(id) = command[2].parse::<u64>() else {
+                        return RespValue::Error(
+                            "value is not an integer or out of range".to_string(),
+                        );
+                    };
+                    let mode = match command.get(3).map(|mode| mode.to_uppercase()).as_deref() {
+                        None | Some("TIMEOUT") => UnblockMode::Timeout,
+                        Some("ERROR") => UnblockMode::Error,
+                        Some(_) => {
+                            return RespValue::Error(
+                                "CLIENT UNBLOCK reason should be TIMEOUT or ERROR".to_string(),
+                            );
+                        }
+                    };
+
+                    RespValue::Integer(memory.unblock_client(id, mode) as i64)
+                }
+                _ => RespValue::Error(format!(
+                    "unknown subcommand '{}'. Try CLIENT HELP.",
+                    command[1]
+                )),
+            }
+        }
+        "PUBLISH" => {
+            if command.len() < 3 {
+                return RespValue::Error(
+                    "wrong number of arguments for 'publish' command".to_string(),
+                );
+            }
+
+            RespValue::Integer(memory.pubsub().publish(&command[1], &command[2]) as i64)
+        }
+        "SUBSCRIBE" | "PSUBSCRIBE" => {
+            if command.len() < 2 {
+                return RespValue::Error(format!(
+                    "wrong number of arguments for '{}' command",
+                    command[0].to_lowercase()
+                ));
+            }
+
+            let kind = command[0].to_lowercase();
+            let mut replies = Vec::new();
+            for target in &command[1..] {
+                if kind == "subscribe" {
+                    client.subscribe(memory.pubsub(), target.clone());
+                } else {
+                    client.psubscribe(memory.pubsub(), target.clone());
+                }
+                replies.push(RespValue::Array(vec![
+                    RespValue::BulkString(Some(kind.clone())),
+                    RespValue::BulkString(Some(target.clone())),
+                    RespValue::Integer(client.subscription_count() as i64),
+                ]));
+            }
+            RespValue::Multiple(replies)
+        }
+        "UNSUBSCRIBE" | "PUNSUBSCRIBE" => {
+            let kind = command[0].to_lowercase();
+            let targets = match (command.len(), kind.as_str()) {
+                (1, "unsubscribe") => client.channels(),
+                (1, _) => client.patterns(),
+                _ => command[1..].to_vec(),
+            };
+
+            if targets.is_empty() {
+                return RespValue::Array(vec![
+                    RespValue::BulkString(Some(kind)),
+                    RespValue::Null,
+                    RespValue::Integer(client.subscription_count() as i64),
+                ]);
+            }
+
+            let mut replies = Vec::new();
+            for target in targets {
+                if kind == "unsubscribe" {
+                    client.unsubscribe(memory.pubsub(), &target);
+                } else {
+                    client.punsubscribe(memory.pubsub(), &target);
+                }
+                replies.push(RespValue::Array(vec![
+                    RespValue::BulkString(Some(kind.clone())),
+                    RespValue::BulkString(Some(target)),
+                    RespValue::Integer(client.subscription_count() as i64),
+                ]));
+            }
+            RespValue::Multiple(replies)
+        }
+        _ => RespValue::Error("unknown command".to_string()),
+    }
+}
+
+/// Turns an `EX` / `PX` / `EXAT` / `PXAT` argument into an absolute expiration
+/// in unix milliseconds.
+fn parse_expiration(
+    memory: &Store,
+    unit: &str,
+    value: &str,
+    command: &str,
+) -> Result<u64, RespValue> {
+    let Ok(amount) = value.parse::<i64>() else {
+        return Err(RespValue::Error(
+            "value is not an integer or out of range".to_string(),
+        ));
+    };
+    let invalid = || RespValue::Error(format!("invalid expire time in '{command}' command"));
+    if amount <= 0 {
+        return Err(invalid());
+    }
+
+    let now = memory.clock().now_ms() as i64;
+    let at = match unit {
+        "EX" => amount.checked_mul(1000).and_then(|ms| ms.checked_add(now)),
+        "PX" => amount.checked_add(now),
+        "EXAT" => amount.checked_mul(1000),
+        _ => Some(amount),
+    };
+    at.map(|at| at as u64).ok_or_else(invalid)
+}
+
+/// Parses a bit offset such that a field of `width` bits starting there
+/// still fits in the largest allowed string.
+fn parse_bit_offset(value: &str, width: u32) -> Result<usize, RespValue> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|offset| {
+            offset
+                .checked_add(width as usize - 1)
+                .is_some_and(|end| end <= MAX_BIT_OFFSET)
+        })
+        .ok_or_else(|| RespValue::Error("bit offset is not an integer or out of range".to_string()))
+}
+
+fn parse_bit_unit(value: Option<&String>) -> Result<BitUnit, RespValue> {
+    match value.map(|unit| unit.to_uppercase()).as_deref() {
+        None | Some("BYTE") => Ok(BitUnit::Byte),
+        Some("BIT") => Ok(BitUnit::Bit),
+        Some(_) => Err(RespValue::Error("syntax error".to_string())),
+    }
+}
+
+/// Parses BITFIELD types such as `i8` or `u16`; unsigned fields stop at 63
+/// bits so every value fits in a RESP integer.
+fn parse_bitfield_type(value: &str) -> Result<BitfieldType, RespValue> {
+    let invalid = || {
+        RespValue::Error(
+            "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."
+                .to_string(),
+        )
+    };
+
+    let signed = match value.as_bytes().first() {
+        Some(b'i' | b'I') => true,
+        Some(b'u' | b'U') => false,
+        _ => return Err(invalid()),
+    };
+    let bits = value[1..].parse::<u32>().map_err(|_| invalid())?;
+    let max_bits = if signed { 64 } else { 63 };
+    if !(1..=max_bits).contains(&bits) {
+        return Err(invalid());
+    }
+    Ok(BitfieldType { signed, bits })
+}
+
+/// Parses a BITFIELD offset, where `#N` means the N-th field of this type.
+fn parse_bitfield_offset(value: &str, ty: BitfieldType) -> Result<usize, RespValue> {
+    let offset = match value.strip_prefix('#') {
+        Some(index) => index
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| index.checked_mul(ty.bits as usize))
+            .map(|offset| offset.to_string())
+            .unwrap_or_default(),
+        None => value.to_string(),
+    };
+    parse_bit_offset(&offset, ty.bits)
+}
+
+/// Parses a blocking timeout in seconds, where 0 means waiting forever.
+fn parse_timeout(value: &str) -> Result<Option<Duration>, RespValue> {
+    let Some(seconds) = value
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite())
+    else {
+        return Err(RespValue::Error(
+            "timeout is not a float or out of range".to_string(),
+        ));
+    };
+    if seconds < 0.0 {
+        return Err(RespValue::Error("timeout is negative".to_string()));
+    }
+
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(seconds)
+        .map(Some)
+        .map_err(|_| RespValue::Error("timeout is out of range".to_string()))
+}
+
+/// Parses the milliseconds of XREAD BLOCK, where 0 waits forever.
+fn parse_block_timeout(value: &str) -> Result<Option<Duration>, RespValue> {
+    let Ok(millis) = value.parse::<i64>() else {
+        return Err(RespValue::Error(
+            "timeout is not an integer or out of range".to_string(),
+        ));
+    };
+    if millis < 0 {
+        return Err(RespValue::Error("timeout is negative".to_string()));
+    }
+
+    Ok((millis > 0).then(|| Duration::from_millis(millis as u64)))
+}
+
+fn parse_list_end(value: &str) -> Result<ListEnd, RespValue> {
+    match value.to_uppercase().as_str() {
+        "LEFT" => Ok(ListEnd::Left),
+        "RIGHT" => Ok(ListEnd::Right),
+        _ => Err(RespValue::Error("syntax error".to_string())),
+    }
+}
+
+/// Splits `numkeys key [key ...] where [COUNT count]`, the tail shared by
+/// the *MPOP commands, into the keys, the `where` argument and the count.
+fn parse_mpop_args(args: &[String]) -> Result<(&[String], &str, usize), RespValue> {
+    let numkeys = args[0]
+        .parse::<usize>()
+        .ok()
+        .filter(|&numkeys| numkeys > 0)
+        .ok_or_else(|| RespValue::Error("numkeys should be greater than 0".to_string()))?;
+    if numkeys > args.len() - 2 {
+        return Err(RespValue::Error("syntax error".to_string()));
+    }
+
+    let (keys, rest) = args[1..].split_at(numkeys);
+    let count = match rest {
+        [_] => 1,
+        [_, option, count] if option.eq_ignore_ascii_case("COUNT") => count
+            .parse::<usize>()
+            .ok()
+            .filter(|&count| count > 0)
+            .ok_or_else(|| RespValue::Error("count should be greater than 0".to_string()))?,
+        _ => return Err(RespValue::Error("syntax error".to_string())),
+    };
+
+    Ok((keys, &rest[0], count))
+}
+
+/// Parses `numkeys key [key ...] [LIMIT limit]`, the arguments of
+/// SINTERCARD and ZINTERCARD, into the keys and the limit, 0 if none.
+fn parse_intercard_args(args: &[String]) -> Result<(&[String], usize), RespValue> {
+    let Some(numkeys) = args[0].parse::<usize>().ok().filter(|&numkeys| numkeys > 0) else {
+        return Err(RespValue::Error(
+            "numkeys should be greater than 0".to_string(),
+        ));
+    };
+    let Some(keys) = args[1..].get(..numkeys) else {
+        return Err(RespValue::Error(
+            "Number of keys can't be greater than number of args".to_string(),
+        ));
+    };
+    let limit = match &args[1 + numkeys..] {
+        [] => 0,
+        [option, limit] if option.eq_ignore_ascii_case("LIMIT") => limit
+            .parse::<usize>()
+            .map_err(|_| RespValue::Error("LIMIT can't be negative".to_string()))?,
+        _ => return Err(RespValue::Error("syntax error".to_string())),
+    };
+    Ok((keys, limit))
+}
+
+/// Arguments of ZUNION, ZINTER, ZDIFF and their STORE forms.
+struct ZSetOperationArgs<'a> {
+    keys: &'a [String],
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    with_scores: bool,
+}
+
+/// Parses `numkeys key [key ...] [WEIGHTS weight ...] [AGGREGATE SUM | MIN |
+/// MAX]` and, if `allow_scores` is set, `[WITHSCORES]`; `name` is the
+/// command, for errors. ZDIFF takes neither WEIGHTS nor AGGREGATE.
+fn parse_zset_operation_args<'a>(
+    args: &'a [String],
+    operation: SetOperation,
+    allow_scores: bool,
+    name: &str,
+) -> Result<ZSetOperationArgs<'a>, RespValue> {
+    let numkeys = match args[0].parse::<i64>() {
+        Ok(numkeys) if numkeys >= 1 => numkeys as usize,
+        Ok(_) => {
+            return Err(RespValue::Error(format!(
+                "at least 1 input key is needed for '{name}' command"
+            )));
+        }
+        Err(_) => {
+            return Err(RespValue::Error(
+                "value is not an integer or out of range".to_string(),
+            ));
+        }
+    };
+    let Some(keys) = args[1..].get(..numkeys) else {
+        return Err(RespValue::Error("syntax error".to_string()));
+    };
+
+    let (mut weights, mut aggregate, mut with_scores) = (Vec::new(), Aggregate::Sum, false);
+    let mut rest = &args[1 + numkeys..];
+    while let Some(option) = rest.first() {
+        let option = option.to_uppercase();
+        let combines = operation != SetOperation::Diff;
+        match option.as_str() {
+            "WEIGHTS" if combines && rest.len() > numkeys => {
+                for weight in &rest[1..=numkeys] {
+                    let Some(weight) = parse_score(weight) else {
+                        return Err(RespValue::Error("weight value is not a float".to_string()));
+                    };
+                    weights.push(weight);
+                }
+                rest = &rest[1 + numkeys..];
+            }
+            "AGGREGATE" if combines && rest.len() > 1 => {
+                aggregate = match rest[1].to_uppercase().as_str() {
+                    "SUM" => Aggregate::Sum,
+                    "MIN" => Aggregate::Min,
+                    "MAX" => Aggregate::Max,
+                    _ => return Err(RespValue::Error("syntax error".to_string())),
+                };
+                rest = &rest[2..];
+            }
+            "WITHSCORES" if allow_scores => {
+                with_scores = true;
+                rest = &rest[1..];
+            }
+            _ => return Err(RespValue::Error("syntax error".to_string())),
+        }
+    }
+    Ok(ZSetOperationArgs {
+        keys,
+        weights,
+        aggregate,
+        with_scores,
+    })
+}
+
+/// Parses the `MIN | MAX` argument of ZMPOP and BZMPOP.
+fn parse_score_end(value: &str) -> Result<ScoreEnd, RespValue> {
+    match value.to_uppercase().as_str() {
+        "MIN" => Ok(ScoreEnd::Min),
+        "MAX" => Ok(ScoreEnd::Max),
+        _ => Err(RespValue::Error("syntax error".to_string())),
+    }
+}
+
+/// Parses a sorted set score, which may be `inf` or `-inf` but not NaN.
+fn parse_score(value: &str) -> Option<f64> {
+    value.parse::<f64>().ok().filter(|score| !score.is_nan())
+}
+
+/// Parses the `min` and `max` of a score range, where `(` excludes a bound.
+fn parse_score_range(min: &str, max: &str) -> Result<ScoreRange, RespValue> {
+    let bound = |value: &str| match value.strip_prefix('(') {
+        Some(value) => Some((parse_score(value)?, true)),
+        None => Some((parse_score(value)?, false)),
+    };
+    let (Some((min, min_exclusive)), Some((max, max_exclusive))) = (bound(min), bound(max)) else {
+        return Err(RespValue::Error("min or max is not a float".to_string()));
+    };
+    Ok(ScoreRange {
+        min,
+        max,
+        min_exclusive,
+        max_exclusive,
+    })
+}
+
+/// Parses the `min` and `max` of a range of members: `-`, `+`, `[member`
+/// or `(member`.
+fn parse_lex_range(min: &str, max: &str) -> Result<LexRange, RespValue> {
+    let bound = |value: &str| match value.as_bytes().first() {
+        Some(b'-') if value.len() == 1 => Some(LexBound::Min),
+        Some(b'+') if value.len() == 1 => Some(LexBound::Max),
+        Some(b'[') => Some(LexBound::Inclusive(value[1..].to_string())),
+        Some(b'(') => Some(LexBound::Exclusive(value[1..].to_string())),
+        _ => None,
+    };
+    let (Some(min), Some(max)) = (bound(min), bound(max)) else {
+        return Err(RespValue::Error(
+            "min or max not valid string range item".to_string(),
+        ));
+    };
+    Ok(LexRange { min, max })
+}
+
+/// Parses `start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]` and,
+/// if `allow_scores` is set, `[WITHSCORES]`, as ZRANGE and ZRANGESTORE take
+/// them.
+fn parse_zrange(args: &[String], allow_scores: bool) -> Result<(ZRangeSpec, bool), RespValue> {
+    let (mut by_score, mut by_lex, mut reverse, mut with_scores) = (false, false, false, false);
+    let mut limit = None;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        match (option.to_uppercase().as_str(), options.len()) {
+            ("BYSCORE", _) => by_score = true,
+            ("BYLEX", _) => by_lex = true,
+            ("REV", _) => reverse = true,
+            ("WITHSCORES", _) if allow_scores => with_scores = true,
+            ("LIMIT", 2..) => {
+                let mut value = || options.next().and_then(|value| value.parse::<i64>().ok());
+                let (Some(offset), Some(count)) = (value(), value()) else {
+                    return Err(RespValue::Error(
+                        "value is not an integer or out of range".to_string(),
+                    ));
+                };
+                limit = Some((offset, count));
+            }
+            _ => return Err(RespValue::Error("syntax error".to_string())),
+        }
+    }
+    if by_score && by_lex {
+        return Err(RespValue::Error("syntax error".to_string()));
+    }
+    if limit.is_some() && !by_score && !by_lex {
+        return Err(RespValue::Error(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                .to_string(),
+        ));
+    }
+    if with_scores && by_lex {
+        return Err(RespValue::Error(
+            "syntax error, WITHSCORES not supported in combination with BYLEX".to_string(),
+        ));
+    }
+
+    // Going in reverse, a score or member range is given from its end.
+    let (start, stop) = (&args[0], &args[1]);
+    let (min, max) = if reverse {
+        (stop, start)
+    } else {
+        (start, stop)
+    };
+    let by = if by_score {
+        ZRangeBy::Score(parse_score_range(min, max)?)
+    } else if by_lex {
+        ZRangeBy::Lex(parse_lex_range(min, max)?)
+    } else {
+        let (Ok(start), Ok(stop)) = (start.parse::<i64>(), stop.parse::<i64>()) else {
+            return Err(RespValue::Error(
+                "value is not an integer or out of range".to_string(),
+            ));
+        };
+        ZRangeBy::Rank(start, stop)
+    };
+    Ok((ZRangeSpec { by, reverse, limit }, with_scores))
+}
+
+/// Formats a score the way Redis replies with one: the shortest digits that
+/// read back as the same number, switching to an exponent when it is very
+/// large or small.
+fn format_score(score: f64) -> String {
+    if score.is_infinite() {
+        return if score > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let scientific = format!("{score:e}");
+    let (mantissa, exponent) = scientific.split_once('e').expect("{:e} has an exponent");
+    let exponent: i32 = exponent.parse().expect("{:e} has an integer exponent");
+    if (-4..17).contains(&exponent) {
+        return score.to_string();
+    }
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exponent.abs())
+}
+
+/// Members, each followed by its score if `with_scores` is set.
+fn member_score_array(elements: Vec<(String, f64)>, with_scores: bool) -> RespValue {
+    RespValue::Array(
+        elements
+            .into_iter()
+            .flat_map(|(member, score)| {
+                let score = with_scores.then(|| RespValue::BulkString(Some(format_score(score))));
+                std::iter::once(RespValue::BulkString(Some(member))).chain(score)
+            })
+            .collect(),
+    )
+}
+
+/// Parses a longitude and latitude, which must be within the range Redis
+/// can index.
+fn parse_geo_position(longitude: &str, latitude: &str) -> Result<(f64, f64), RespValue> {
+    let (Ok(longitude), Ok(latitude)) = (longitude.parse::<f64>(), latitude.parse::<f64>()) else {
+        return Err(RespValue::Error("value is not a valid float".to_string()));
+    };
+    if !is_valid_position(longitude, latitude) {
+        return Err(RespValue::Error(format!(
+            "invalid longitude,latitude pair {longitude:.6},{latitude:.6}"
+        )));
+    }
+    Ok((longitude, latitude))
+}
+
+fn parse_geo_unit(value: &str) -> Result<GeoUnit, RespValue> {
+    match value.to_lowercase().as_str() {
+        "m" => Ok(GeoUnit::Meters),
+        "km" => Ok(GeoUnit::Kilometers),
+        "ft" => Ok(GeoUnit::Feet),
+        "mi" => Ok(GeoUnit::Miles),
+        _ => Err(RespValue::Error(
+            "unsupported unit provided. please use M, KM, FT, MI".to_string(),
+        )),
+    }
+}
+
+/// Parses a radius, width or height, named `what` in the error.
+fn parse_geo_distance(value: &str, what: &str) -> Result<f64, RespValue> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|distance| !distance.is_nan())
+        .ok_or_else(|| RespValue::Error(format!("need numeric {what}")))
+}
+
+struct GeoSearchArgs {
+    search: GeoSearch,
+    with_distance: bool,
+    with_hash: bool,
+    with_coordinates: bool,
+    /// Store distances rather than positions (`STOREDIST`).
+    store_distance: bool,
+}
+
+/// Parses the options of GEOSEARCH, or of GEOSEARCHSTORE if `name` is
+/// `geosearchstore`, after the source key.
+fn parse_geosearch_args(args: &[String], name: &str) -> Result<GeoSearchArgs, RespValue> {
+    let store = name == "geosearchstore";
+    let syntax_error = || RespValue::Error("syntax error".to_string());
+    let (mut origin, mut shape, mut unit) = (None, None, GeoUnit::Meters);
+    let (mut order, mut count, mut any) = (None, None, false);
+    let (mut with_distance, mut with_hash, mut with_coordinates, mut store_distance) =
+        (false, false, false, false);
+
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match (option.to_uppercase().as_str(), options.len()) {
+            ("WITHDIST", _) => with_distance = true,
+            ("WITHHASH", _) => with_hash = true,
+            ("WITHCOORD", _) => with_coordinates = true,
+            ("STOREDIST", _) if store => store_distance = true,
+            ("ANY", _) => any = true,
+            ("ASC", _) => order = Some(GeoOrder::Ascending),
+            ("DESC", _) => order = Some(GeoOrder::Descending),
+            ("COUNT", 1..) => {
+                let value = options.next().expect("length checked");
+                let Some(value) = value.parse::<usize>().ok().filter(|&count| count > 0) else {
+                    return Err(RespValue::Error("COUNT must be > 0".to_string()));
+                };
+                count = Some(value);
+            }
+            ("FROMMEMBER", 1..) if origin.is_none() => {
+                let member = options.next().expect("length checked");
+                origin = Some(GeoOrigin::Member(member.clone()));
+            }
+            ("FROMLONLAT", 2..) if origin.is_none() => {
+                let longitude = options.next().expect("length checked");
+                let latitude = options.next().expect("length checked");
+                let (longitude, latitude) = parse_geo_position(longitude, latitude)?;
+                origin = Some(GeoOrigin::Position(longitude, latitude));
+            }
+            ("BYRADIUS", 2..) if shape.is_none() => {
+                let radius = parse_geo_distance(options.next().expect("length checked"), "radius")?;
+                if radius < 0.0 {
+                    return Err(RespValue::Error("radius cannot be negative".to_string()));
+                }
+                unit = parse_geo_unit(options.next().expect("length checked"))?;
+                shape = Some(GeoShape::Radius(radius));
+            }
+            ("BYBOX", 3..) if shape.is_none() => {
+                let width = parse_geo_distance(options.next().expect("length checked"), "width")?;
+                let height = parse_geo_distance(options.next().expect("length checked"), "height")?;
+                if width < 0.0 || height < 0.0 {
+                    return Err(RespValue::Error(
+                        "height or width cannot be negative".to_string(),
+                    ));
+                }
+                unit = parse_geo_unit(options.next().expect("length checked"))?;
+                shape = Some(GeoShape::Box(width, height));
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+
+    let Some(origin) = origin else {
+        return Err(RespValue::Error(format!(
+            "exactly one of FROMMEMBER or FROMLONLAT can be specified for {name}"
+        )));
+    };
+    let Some(shape) = shape else {
+        return Err(RespValue::Error(format!(
+            "exactly one of BYRADIUS and BYBOX can be specified for {name}"
+        )));
+    };
+    if any && count.is_none() {
+        return Err(RespValue::Error(
+            "the ANY argument requires COUNT argument".to_string(),
+        ));
+    }
+    if store && (with_distance || with_hash || with_coordinates) {
+        return Err(RespValue::Error(
+            "GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options"
+                .to_string(),
+        ));
+    }
+
+    Ok(GeoSearchArgs {
+        search: GeoSearch {
+            origin,
+            shape,
+            unit,
+            order,
+            count,
+            any,
+        },
+        with_distance,
+        with_hash,
+        with_coordinates,
+        store_distance,
+    })
+}
+
+/// Formats a distance the way Redis replies with one, to four decimals.
+fn format_distance(distance: f64) -> String {
+    format!("{distance:.4}")
+}
+
+/// Formats a coordinate the way Redis replies with one: to 17 significant
+/// digits, without trailing zeros.
+fn format_coordinate(value: f64) -> String {
+    let scientific = format!("{value:.16e}");
+    let (_, exponent) = scientific.split_once('e').expect("{:e} has an exponent");
+    let exponent: i32 = exponent.parse().expect("{:e} has an integer exponent");
+    let decimals = (16 - exponent).max(0) as usize;
+    let formatted = format!("{value:.decimals$}");
+    if formatted.contains('.') {
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        formatted
+    }
+}
+
+/// Parses the options of HSCAN and SSCAN after the cursor, returning the
+/// MATCH pattern as it was sent, from `raw`, the COUNT hint and whether
+/// NOVALUES was given.
+fn parse_scan_options<'a>(
+    args: &[String],
+    raw: &'a [Vec<u8>],
+) -> Result<(Option<&'a [u8]>, usize, bool), RespValue> {
+    let (mut pattern, mut count, mut no_values) = (None, 10, false);
+    let mut options = args.iter().enumerate();
+    while let Some((i, option)) = options.next() {
+        match (option.to_uppercase().as_str(), options.len()) {
+            ("MATCH", 1..) => {
+                options.next();
+                pattern = Some(raw[i + 1].as_slice());
+            }
+            ("COUNT", 1..) => match options.next().map(|(_, count)| count.parse::<i64>()) {
+                Some(Ok(value)) if value >= 1 => count = value as usize,
+                Some(Ok(_)) => return Err(RespValue::Error("syntax error".to_string())),
+                _ => {
+                    return Err(RespValue::Error(
+                        "value is not an integer or out of range".to_string(),
+                    ));
+                }
+            },
+            ("NOVALUES", _) => no_values = true,
+            _ => return Err(RespValue::Error("syntax error".to_string())),
+        }
+    }
+    Ok((pattern, count, no_values))
+}
+
+/// Parses `FIELDS numfields field...` at the start of `args`, where each
+/// field takes up `width` arguments, returning the arguments after the count
+/// as they were sent, from `raw`.
+fn parse_fields<'a>(
+    args: &[String],
+    raw: &'a [Vec<u8>],
+    width: usize,
+) -> Result<&'a [Vec<u8>], RespValue> {
+    if !args
+        .first()
+        .is_some_and(|arg| arg.eq_ignore_ascii_case("FIELDS"))
+    {
+        return Err(RespValue::Error(
+            "Mandatory argument FIELDS is missing or not at the right position".to_string(),
+        ));
+    }
+    let Some(count) = args
+        .get(1)
+        .and_then(|count| count.parse::<usize>().ok())
+        .filter(|&count| count > 0)
+    else {
+        return Err(RespValue::Error(
+            "Number of fields must be a positive integer".to_string(),
+        ));
+    };
+    if args.len() - 2 != count.saturating_mul(width) {
+        return Err(RespValue::Error(
+            "The `numfields` parameter must match the number of arguments".to_string(),
+        ));
+    }
+    Ok(&raw[2..])
+}
+
+/// Fields as an array, each followed by its value when `with_values` is set.
+fn field_value_array(pairs: Vec<(Vec<u8>, Vec<u8>)>, with_values: bool) -> RespValue {
+    RespValue::Array(
+        pairs
+            .into_iter()
+            .flat_map(|(field, value)| [Some(field), with_values.then_some(value)])
+            .flatten()
+            .map(|value| RespValue::BulkBytes(Some(value)))
+            .collect(),
+    )
+}
+
+/// Trimming options of XADD and XTRIM, as they are parsed.
+#[derive(Default)]
+struct StreamTrimArgs {
+    strategy: Option<TrimStrategy>,
+    approximate: bool,
+    limit: Option<usize>,
+}
+
+impl StreamTrimArgs {
+    /// Parses the trimming option at the start of `args`, `MAXLEN|MINID
+    /// [=|~] threshold` or `LIMIT count`, returning how many arguments it
+    /// took, or 0 if there is none.
+    fn parse_option(&mut self, args: &[String]) -> Result<usize, RespValue> {
+        if args.len() < 2 {
+            return Ok(0);
+        }
+        let option = args[0].to_uppercase();
+        match option.as_str() {
+            "MAXLEN" | "MINID" => {
+                if self.strategy.is_some() {
+                    return Err(RespValue::Error(
+                        "syntax error, MAXLEN and MINID options at the same time are not compatible"
+                            .to_string(),
+                    ));
+                }
+                let mut taken = 1;
+                self.approximate = false;
+                if args.len() > 2 && matches!(args[1].as_str(), "~" | "=") {
+                    self.approximate = args[1] == "~";
+                    taken += 1;
+                }
+
+                let threshold = &args[taken];
+                self.strategy = Some(if option == "MAXLEN" {
+                    let Ok(max_len) = threshold.parse::<i64>() else {
+                        return Err(RespValue::Error(
+                            "value is not an integer or out of range".to_string(),
+                        ));
+                    };
+                    if max_len < 0 {
+                        return Err(RespValue::Error(
+                            "The MAXLEN argument must be >= 0.".to_string(),
+                        ));
+                    }
+                    TrimStrategy::MaxLen(max_len as usize)
+                } else {
+                    match StreamEntryID::parse(threshold, 0) {
+                        Ok(min_id) => TrimStrategy::MinId(min_id),
+                        Err(err) => return Err(RespValue::Error(err.to_string())),
+                    }
+                });
+                Ok(taken + 1)
+            }
+            "LIMIT" => {
+                let Ok(limit) = args[1].parse::<i64>() else {
+                    return Err(RespValue::Error(
+                        "value is not an integer or out of range".to_string(),
+                    ));
+                };
+                if limit < 0 {
+                    return Err(RespValue::Error(
+                        "The LIMIT argument must be >= 0.".to_string(),
+                    ));
+                }
+                self.limit = Some(limit as usize);
+                Ok(2)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    /// Checks the options go together, giving `None` if no trimming was
+    /// asked for.
+    fn finish(self) -> Result<Option<TrimOptions>, RespValue> {
+        match (self.strategy, self.limit) {
+            (None, None) => Ok(None),
+            (None, Some(_)) => Err(RespValue::Error(
+                "syntax error, LIMIT cannot be used without specifying a trimming strategy"
+                    .to_string(),
+            )),
+            (Some(_), Some(_)) if !self.approximate => Err(RespValue::Error(
+                "syntax error, LIMIT cannot be used without the special ~ option".to_string(),
+            )),
+            (Some(strategy), limit) => Ok(Some(TrimOptions {
+                strategy,
+                approximate: self.approximate,
+                limit,
+            })),
+        }
+    }
+}
+
+/// Parses the `start` and `end` of XRANGE. `-` and `+` stand for the
+/// smallest and greatest IDs, an ID without a sequence number covers its
+/// whole millisecond, and `(` excludes an ID from the range.
+fn parse_stream_range(start: &str, end: &str) -> Result<(StreamEntryID, StreamEntryID), RespValue> {
+    let parse = |value: &str, missing_seq: u64| match value.strip_prefix('(') {
+        Some(id) if !id.is_empty() => StreamEntryID::parse(id, missing_seq).map(|id| (id, true)),
+        _ => StreamEntryID::parse_bound(value, missing_seq).map(|id| (id, false)),
+    };
+    let (start, start_exclusive) =
+        parse(start, 0).map_err(|err| RespValue::Error(err.to_string()))?;
+    let (end, end_exclusive) =
+        parse(end, u64::MAX).map_err(|err| RespValue::Error(err.to_string()))?;
+
+    let start = match start_exclusive {
+        true => start
+            .next()
+            .ok_or_else(|| RespValue::Error("invalid start ID for the interval".to_string()))?,
+        false => start,
+    };
+    let end = match end_exclusive {
+        true => end
+            .prev()
+            .ok_or_else(|| RespValue::Error("invalid end ID for the interval".to_string()))?,
+        false => end,
+    };
+    Ok((start, end))
+}
+
+/// Stream entries as XRANGE and XREAD reply with them.
+fn stream_entries_array(entries: StramValue) -> RespValue {
+    RespValue::Array(
+        entries
+            .0
+            .into_iter()
+            .map(|(id, fields)| stream_entry(id, Some(fields)))
+            .collect(),
+    )
+}
+
+/// A stream entry as an array of its ID and its fields and values, which
+/// are nil for an entry that has been deleted.
+fn stream_entry(id: StreamEntryID, fields: Option<Vec<(String, String)>>) -> RespValue {
+    let field_values = match fields {
+        Some(fields) => RespValue::Array(
+            fields
+                .into_iter()
+                .flat_map(|(field, value)| {
+                    [
+                        RespValue::BulkString(Some(field)),
+                        RespValue::BulkString(Some(value)),
+                    ]
+                })
+                .collect(),
+        ),
+        None => RespValue::NullArray,
+    };
+    RespValue::Array(vec![
+        RespValue::BulkString(Some(id.to_string())),
+        field_values,
+    ])
+}
+
+fn stream_ids_array(ids: impl IntoIterator<Item = StreamEntryID>) -> RespValue {
+    RespValue::Array(
+        ids.into_iter()
+            .map(|id| RespValue::BulkString(Some(id.into())))
+            .collect(),
+    )
+}
+
+/// Names and values as a flat array, the way XINFO replies.
+fn info_array(pairs: Vec<(&str, RespValue)>) -> RespValue {
+    RespValue::Array(
+        pairs
+            .into_iter()
+            .flat_map(|(name, value)| [RespValue::BulkString(Some(name.to_string())), value])
+            .collect(),
+    )
+}
+
+/// What XINFO STREAM reports with or without FULL, up to its groups.
+fn stream_info_pairs(info: StreamInfo) -> Vec<(&'static str, RespValue)> {
+    vec![
+        ("length", RespValue::Integer(info.length as i64)),
+        (
+            "radix-tree-keys",
+            RespValue::Integer(info.radix_tree_keys as i64),
+        ),
+        (
+            "radix-tree-nodes",
+            RespValue::Integer(info.radix_tree_nodes as i64),
+        ),
+        (
+            "last-generated-id",
+            RespValue::BulkString(Some(info.last_generated_id.into())),
+        ),
+        (
+            "max-deleted-entry-id",
+            RespValue::BulkString(Some(info.max_deleted_id.into())),
+        ),
+        (
+            "entries-added",
+            RespValue::Integer(info.entries_added as i64),
+        ),
+        (
+            "recorded-first-entry-id",
+            RespValue::BulkString(Some(info.first_id.into())),
+        ),
+    ]
+}
+
+fn optional_integer(value: Option<u64>) -> RespValue {
+    match value {
+        Some(value) => RespValue::Integer(value as i64),
+        None => RespValue::Null,
     }
 }
@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicI64, AtomicU32, AtomicUsize, Ordering};
+
+use thiserror::Error;
+
+use crate::data::NotifyFlags;
+use crate::glob;
+
+/// Runtime parameters exposed through `CONFIG GET` / `CONFIG SET`.
+#[derive(Debug)]
+pub struct Config {
+    notify_keyspace_events: AtomicU32,
+    hll_sparse_max_bytes: AtomicUsize,
+    list_max_listpack_size: AtomicI64,
+    set_max_intset_entries: AtomicUsize,
+    stream_node_max_entries: AtomicUsize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            notify_keyspace_events: AtomicU32::default(),
+            hll_sparse_max_bytes: AtomicUsize::new(3000),
+            list_max_listpack_size: AtomicI64::new(-2),
+            set_max_intset_entries: AtomicUsize::new(512),
+            stream_node_max_entries: AtomicUsize::new(100),
+        }
+    }
+}
+
+impl Config {
+    const PARAMETERS: [&'static str; 5] = [
+        "notify-keyspace-events",
+        "hll-sparse-max-bytes",
+        "list-max-listpack-size",
+        "set-max-intset-entries",
+        "stream-node-max-entries",
+    ];
+
+    pub fn notify_keyspace_events(&self) -> NotifyFlags {
+        NotifyFlags::from_bits(self.notify_keyspace_events.load(Ordering::Relaxed))
+    }
+
+    /// Size, header included, past which a sparse HyperLogLog is converted
+    /// to the dense encoding.
+    pub fn hll_sparse_max_bytes(&self) -> usize {
+        self.hll_sparse_max_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Largest listpack a list is kept in: a number of entries when
+    /// positive, or a size from 4KB (-1) to 64KB (-5) when negative.
+    pub fn list_max_listpack_size(&self) -> i64 {
+        self.list_max_listpack_size.load(Ordering::Relaxed)
+    }
+
+    /// Most elements a set of integers is kept in an intset for.
+    pub fn set_max_intset_entries(&self) -> usize {
+        self.set_max_intset_entries.load(Ordering::Relaxed)
+    }
+
+    /// Most entries in a stream node, which approximate trimming evicts
+    /// whole.
+    pub fn stream_node_max_entries(&self) -> usize {
+        self.stream_node_max_entries.load(Ordering::Relaxed)
+    }
+
+    pub fn get(&self, pattern: &str) -> Vec<(String, String)> {
+        Self::PARAMETERS
+            .iter()
+            .filter(|name| glob::matches(&pattern.to_lowercase(), name))
+            .filter_map(|name| Some((name.to_string(), self.value_of(name)?)))
+            .collect()
+    }
+
+    pub fn set(&self, name: &str, value: &str) -> Result<(), ConfigError> {
+        match name.to_lowercase().as_str() {
+            "notify-keyspace-events" => {
+                let flags = value
+                    .parse::<NotifyFlags>()
+                    .map_err(|err| ConfigError::InvalidValue(name.to_string(), err.to_string()))?;
+                self.notify_keyspace_events
+                    .store(flags.bits(), Ordering::Relaxed);
+            }
+            "hll-sparse-max-bytes" => {
+                let bytes = value.parse::<usize>().map_err(|_| {
+                    ConfigError::InvalidValue(
+                        name.to_string(),
+                        "argument couldn't be parsed into an integer".to_string(),
+                    )
+                })?;
+                self.hll_sparse_max_bytes.store(bytes, Ordering::Relaxed);
+            }
+            "list-max-listpack-size" => {
+                let size = value.parse::<i64>().map_err(|_| {
+                    ConfigError::InvalidValue(
+                        name.to_string(),
+                        "argument couldn't be parsed into an integer".to_string(),
+                    )
+                })?;
+                self.list_max_listpack_size.store(size, Ordering::Relaxed);
+            }
+            "set-max-intset-entries" => {
+                let entries = value.parse::<usize>().map_err(|_| {
+                    ConfigError::InvalidValue(
+                        name.to_string(),
+                        "argument couldn't be parsed into an integer".to_string(),
+                    )
+                })?;
+                self.set_max_intset_entries
+                    .store(entries, Ordering::Relaxed);
+            }
+            "stream-node-max-entries" => {
+                let entries = value.parse::<usize>().map_err(|_| {
+                    ConfigError::InvalidValue(
+                        name.to_string(),
+                        "argument couldn't be parsed into an integer".to_string(),
+                    )
+                })?;
+                self.stream_node_max_entries
+                    .store(entries, Ordering::Relaxed);
+            }
+            _ => return Err(ConfigError::UnknownParameter(name.to_string())),
+        }
+        Ok(())
+    }
+
+    fn value_of(&self, name: &str) -> Option<String> {
+        match name {
+            "notify-keyspace-events" => Some(self.notify_keyspace_events().to_string()),
+            "hll-sparse-max-bytes" => Some(self.hll_sparse_max_bytes().to_string()),
+            "list-max-listpack-size" => Some(self.list_max_listpack_size().to_string()),
+            "set-max-intset-entries" => Some(self.set_max_intset_entries().to_string()),
+            "stream-node-max-entries" => Some(self.stream_node_max_entries().to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Unknown option or number of arguments for CONFIG SET - '{0}'")]
+    UnknownParameter(String),
+    #[error("CONFIG SET failed (possibly related to argument '{0}') - {1}")]
+    InvalidValue(String, String),
+}
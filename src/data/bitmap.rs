@@ -0,0 +1,364 @@
+use crate::data::{
+    Store,
+    notify::NotifyFlags,
+    record::{RecordData, RecordType, WRONGTYPE},
+    string::{MAX_STRING_LEN, StringValue},
+};
+
+/// Largest bit offset a command may address, the last bit of the largest
+/// allowed string.
+pub const MAX_BIT_OFFSET: usize = MAX_STRING_LEN * 8 - 1;
+
+/// Unit of the range given to BITCOUNT and BITPOS.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BitUnit {
+    #[default]
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+    /// Bits set in the first key but in none of the others.
+    Diff,
+}
+
+/// Integer type of a BITFIELD operation, such as `i5` or `u16`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitfieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl BitfieldType {
+    fn min(self) -> i128 {
+        if self.signed {
+            -(1i128 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(self) -> i128 {
+        if self.signed {
+            (1i128 << (self.bits - 1)) - 1
+        } else {
+            (1i128 << self.bits) - 1
+        }
+    }
+
+    /// Brings `value` into range following `overflow`, or `None` on FAIL.
+    fn fit(self, value: i128, overflow: BitfieldOverflow) -> Option<i64> {
+        if (self.min()..=self.max()).contains(&value) {
+            return Some(value as i64);
+        }
+
+        match overflow {
+            BitfieldOverflow::Wrap => {
+                let span = 1i128 << self.bits;
+                Some(((value - self.min()).rem_euclid(span) + self.min()) as i64)
+            }
+            BitfieldOverflow::Sat => Some(value.clamp(self.min(), self.max()) as i64),
+            BitfieldOverflow::Fail => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BitfieldOverflow {
+    #[default]
+    Wrap,
+    Sat,
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BitfieldOp {
+    Get(BitfieldType, usize),
+    Set(BitfieldType, usize, i64, BitfieldOverflow),
+    IncrBy(BitfieldType, usize, i64, BitfieldOverflow),
+}
+
+impl BitfieldOp {
+    fn write_end(&self) -> Option<usize> {
+        match self {
+            Self::Get(..) => None,
+            Self::Set(ty, offset, ..) | Self::IncrBy(ty, offset, ..) => {
+                Some(offset + ty.bits as usize)
+            }
+        }
+    }
+}
+
+fn get_bit(bytes: &[u8], offset: usize) -> bool {
+    bytes
+        .get(offset / 8)
+        .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+fn set_bit(bytes: &mut [u8], offset: usize, bit: bool) {
+    let mask = 0x80 >> (offset % 8);
+    if bit {
+        bytes[offset / 8] |= mask;
+    } else {
+        bytes[offset / 8] &= !mask;
+    }
+}
+
+/// Number of set bits between the inclusive bit offsets `start` and `end`.
+fn count_bits(bytes: &[u8], start: usize, end: usize) -> usize {
+    let (first, last) = (start / 8, end / 8);
+    let total: u32 = bytes[first..=last]
+        .iter()
+        .map(|byte| byte.count_ones())
+        .sum();
+    let before_start = bytes[first] & !(0xff >> (start % 8));
+    let after_end = bytes[last] & (0xff_u16 >> (end % 8 + 1)) as u8;
+    (total - before_start.count_ones() - after_end.count_ones()) as usize
+}
+
+fn read_field(bytes: &[u8], ty: BitfieldType, offset: usize) -> i64 {
+    let mut value = 0u64;
+    for i in 0..ty.bits as usize {
+        value = (value << 1) | get_bit(bytes, offset + i) as u64;
+    }
+
+    if ty.signed && ty.bits < 64 && value & (1 << (ty.bits - 1)) != 0 {
+        value |= u64::MAX << ty.bits;
+    }
+    value as i64
+}
+
+fn write_field(bytes: &mut [u8], ty: BitfieldType, offset: usize, value: i64) {
+    let value = value as u64;
+    for i in 0..ty.bits as usize {
+        let bit = value >> (ty.bits as usize - 1 - i) & 1 != 0;
+        set_bit(bytes, offset + i, bit);
+    }
+}
+
+/// Resolves a `start`/`end` pair with negative offsets counting from the
+/// end, the way BITCOUNT and BITPOS do, into an inclusive bit range.
+fn bit_range(len: usize, start: i64, end: i64, unit: BitUnit) -> Option<(usize, usize)> {
+    let total = match unit {
+        BitUnit::Byte => len as i64,
+        BitUnit::Bit => len as i64 * 8,
+    };
+    let start = if start < 0 { total + start } else { start }.max(0);
+    let end = if end < 0 { total + end } else { end }
+        .max(0)
+        .min(total - 1);
+    if total == 0 || start > end {
+        return None;
+    }
+
+    match unit {
+        BitUnit::Byte => Some((start as usize * 8, end as usize * 8 + 7)),
+        BitUnit::Bit => Some((start as usize, end as usize)),
+    }
+}
+
+impl Store {
+    /// Runs `f` over the bytes of a string key, treating a missing key as an
+    /// empty string.
+    fn with_bitmap<T>(&self, key: &str, f: impl FnOnce(&[u8]) -> T) -> anyhow::Result<T> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(f(&[]));
+        };
+        let RecordType::String(string) = &entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+
+        Ok(f(&string.to_bytes()))
+    }
+
+    pub fn setbit(&self, key: String, offset: usize, bit: bool) -> anyhow::Result<bool> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(&key);
+
+        let mut entry = self
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| RecordData::new(RecordType::String(StringValue::default()), None));
+        let RecordType::String(string) = &mut entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+        let bytes = string.bytes_mut();
+        if bytes.len() <= offset / 8 {
+            bytes.resize(offset / 8 + 1, 0);
+        }
+        let previous = get_bit(bytes, offset);
+        set_bit(bytes, offset, bit);
+        drop(entry);
+
+        self.notify_keyspace_event(NotifyFlags::STRING, "setbit", &key);
+        Ok(previous)
+    }
+
+    pub fn getbit(&self, key: &str, offset: usize) -> anyhow::Result<bool> {
+        self.with_bitmap(key, |bytes| get_bit(bytes, offset))
+    }
+
+    pub fn bitcount(&self, key: &str, range: Option<(i64, i64, BitUnit)>) -> anyhow::Result<usize> {
+        self.with_bitmap(key, |bytes| {
+            let range = match range {
+                None if bytes.is_empty() => None,
+                None => Some((0, bytes.len() * 8 - 1)),
+                Some((start, end, unit)) => bit_range(bytes.len(), start, end, unit),
+            };
+            range.map_or(0, |(start, end)| count_bits(bytes, start, end))
+        })
+    }
+
+    /// Position of the first bit equal to `bit` within the range. A clear
+    /// bit is reported just past the end of the string when no explicit end
+    /// was given, as if the value were padded with zeros.
+    pub fn bitpos(
+        &self,
+        key: &str,
+        bit: bool,
+        start: Option<i64>,
+        end: Option<i64>,
+        unit: BitUnit,
+    ) -> anyhow::Result<i64> {
+        self.expire_if_needed(key);
+        if !self.entries.contains_key(key) {
+            return Ok(if bit { -1 } else { 0 });
+        }
+
+        self.with_bitmap(key, |bytes| {
+            let Some((from, to)) =
+                bit_range(bytes.len(), start.unwrap_or(0), end.unwrap_or(-1), unit)
+            else {
+                return -1;
+            };
+
+            match (from..=to).find(|&offset| get_bit(bytes, offset) == bit) {
+                Some(offset) => offset as i64,
+                None if !bit && end.is_none() => (to + 1) as i64,
+                None => -1,
+            }
+        })
+    }
+
+    /// Stores the result of `operation` over `keys` in `destination`,
+    /// deleting it when the result is empty, and returns its length.
+    pub fn bitop(
+        &self,
+        operation: BitOperation,
+        destination: String,
+        keys: &[String],
+    ) -> anyhow::Result<usize> {
+        let _guard = self.lock_exclusive();
+
+        let mut sources = Vec::with_capacity(keys.len());
+        for key in keys {
+            sources.push(self.with_bitmap(key, <[u8]>::to_vec)?);
+        }
+
+        let len = sources.iter().map(Vec::len).max().unwrap_or(0);
+        let byte_at = |source: &Vec<u8>, i: usize| source.get(i).copied().unwrap_or(0);
+        let result: Vec<u8> = (0..len)
+            .map(|i| match operation {
+                BitOperation::Not => !byte_at(&sources[0], i),
+                BitOperation::And => sources.iter().fold(0xff, |acc, s| acc & byte_at(s, i)),
+                BitOperation::Or => sources.iter().fold(0, |acc, s| acc | byte_at(s, i)),
+                BitOperation::Xor => sources.iter().fold(0, |acc, s| acc ^ byte_at(s, i)),
+                BitOperation::Diff => {
+                    let others = sources[1..].iter().fold(0, |acc, s| acc | byte_at(s, i));
+                    byte_at(&sources[0], i) & !others
+                }
+            })
+            .collect();
+
+        self.expire_if_needed(&destination);
+        if result.is_empty() {
+            if self.entries.remove(&destination).is_some() {
+                self.notify_keyspace_event(NotifyFlags::GENERIC, "del", &destination);
+            }
+            return Ok(0);
+        }
+
+        self.entries.insert(
+            destination.clone(),
+            RecordData::new(RecordType::String(StringValue::Raw(result)), None),
+        );
+        self.notify_keyspace_event(NotifyFlags::STRING, "set", &destination);
+        Ok(len)
+    }
+
+    /// Runs BITFIELD operations in order. Writes grow the string up front to
+    /// fit the highest field written; FAIL overflows yield `None`.
+    pub fn bitfield(&self, key: String, ops: &[BitfieldOp]) -> anyhow::Result<Vec<Option<i64>>> {
+        let Some(write_end) = ops.iter().filter_map(BitfieldOp::write_end).max() else {
+            return self.with_bitmap(&key, |bytes| {
+                ops.iter()
+                    .map(|op| match op {
+                        BitfieldOp::Get(ty, offset) => Some(read_field(bytes, *ty, *offset)),
+                        _ => unreachable!("read-only operations only contain GET"),
+                    })
+                    .collect()
+            });
+        };
+
+        let _guard = self.lock_shared();
+        self.expire_if_needed(&key);
+        let mut entry = self
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| RecordData::new(RecordType::String(StringValue::default()), None));
+        let RecordType::String(string) = &mut entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+        let bytes = string.bytes_mut();
+        if bytes.len() < write_end.div_ceil(8) {
+            bytes.resize(write_end.div_ceil(8), 0);
+        }
+
+        let mut changed = false;
+        let mut results = Vec::with_capacity(ops.len());
+        for op in ops {
+            let result = match *op {
+                BitfieldOp::Get(ty, offset) => Some(read_field(bytes, ty, offset)),
+                BitfieldOp::Set(ty, offset, value, overflow) => {
+                    let value = if ty.signed {
+                        value as i128
+                    } else {
+                        value as u64 as i128
+                    };
+                    ty.fit(value, overflow).map(|value| {
+                        let previous = read_field(bytes, ty, offset);
+                        write_field(bytes, ty, offset, value);
+                        changed = true;
+                        previous
+                    })
+                }
+                BitfieldOp::IncrBy(ty, offset, increment, overflow) => {
+                    let current = read_field(bytes, ty, offset);
+                    let current = if ty.signed {
+                        current as i128
+                    } else {
+                        current as u64 as i128
+                    };
+                    ty.fit(current + increment as i128, overflow)
+                        .inspect(|&value| {
+                            write_field(bytes, ty, offset, value);
+                            changed = true;
+                        })
+                }
+            };
+            results.push(result);
+        }
+        drop(entry);
+
+        if changed {
+            self.notify_keyspace_event(NotifyFlags::STRING, "setbit", &key);
+        }
+        Ok(results)
+    }
+}
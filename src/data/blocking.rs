@@ -0,0 +1,289 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use tokio::sync::oneshot;
+
+use crate::data::Store;
+
+/// How CLIENT UNBLOCK ends a wait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnblockMode {
+    /// The command returns as if its timeout had passed.
+    Timeout,
+    /// The command fails with an UNBLOCKED error.
+    Error,
+}
+
+/// Runs a blocked command against the store, returning whether it
+/// completed, in which case it has already delivered its reply.
+type Serve = Box<dyn FnMut(&Store) -> bool + Send>;
+
+struct Waiter {
+    client_id: u64,
+    keys: Vec<String>,
+    /// Key the command pushes to when it completes, which may in turn serve
+    /// clients blocked on it.
+    destination: Option<String>,
+    serve: Serve,
+    unblock: oneshot::Sender<UnblockMode>,
+}
+
+#[derive(Default)]
+struct BlockedState {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    /// Waiters blocked on each key, oldest first.
+    queues: HashMap<String, VecDeque<u64>>,
+}
+
+impl BlockedState {
+    fn register(&mut self, waiter: Waiter) -> u64 {
+        self.next_id += 1;
+        for key in &waiter.keys {
+            self.queues
+                .entry(key.clone())
+                .or_default()
+                .push_back(self.next_id);
+        }
+        self.waiters.insert(self.next_id, waiter);
+        self.next_id
+    }
+
+    fn remove(&mut self, id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        for key in &waiter.keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|&queued| queued != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+}
+
+/// Clients blocked in BLPOP and friends.
+///
+/// Elements are handed out as they arrive: after a push, each waiter on
+/// that key runs its command in FIFO order, so no other client can take the
+/// elements first and a push of N elements serves N single-element waiters.
+/// Waiters that find nothing, such as stream readers waiting past a later
+/// ID, stay queued without holding up those behind them.
+///
+/// How many clients wait on each key is kept outside the lock, so writes to
+/// keys nobody waits on never take it.
+#[derive(Default)]
+pub(super) struct BlockedClients {
+    state: Mutex<BlockedState>,
+    waiting: DashMap<String, usize>,
+}
+
+impl fmt::Debug for BlockedClients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockedClients")
+            .field("waiters", &self.lock().waiters.len())
+            .finish()
+    }
+}
+
+impl BlockedClients {
+    fn lock(&self) -> MutexGuard<'_, BlockedState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_waited_on(&self, key: &str) -> bool {
+        self.waiting.contains_key(key)
+    }
+
+    /// Counts a client as waiting on `keys`. This comes before its attempt,
+    /// so a write that then finds nobody waiting is already visible to it.
+    fn watch(&self, keys: &[String]) {
+        for key in keys {
+            *self.waiting.entry(key.clone()).or_default() += 1;
+        }
+    }
+
+    fn unwatch(&self, keys: &[String]) {
+        for key in keys {
+            self.waiting.remove_if_mut(key, |_, count| {
+                *count -= 1;
+                *count == 0
+            });
+        }
+    }
+
+    fn remove(&self, state: &mut BlockedState, id: u64) -> Option<Waiter> {
+        let waiter = state.remove(id)?;
+        self.unwatch(&waiter.keys);
+        Some(waiter)
+    }
+}
+
+/// Removes a waiter when its command stops waiting for any reason,
+/// including the connection going away mid-wait.
+struct Registration<'a> {
+    blocked: &'a BlockedClients,
+    id: u64,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        let mut state = self.blocked.lock();
+        self.blocked.remove(&mut state, self.id);
+    }
+}
+
+impl Store {
+    /// Runs `attempt` now and, if it finds nothing, whenever one of `keys`
+    /// receives elements, until it yields a value or `timeout` passes.
+    /// `destination` is the key the command pushes to on success.
+    pub(super) async fn block_on<T: Send + 'static>(
+        &self,
+        client_id: u64,
+        keys: Vec<String>,
+        destination: Option<String>,
+        timeout: Option<Duration>,
+        mut attempt: impl FnMut(&Store) -> anyhow::Result<Option<T>> + Send + 'static,
+    ) -> anyhow::Result<Option<T>> {
+        // A deadline past what the clock can count is as good as none.
+        let now = self.clock().now_ms();
+        let deadline = timeout.and_then(|timeout| {
+            u64::try_from(timeout.as_millis())
+                .ok()
+                .and_then(|ms| now.checked_add(ms))
+        });
+        let (reply_sender, mut reply) = oneshot::channel();
+        let (unblock_sender, mut unblock) = oneshot::channel();
+
+        // With nobody queued on these keys there is no one to go first, so
+        // an attempt that succeeds needn't take the lock.
+        if !keys.iter().any(|key| self.blocked.is_waited_on(key))
+            && let Some(value) = attempt(self)?
+        {
+            if let Some(destination) = &destination {
+                self.serve_blocked(destination);
+            }
+            return Ok(Some(value));
+        }
+
+        // Trying and registering under the same lock that serving takes
+        // means a push either finds us registered or leaves its elements
+        // for our attempt.
+        let registration = {
+            let mut state = self.blocked.lock();
+            self.blocked.watch(&keys);
+            let value = attempt(self);
+            if !matches!(value, Ok(None)) {
+                self.blocked.unwatch(&keys);
+            }
+            if let Some(value) = value? {
+                drop(state);
+                if let Some(destination) = &destination {
+                    self.serve_blocked(destination);
+                }
+                return Ok(Some(value));
+            }
+
+            let mut reply_sender = Some(reply_sender);
+            let serve: Serve = Box::new(move |store| match attempt(store).transpose() {
+                None => false,
+                Some(result) => {
+                    if let Some(sender) = reply_sender.take() {
+                        let _ = sender.send(result);
+                    }
+                    true
+                }
+            });
+            let id = state.register(Waiter {
+                client_id,
+                keys,
+                destination,
+                serve,
+                unblock: unblock_sender,
+            });
+            Registration {
+                blocked: &self.blocked,
+                id,
+            }
+        };
+
+        let expired = async {
+            match deadline {
+                Some(deadline) => self.clock().sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        let (served, unblocked) = tokio::select! {
+            result = &mut reply => (result.ok(), None),
+            mode = &mut unblock => (None, mode.ok()),
+            _ = expired => (None, None),
+        };
+
+        // Once deregistered nothing can serve us, so a reply that raced
+        // with the timeout is still delivered rather than lost.
+        drop(registration);
+        if let Some(result) = served.or_else(|| reply.try_recv().ok()) {
+            return result.map(Some);
+        }
+        match unblocked.or_else(|| unblock.try_recv().ok()) {
+            Some(UnblockMode::Error) => {
+                anyhow::bail!("UNBLOCKED client unblocked via CLIENT UNBLOCK")
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Hands elements just added to `key` to the clients blocked on it.
+    /// Must be called without holding the keyspace lock or any entry.
+    pub(super) fn serve_blocked(&self, key: &str) {
+        if !self.blocked.is_waited_on(key) {
+            return;
+        }
+        let mut state = self.blocked.lock();
+        let mut ready = VecDeque::from([key.to_string()]);
+
+        while let Some(key) = ready.pop_front() {
+            let queued: Vec<u64> = state
+                .queues
+                .get(&key)
+                .map(|queue| queue.iter().copied().collect())
+                .unwrap_or_default();
+            for id in queued {
+                // An earlier waiter may have been served through another key.
+                let Some(waiter) = state.waiters.get_mut(&id) else {
+                    continue;
+                };
+                if !(waiter.serve)(self) {
+                    continue;
+                }
+                if let Some(waiter) = self.blocked.remove(&mut state, id) {
+                    ready.extend(waiter.destination);
+                }
+            }
+        }
+    }
+
+    /// Ends the blocking command of `client_id`, returning whether it was
+    /// blocked.
+    pub fn unblock_client(&self, client_id: u64, mode: UnblockMode) -> bool {
+        let mut state = self.blocked.lock();
+        let Some(id) = state
+            .waiters
+            .iter()
+            .find_map(|(&id, waiter)| (waiter.client_id == client_id).then_some(id))
+        else {
+            return false;
+        };
+
+        if let Some(waiter) = self.blocked.remove(&mut state, id) {
+            let _ = waiter.unblock.send(mode);
+        }
+        true
+    }
+}
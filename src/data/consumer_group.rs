@@ -0,0 +1,937 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    ops::Bound,
+    time::Duration,
+};
+
+use anyhow::bail;
+
+use crate::data::{
+    Store,
+    notify::NotifyFlags,
+    record::{RecordData, RecordType, WRONGTYPE},
+    stream::{StramValue, StreamEntryID, StreamFields, StreamRecord},
+};
+
+/// Error for XGROUP against a key that doesn't exist.
+const NO_KEY: &str = "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+
+/// An entry delivered to a consumer that it has yet to acknowledge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: String,
+    /// Unix milliseconds of the last delivery.
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Consumer {
+    /// Unix milliseconds of the last time it read or claimed, whether or
+    /// not it got anything.
+    pub seen_time: u64,
+    /// Unix milliseconds of the last time it got entries, if it ever did.
+    pub active_time: Option<u64>,
+    /// IDs of the entries pending for it.
+    pub pending: BTreeSet<StreamEntryID>,
+}
+
+/// Readers sharing a stream: how far the group has read it, and which of
+/// the entries handed out each consumer has yet to acknowledge.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamEntryID,
+    /// How many entries of the stream come up to the last delivered one,
+    /// if known.
+    pub entries_read: Option<u64>,
+    pub pending: BTreeMap<StreamEntryID, PendingEntry>,
+    pub consumers: BTreeMap<String, Consumer>,
+}
+
+impl ConsumerGroup {
+    /// Marks `consumer` as seen at `now`, returning whether it had to be
+    /// created.
+    fn touch_consumer(&mut self, consumer: &str, now: u64) -> bool {
+        if let Some(found) = self.consumers.get_mut(consumer) {
+            found.seen_time = now;
+            return false;
+        }
+        self.consumers.insert(
+            consumer.to_string(),
+            Consumer {
+                seen_time: now,
+                ..Default::default()
+            },
+        );
+        true
+    }
+
+    /// Makes `id` pending for `consumer` as of `delivery_time`, taking it
+    /// from whichever consumer had it. A new pending entry starts with no
+    /// deliveries counted.
+    fn assign(
+        &mut self,
+        id: &StreamEntryID,
+        consumer: &str,
+        delivery_time: u64,
+    ) -> &mut PendingEntry {
+        let entry = self
+            .pending
+            .entry(id.clone())
+            .or_insert_with(|| PendingEntry {
+                consumer: consumer.to_string(),
+                delivery_time,
+                delivery_count: 0,
+            });
+        if entry.consumer != consumer {
+            if let Some(previous) = self.consumers.get_mut(&entry.consumer) {
+                previous.pending.remove(id);
+            }
+            entry.consumer = consumer.to_string();
+        }
+        entry.delivery_time = delivery_time;
+        self.consumers
+            .entry(consumer.to_string())
+            .or_default()
+            .pending
+            .insert(id.clone());
+        entry
+    }
+
+    /// Drops `id` from the pending entries, returning whether it was there.
+    fn ack(&mut self, id: &StreamEntryID) -> bool {
+        let Some(entry) = self.pending.remove(id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(id);
+        }
+        true
+    }
+
+    fn set_active(&mut self, consumer: &str, now: u64) {
+        if let Some(consumer) = self.consumers.get_mut(consumer) {
+            consumer.active_time = Some(now);
+        }
+    }
+}
+
+/// Where XREADGROUP reads a stream from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupReadFrom {
+    /// Entries not yet delivered to the group (`>`).
+    Undelivered,
+    /// The consumer's own pending entries after an ID.
+    Pending(StreamEntryID),
+}
+
+/// Options of XREADGROUP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupReadOptions {
+    /// Most entries to read from each stream.
+    pub count: Option<usize>,
+    /// Don't make entries pending, as if acknowledged on delivery.
+    pub no_ack: bool,
+}
+
+/// Entries handed to a consumer, `None` for pending ones that have since
+/// been deleted from the stream.
+pub type GroupEntries = Vec<(StreamEntryID, Option<StreamFields>)>;
+
+/// Summary form of XPENDING.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingSummary {
+    pub count: usize,
+    /// Smallest and greatest pending IDs.
+    pub range: Option<(StreamEntryID, StreamEntryID)>,
+    /// Consumers with pending entries and how many each has.
+    pub consumers: Vec<(String, usize)>,
+}
+
+/// Which pending entries the extended form of XPENDING lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingFilter {
+    /// Least milliseconds since the last delivery.
+    pub min_idle: Option<u64>,
+    pub start: StreamEntryID,
+    pub end: StreamEntryID,
+    pub count: usize,
+    pub consumer: Option<String>,
+}
+
+/// A pending entry as the extended form of XPENDING lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingInfo {
+    pub id: StreamEntryID,
+    pub consumer: String,
+    /// Milliseconds since the last delivery.
+    pub idle: u64,
+    pub delivery_count: u64,
+}
+
+/// Options of XCLAIM.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClaimOptions {
+    /// Unix milliseconds to record as the delivery time, rather than now
+    /// (`IDLE` or `TIME`).
+    pub delivery_time: Option<u64>,
+    /// Delivery count to set rather than incrementing it (`RETRYCOUNT`).
+    pub retry_count: Option<u64>,
+    /// Claim entries that aren't pending yet (`FORCE`).
+    pub force: bool,
+    /// Leave the delivery count alone and reply with IDs only (`JUSTID`).
+    pub just_id: bool,
+    /// Move the group's last delivered ID up to this (`LASTID`).
+    pub last_id: Option<StreamEntryID>,
+}
+
+/// What XAUTOCLAIM returns: the ID to continue scanning from, 0-0 once
+/// done, the entries claimed, and the IDs of pending entries it dropped
+/// because they were deleted from the stream.
+pub type AutoClaimed = (StreamEntryID, StramValue, Vec<StreamEntryID>);
+
+/// A group as XINFO GROUPS reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupInfo {
+    pub name: String,
+    pub consumers: usize,
+    pub pending: usize,
+    pub last_delivered: StreamEntryID,
+    pub entries_read: Option<u64>,
+    /// Entries not yet delivered to the group, if that can be told.
+    pub lag: Option<u64>,
+}
+
+/// A consumer as XINFO CONSUMERS reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerInfo {
+    pub name: String,
+    pub pending: usize,
+    /// Milliseconds since it last read or claimed.
+    pub idle: u64,
+    /// Milliseconds since it last got entries, if it ever did.
+    pub inactive: Option<u64>,
+}
+
+/// A group as XINFO STREAM FULL reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDetail {
+    pub info: GroupInfo,
+    pub pending: Vec<(StreamEntryID, PendingEntry)>,
+    pub consumers: Vec<ConsumerDetail>,
+}
+
+/// A consumer as XINFO STREAM FULL reports it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerDetail {
+    pub name: String,
+    pub seen_time: u64,
+    pub active_time: Option<u64>,
+    /// How many entries are pending for it, of which `pending` may list
+    /// only the first.
+    pub pending_count: usize,
+    pub pending: Vec<(StreamEntryID, PendingEntry)>,
+}
+
+fn no_group(key: &str, group: &str) -> anyhow::Error {
+    anyhow::anyhow!("NOGROUP No such key '{key}' or consumer group '{group}'")
+}
+
+impl StreamRecord {
+    /// Whether an entry from `start` on has been deleted, which leaves the
+    /// entries after it uncounted.
+    fn has_tombstones_from(&self, start: &StreamEntryID) -> bool {
+        !self.entries.is_empty()
+            && self.max_deleted_id != StreamEntryID::MIN
+            && *start <= self.max_deleted_id
+    }
+
+    /// How many entries were added up to `id`, if that can be told from
+    /// the entries added and the first and last IDs.
+    fn estimate_entries_read(&self, id: &StreamEntryID) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.entries.is_empty() && *id <= self.last_id {
+            return Some(self.entries_added);
+        }
+        match id.cmp(&self.last_id) {
+            Ordering::Equal => return Some(self.entries_added),
+            Ordering::Greater => return None,
+            Ordering::Less => {}
+        }
+
+        let first_id = self.first_id();
+        if self.max_deleted_id != StreamEntryID::MIN && self.max_deleted_id >= first_id {
+            return None;
+        }
+        let before_first = self.entries_added - self.entries.len() as u64;
+        match id.cmp(&first_id) {
+            Ordering::Less => Some(before_first),
+            Ordering::Equal => Some(before_first + 1),
+            Ordering::Greater => None,
+        }
+    }
+
+    /// Entries of the stream not yet delivered to `group`, if known.
+    fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(read) if !self.has_tombstones_from(&group.last_delivered) => read,
+            _ => self.estimate_entries_read(&group.last_delivered)?,
+        };
+        Some(self.entries_added.saturating_sub(entries_read))
+    }
+
+    fn group_info(&self, name: &str, group: &ConsumerGroup) -> GroupInfo {
+        GroupInfo {
+            name: name.to_string(),
+            consumers: group.consumers.len(),
+            pending: group.pending.len(),
+            last_delivered: group.last_delivered.clone(),
+            entries_read: group.entries_read,
+            lag: self.lag(group),
+        }
+    }
+
+    /// Every group with its consumers, listing up to `count` pending
+    /// entries for each group and each consumer.
+    pub(super) fn group_details(&self, count: usize) -> Vec<GroupDetail> {
+        let pending_entry =
+            |id: &StreamEntryID, group: &ConsumerGroup| (id.clone(), group.pending[id].clone());
+        self.groups
+            .iter()
+            .map(|(name, group)| GroupDetail {
+                info: self.group_info(name, group),
+                pending: group
+                    .pending
+                    .keys()
+                    .take(count)
+                    .map(|id| pending_entry(id, group))
+                    .collect(),
+                consumers: group
+                    .consumers
+                    .iter()
+                    .map(|(name, consumer)| ConsumerDetail {
+                        name: name.clone(),
+                        seen_time: consumer.seen_time,
+                        active_time: consumer.active_time,
+                        pending_count: consumer.pending.len(),
+                        pending: consumer
+                            .pending
+                            .iter()
+                            .take(count)
+                            .map(|id| pending_entry(id, group))
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Reads for `consumer` of `group` as XREADGROUP does, returning the
+    /// entries and whether the consumer had to be created, or `None` if
+    /// there is no such group.
+    fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        from: &GroupReadFrom,
+        options: GroupReadOptions,
+        now: u64,
+    ) -> Option<(GroupEntries, bool)> {
+        let count = options.count.unwrap_or(usize::MAX);
+        let read: GroupEntries = match from {
+            GroupReadFrom::Undelivered => {
+                let found = self.groups.get(group)?;
+                let read: GroupEntries = self
+                    .entries
+                    .range((Bound::Excluded(&found.last_delivered), Bound::Unbounded))
+                    .take(count)
+                    .map(|(id, fields)| (id.clone(), Some(fields.clone())))
+                    .collect();
+                let mut entries_read = found.entries_read;
+                for (id, _) in &read {
+                    entries_read = match entries_read {
+                        Some(read) if !self.has_tombstones_from(id) => Some(read + 1),
+                        _ => self.estimate_entries_read(id),
+                    };
+                }
+
+                let found = self.groups.get_mut(group)?;
+                if let Some((last, _)) = read.last() {
+                    found.last_delivered = last.clone();
+                    found.entries_read = entries_read;
+                }
+                read
+            }
+            GroupReadFrom::Pending(after) => {
+                let found = self.groups.get(group)?;
+                found
+                    .consumers
+                    .get(consumer)
+                    .into_iter()
+                    .flat_map(|found| {
+                        found
+                            .pending
+                            .range((Bound::Excluded(after), Bound::Unbounded))
+                    })
+                    .take(count)
+                    .map(|id| (id.clone(), self.entries.get(id).cloned()))
+                    .collect()
+            }
+        };
+
+        let found = self.groups.get_mut(group)?;
+        let created = found.touch_consumer(consumer, now);
+        for (id, fields) in &read {
+            match from {
+                GroupReadFrom::Undelivered if !options.no_ack => {
+                    found.assign(id, consumer, now).delivery_count = 1;
+                }
+                GroupReadFrom::Pending(_) if fields.is_some() => {
+                    if let Some(pending) = found.pending.get_mut(id) {
+                        pending.delivery_time = now;
+                        pending.delivery_count += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !read.is_empty() {
+            found.set_active(consumer, now);
+        }
+        Some((read, created))
+    }
+}
+
+impl Store {
+    /// Runs `f` over the stream at `key`, or returns `None` if there is
+    /// none.
+    fn with_stream<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&StreamRecord) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Option<T>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(None);
+        };
+        let RecordType::Stream(stream) = &entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        f(stream).map(Some)
+    }
+
+    /// Runs `f` over the stream at `key` for writing, or returns `None` if
+    /// there is none.
+    fn with_stream_mut<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut StreamRecord) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Option<T>> {
+        self.expire_if_needed(key);
+        let Some(mut entry) = self.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        let RecordType::Stream(stream) = &mut entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        f(stream).map(Some)
+    }
+
+    /// Runs `f` over `group` of the stream at `key` for writing, failing as
+    /// XGROUP does if either is missing.
+    fn with_group_mut<T>(
+        &self,
+        key: &str,
+        group: &str,
+        f: impl FnOnce(&mut ConsumerGroup) -> T,
+    ) -> anyhow::Result<T> {
+        let result = self.with_stream_mut(key, |stream| match stream.groups.get_mut(group) {
+            Some(found) => Ok(f(found)),
+            None => bail!("NOGROUP No such consumer group '{group}' for key name '{key}'"),
+        })?;
+        match result {
+            Some(result) => Ok(result),
+            None => bail!(NO_KEY),
+        }
+    }
+
+    /// Creates `group` reading the stream at `key` after `start`, or after
+    /// its last entry if `None`, with `entries_read` entries taken as read
+    /// up to there. With `make_stream` a missing stream is created empty.
+    pub fn xgroup_create(
+        &self,
+        key: &str,
+        group: &str,
+        start: Option<StreamEntryID>,
+        entries_read: Option<u64>,
+        make_stream: bool,
+    ) -> anyhow::Result<()> {
+        {
+            let _guard = self.lock_shared();
+            self.expire_if_needed(key);
+            if make_stream {
+                self.entries.entry(key.to_string()).or_insert_with(|| {
+                    RecordData::new(RecordType::Stream(StreamRecord::default()), None)
+                });
+            }
+            let created = self.with_stream_mut(key, |stream| {
+                if stream.groups.contains_key(group) {
+                    bail!("BUSYGROUP Consumer Group name already exists");
+                }
+                let last_delivered = start.unwrap_or_else(|| stream.last_id.clone());
+                stream.groups.insert(
+                    group.to_string(),
+                    ConsumerGroup {
+                        last_delivered,
+                        entries_read,
+                        ..Default::default()
+                    },
+                );
+                Ok(())
+            })?;
+            if created.is_none() {
+                bail!(NO_KEY);
+            }
+        }
+
+        self.notify_keyspace_event(NotifyFlags::STREAM, "xgroup-create", key);
+        Ok(())
+    }
+
+    /// Sets the last delivered ID of `group` to `start`, or to the last ID
+    /// of the stream if `None`, and the entries read up to it.
+    pub fn xgroup_setid(
+        &self,
+        key: &str,
+        group: &str,
+        start: Option<StreamEntryID>,
+        entries_read: Option<u64>,
+    ) -> anyhow::Result<()> {
+        {
+            let _guard = self.lock_shared();
+            let last_id = self.with_stream(key, |stream| Ok(stream.last_id.clone()))?;
+            self.with_group_mut(key, group, |group| {
+                group.last_delivered = start.or(last_id).unwrap_or_default();
+                group.entries_read = entries_read;
+            })?;
+        }
+
+        self.notify_keyspace_event(NotifyFlags::STREAM, "xgroup-setid", key);
+        Ok(())
+    }
+
+    /// Deletes `group` with its consumers and pending entries, returning
+    /// whether it existed.
+    pub fn xgroup_destroy(&self, key: &str, group: &str) -> anyhow::Result<bool> {
+        let destroyed = {
+            let _guard = self.lock_shared();
+            let destroyed =
+                self.with_stream_mut(key, |stream| Ok(stream.groups.remove(group).is_some()))?;
+            match destroyed {
+                Some(destroyed) => destroyed,
+                None => bail!(NO_KEY),
+            }
+        };
+
+        if destroyed {
+            self.notify_keyspace_event(NotifyFlags::STREAM, "xgroup-destroy", key);
+            // Clients blocked reading for the group find out it is gone.
+            self.serve_blocked(key);
+        }
+        Ok(destroyed)
+    }
+
+    /// Adds `consumer` to `group`, returning whether it is new.
+    pub fn xgroup_createconsumer(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+    ) -> anyhow::Result<bool> {
+        let now = self.clock().now_ms();
+        let created = {
+            let _guard = self.lock_shared();
+            self.with_group_mut(key, group, |group| {
+                !group.consumers.contains_key(consumer) && group.touch_consumer(consumer, now)
+            })?
+        };
+
+        if created {
+            self.notify_keyspace_event(NotifyFlags::STREAM, "xgroup-createconsumer", key);
+        }
+        Ok(created)
+    }
+
+    /// Removes `consumer` from `group` along with its pending entries,
+    /// returning how many it had.
+    pub fn xgroup_delconsumer(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+    ) -> anyhow::Result<usize> {
+        let removed = {
+            let _guard = self.lock_shared();
+            self.with_group_mut(key, group, |group| {
+                let removed = group.consumers.remove(consumer)?;
+                for id in &removed.pending {
+                    group.pending.remove(id);
+                }
+                Some(removed.pending.len())
+            })?
+        };
+
+        let Some(pending) = removed else {
+            return Ok(0);
+        };
+        self.notify_keyspace_event(NotifyFlags::STREAM, "xgroup-delconsumer", key);
+        Ok(pending)
+    }
+
+    /// Reads each stream for `consumer` of `group`, creating the consumer
+    /// if needed. Streams read with [`GroupReadFrom::Undelivered`] are left
+    /// out if there is nothing new, and if that leaves nothing, `None` is
+    /// returned. Unless NOACK is given, new entries become pending for the
+    /// consumer.
+    pub fn xreadgroup(
+        &self,
+        group: &str,
+        consumer: &str,
+        streams: &[(String, GroupReadFrom)],
+        options: GroupReadOptions,
+    ) -> anyhow::Result<Option<Vec<(String, GroupEntries)>>> {
+        let now = self.clock().now_ms();
+        let mut created_on = Vec::new();
+        let result = {
+            let _guard = self.lock_shared();
+            // Check every stream first so a missing group changes nothing.
+            for (key, _) in streams {
+                let found =
+                    self.with_stream(key, |stream| Ok(stream.groups.contains_key(group)))?;
+                if found != Some(true) {
+                    bail!(
+                        "NOGROUP No such key '{key}' or consumer group '{group}' in XREADGROUP with GROUP option"
+                    );
+                }
+            }
+
+            let mut result = Vec::new();
+            for (key, from) in streams {
+                let read = self.with_stream_mut(key, |stream| {
+                    Ok(stream.read_group(group, consumer, from, options, now))
+                })?;
+                let Some((entries, created)) = read.flatten() else {
+                    continue;
+                };
+                if created {
+                    created_on.push(key.clone());
+                }
+                if matches!(from, GroupReadFrom::Pending(_)) || !entries.is_empty() {
+                    result.push((key.clone(), entries));
+                }
+            }
+            result
+        };
+
+        for key in created_on {
+            self.notify_keyspace_event(NotifyFlags::STREAM, "xgroup-createconsumer", &key);
+        }
+        Ok((!result.is_empty()).then_some(result))
+    }
+
+    /// [`Store::xreadgroup`] that waits up to `timeout`, forever if `None`,
+    /// for new entries in one of the streams.
+    pub async fn bxreadgroup(
+        &self,
+        client_id: u64,
+        group: String,
+        consumer: String,
+        streams: Vec<(String, GroupReadFrom)>,
+        options: GroupReadOptions,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<Option<Vec<(String, GroupEntries)>>> {
+        let keys = streams.iter().map(|(key, _)| key.clone()).collect();
+        self.block_on(client_id, keys, None, timeout, move |store| {
+            store.xreadgroup(&group, &consumer, &streams, options)
+        })
+        .await
+    }
+
+    /// Acknowledges `ids` for `group`, returning how many were pending.
+    pub fn xack(&self, key: &str, group: &str, ids: &[StreamEntryID]) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        let acked = self.with_stream_mut(key, |stream| {
+            Ok(stream
+                .groups
+                .get_mut(group)
+                .map_or(0, |group| ids.iter().filter(|id| group.ack(id)).count()))
+        })?;
+        Ok(acked.unwrap_or(0))
+    }
+
+    /// Summary form of XPENDING for `group`.
+    pub fn xpending_summary(&self, key: &str, group: &str) -> anyhow::Result<PendingSummary> {
+        let summary = self.with_stream(key, |stream| {
+            let Some(group) = stream.groups.get(group) else {
+                return Ok(None);
+            };
+            let range = group
+                .pending
+                .first_key_value()
+                .zip(group.pending.last_key_value())
+                .map(|((first, _), (last, _))| (first.clone(), last.clone()));
+            let consumers = group
+                .consumers
+                .iter()
+                .filter(|(_, consumer)| !consumer.pending.is_empty())
+                .map(|(name, consumer)| (name.clone(), consumer.pending.len()))
+                .collect();
+            Ok(Some(PendingSummary {
+                count: group.pending.len(),
+                range,
+                consumers,
+            }))
+        })?;
+        summary.flatten().ok_or_else(|| no_group(key, group))
+    }
+
+    /// Extended form of XPENDING: the pending entries of `group` that pass
+    /// `filter`.
+    pub fn xpending(
+        &self,
+        key: &str,
+        group: &str,
+        filter: &PendingFilter,
+    ) -> anyhow::Result<Vec<PendingInfo>> {
+        let now = self.clock().now_ms();
+        let pending = self.with_stream(key, |stream| {
+            let Some(group) = stream.groups.get(group) else {
+                return Ok(None);
+            };
+            if filter.start > filter.end {
+                return Ok(Some(Vec::new()));
+            }
+
+            let ids: Box<dyn Iterator<Item = &StreamEntryID>> = match &filter.consumer {
+                Some(consumer) => match group.consumers.get(consumer) {
+                    Some(consumer) => Box::new(consumer.pending.range(&filter.start..=&filter.end)),
+                    None => return Ok(Some(Vec::new())),
+                },
+                None => Box::new(
+                    group
+                        .pending
+                        .range(&filter.start..=&filter.end)
+                        .map(|(id, _)| id),
+                ),
+            };
+            Ok(Some(
+                ids.filter_map(|id| {
+                    let entry = &group.pending[id];
+                    let idle = now.saturating_sub(entry.delivery_time);
+                    filter
+                        .min_idle
+                        .is_none_or(|min_idle| idle >= min_idle)
+                        .then(|| PendingInfo {
+                            id: id.clone(),
+                            consumer: entry.consumer.clone(),
+                            idle,
+                            delivery_count: entry.delivery_count,
+                        })
+                })
+                .take(filter.count)
+                .collect(),
+            ))
+        })?;
+        pending.flatten().ok_or_else(|| no_group(key, group))
+    }
+
+    /// Hands the entries among `ids` that have been pending for at least
+    /// `min_idle` milliseconds over to `consumer`, returning them. Pending
+    /// entries deleted from the stream are dropped instead.
+    pub fn xclaim(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        ids: &[StreamEntryID],
+        options: &ClaimOptions,
+    ) -> anyhow::Result<StramValue> {
+        let now = self.clock().now_ms();
+        let delivery_time = options.delivery_time.map_or(now, |time| time.min(now));
+        let (claimed, created) = {
+            let _guard = self.lock_shared();
+            let claimed = self.with_stream_mut(key, |stream| {
+                let StreamRecord {
+                    entries, groups, ..
+                } = stream;
+                let Some(group) = groups.get_mut(group) else {
+                    return Ok(None);
+                };
+                if let Some(last_id) = &options.last_id
+                    && *last_id > group.last_delivered
+                {
+                    group.last_delivered = last_id.clone();
+                }
+                if let Some(found) = group.consumers.get_mut(consumer) {
+                    found.seen_time = now;
+                }
+
+                let (mut claimed, mut created) = (Vec::new(), false);
+                for id in ids {
+                    let fields = entries.get(id);
+                    let since = group.pending.get(id).map(|pending| pending.delivery_time);
+                    // FORCE claims entries of the stream nobody was given.
+                    let forced = options.force && fields.is_some() && since.is_none();
+                    if !forced {
+                        let Some(since) = since else {
+                            continue;
+                        };
+                        if now.saturating_sub(since) < min_idle {
+                            continue;
+                        }
+                    }
+                    let Some(fields) = fields else {
+                        group.ack(id);
+                        continue;
+                    };
+
+                    created |= group.touch_consumer(consumer, now);
+                    let pending = group.assign(id, consumer, delivery_time);
+                    if forced {
+                        pending.delivery_count = 1;
+                    }
+                    match options.retry_count {
+                        Some(retry_count) => pending.delivery_count = retry_count,
+                        None if !options.just_id => pending.delivery_count += 1,
+                        None => {}
+                    }
+                    group.set_active(consumer, now);
+                    claimed.push((id.clone(), fields.clone()));
+                }
+                Ok(Some((claimed, created)))
+            })?;
+            claimed.flatten().ok_or_else(|| no_group(key, group))?
+        };
+
+        if created {
+            self.notify_keyspace_event(NotifyFlags::STREAM, "xgroup-createconsumer", key);
+        }
+        Ok(StramValue(claimed))
+    }
+
+    /// Claims for `consumer` up to `count` entries pending in `group` for at
+    /// least `min_idle` milliseconds, scanning from `start`.
+    pub fn xautoclaim(
+        &self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle: u64,
+        (start, count): (&StreamEntryID, usize),
+        just_id: bool,
+    ) -> anyhow::Result<AutoClaimed> {
+        let now = self.clock().now_ms();
+        let (claimed, created) = {
+            let _guard = self.lock_shared();
+            let claimed = self.with_stream_mut(key, |stream| {
+                let StreamRecord {
+                    entries, groups, ..
+                } = stream;
+                let Some(group) = groups.get_mut(group) else {
+                    return Ok(None);
+                };
+                if let Some(found) = group.consumers.get_mut(consumer) {
+                    found.seen_time = now;
+                }
+
+                // Scanning is bounded too, so a PEL of entries too recent
+                // to claim isn't walked in full.
+                let attempts = count.saturating_mul(10);
+                let candidates: Vec<StreamEntryID> = group
+                    .pending
+                    .range(start..)
+                    .take(attempts.saturating_add(1))
+                    .map(|(id, _)| id.clone())
+                    .collect();
+
+                let (mut claimed, mut deleted, mut created) = (Vec::new(), Vec::new(), false);
+                let (mut remaining, mut examined) = (count, 0);
+                while examined < attempts.min(candidates.len()) && remaining > 0 {
+                    let id = &candidates[examined];
+                    examined += 1;
+                    let Some(fields) = entries.get(id) else {
+                        group.ack(id);
+                        deleted.push(id.clone());
+                        remaining -= 1;
+                        continue;
+                    };
+                    if now.saturating_sub(group.pending[id].delivery_time) < min_idle {
+                        continue;
+                    }
+
+                    created |= group.touch_consumer(consumer, now);
+                    let pending = group.assign(id, consumer, now);
+                    if !just_id {
+                        pending.delivery_count += 1;
+                    }
+                    group.set_active(consumer, now);
+                    claimed.push((id.clone(), fields.clone()));
+                    remaining -= 1;
+                }
+
+                let next = candidates.get(examined).cloned().unwrap_or_default();
+                Ok(Some(((next, StramValue(claimed), deleted), created)))
+            })?;
+            claimed.flatten().ok_or_else(|| no_group(key, group))?
+        };
+
+        if created {
+            self.notify_keyspace_event(NotifyFlags::STREAM, "xgroup-createconsumer", key);
+        }
+        Ok(claimed)
+    }
+
+    /// Groups of the stream at `key`, as XINFO GROUPS lists them.
+    pub fn xinfo_groups(&self, key: &str) -> anyhow::Result<Vec<GroupInfo>> {
+        let groups = self.with_stream(key, |stream| {
+            Ok(stream
+                .groups
+                .iter()
+                .map(|(name, group)| stream.group_info(name, group))
+                .collect())
+        })?;
+        groups.ok_or_else(|| anyhow::anyhow!("no such key"))
+    }
+
+    /// Consumers of `group`, as XINFO CONSUMERS lists them.
+    pub fn xinfo_consumers(&self, key: &str, group: &str) -> anyhow::Result<Vec<ConsumerInfo>> {
+        let now = self.clock().now_ms();
+        let consumers = self.with_stream(key, |stream| {
+            let Some(found) = stream.groups.get(group) else {
+                bail!("NOGROUP No such consumer group '{group}' for key name '{key}'");
+            };
+            Ok(found
+                .consumers
+                .iter()
+                .map(|(name, consumer)| ConsumerInfo {
+                    name: name.clone(),
+                    pending: consumer.pending.len(),
+                    idle: now.saturating_sub(consumer.seen_time),
+                    inactive: consumer
+                        .active_time
+                        .map(|active_time| now.saturating_sub(active_time)),
+                })
+                .collect())
+        })?;
+        consumers.ok_or_else(|| anyhow::anyhow!("no such key"))
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    borrow::Borrow,
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hash},
+};
+
+use crate::random;
+
+/// Fewest buckets a non-empty table has.
+const MIN_BUCKETS: usize = 4;
+
+/// Hash table that can be walked with a SCAN cursor.
+///
+/// Std's maps keep their layout to themselves, so this one keeps its own
+/// power-of-two array of buckets. SCAN visits buckets in the order of their
+/// bit-reversed index: growing or shrinking the table splits or merges
+/// buckets without moving any behind the cursor, so every element present
+/// for a whole scan is returned at least once, as Redis guarantees.
+#[derive(Clone)]
+pub struct Dict<K, V> {
+    buckets: Vec<Vec<(K, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K, V> Default for Dict<K, V> {
+    fn default() -> Self {
+        Self {
+            buckets: Vec::new(),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for Dict<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.buckets.iter().flatten().map(|(k, v)| (k, v)))
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq, V: PartialEq> PartialEq for Dict<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl<K: Hash + Eq, V: Eq> Eq for Dict<K, V> {}
+
+impl<K: Hash + Eq, V> Dict<K, V> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn bucket_of<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        self.hasher.hash_one(key) as usize & (self.buckets.len() - 1)
+    }
+
+    fn position<Q>(&self, key: &Q) -> Option<(usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        let bucket = self.bucket_of(key);
+        let index = self.buckets[bucket]
+            .iter()
+            .position(|(candidate, _)| candidate.borrow() == key)?;
+        Some((bucket, index))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, index) = self.position(key)?;
+        Some(&self.buckets[bucket][index].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.position(key).is_some()
+    }
+
+    /// Sets `key` to `value`, returning the value it replaced.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some((bucket, index)) = self.position(&key) {
+            return Some(std::mem::replace(&mut self.buckets[bucket][index].1, value));
+        }
+        self.push_new(key, value);
+        None
+    }
+
+    /// The value at `key`, inserting `default()` first if there is none.
+    pub fn get_or_insert_with(&mut self, key: K, default: impl FnOnce() -> V) -> &mut V {
+        let (bucket, index) = match self.position(&key) {
+            Some(position) => position,
+            None => self.push_new(key, default()),
+        };
+        &mut self.buckets[bucket][index].1
+    }
+
+    /// Adds an entry for a key known to be missing, returning where it went.
+    fn push_new(&mut self, key: K, value: V) -> (usize, usize) {
+        if self.len >= self.buckets.len() {
+            self.resize((self.buckets.len() * 2).max(MIN_BUCKETS));
+        }
+        let bucket = self.bucket_of(&key);
+        self.buckets[bucket].push((key, value));
+        self.len += 1;
+        (bucket, self.buckets[bucket].len() - 1)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (bucket, index) = self.position(key)?;
+        let (_, value) = self.buckets[bucket].swap_remove(index);
+        self.len -= 1;
+
+        if self.buckets.len() > MIN_BUCKETS && self.len * 8 < self.buckets.len() {
+            self.resize(self.len.next_power_of_two().max(MIN_BUCKETS));
+        }
+        Some(value)
+    }
+
+    fn resize(&mut self, buckets: usize) {
+        let entries = std::mem::replace(
+            &mut self.buckets,
+            std::iter::repeat_with(Vec::new).take(buckets).collect(),
+        );
+        for (key, value) in entries.into_iter().flatten() {
+            let bucket = self.bucket_of(&key);
+            self.buckets[bucket].push((key, value));
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.buckets
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// A random entry. Entries sharing a bucket are a little less likely to
+    /// be picked, as with Redis' own random key sampling.
+    pub fn random(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        loop {
+            let bucket = &self.buckets[random::below(self.buckets.len())];
+            if !bucket.is_empty() {
+                let (key, value) = &bucket[random::below(bucket.len())];
+                return Some((key, value));
+            }
+        }
+    }
+
+    /// One SCAN step: calls `visit` on the entries of the buckets from
+    /// `cursor` on until about `count` have been visited, and returns the
+    /// cursor to continue from, 0 once the whole table has been visited.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&K, &V)) -> u64 {
+        let (mut cursor, mut seen) = (cursor, 0);
+        // Like Redis, `count` bounds the entries visited rather than those
+        // the caller keeps, and a sparse table gives up after ten times as
+        // many buckets so a single call can't stall.
+        let mut visits = count.saturating_mul(10).max(1);
+        loop {
+            cursor = self.scan_bucket(cursor, |key, value| {
+                seen += 1;
+                visit(key, value);
+            });
+            visits -= 1;
+            if cursor == 0 || visits == 0 || seen >= count {
+                return cursor;
+            }
+        }
+    }
+
+    /// Calls `visit` on the entries of the bucket at `cursor` and returns the
+    /// cursor of the next bucket.
+    fn scan_bucket(&self, cursor: u64, mut visit: impl FnMut(&K, &V)) -> u64 {
+        if self.buckets.is_empty() {
+            return 0;
+        }
+        let mask = (self.buckets.len() - 1) as u64;
+        for (key, value) in &self.buckets[(cursor & mask) as usize] {
+            visit(key, value);
+        }
+
+        // Increment the bit-reversed cursor, with the bits above the mask set
+        // so the carry runs off the end once every bucket is done.
+        (cursor | !mask)
+            .reverse_bits()
+            .wrapping_add(1)
+            .reverse_bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+
+    #[test]
+    fn matches_a_hash_map_under_random_edits() {
+        let mut dict = Dict::default();
+        let mut model = HashMap::new();
+
+        for round in 0..5000 {
+            let key = random::below(if round < 2500 { 1000 } else { 100 });
+            match random::below(4) {
+                0 => assert_eq!(dict.remove(&key), model.remove(&key)),
+                1 => {
+                    *dict.get_or_insert_with(key, || 0) += 1;
+                    *model.entry(key).or_insert(0) += 1;
+                }
+                _ => assert_eq!(dict.insert(key, round), model.insert(key, round)),
+            }
+        }
+
+        assert_eq!(dict.len(), model.len());
+        for (key, value) in &model {
+            assert_eq!(dict.get(key), Some(value));
+        }
+        let entries: HashMap<_, _> = dict.iter().map(|(&key, &value)| (key, value)).collect();
+        assert_eq!(entries, model);
+        assert!(
+            dict.random()
+                .is_some_and(|(key, _)| model.contains_key(key))
+        );
+    }
+
+    #[test]
+    fn scan_returns_every_element_across_grow_and_shrink() {
+        let mut dict = Dict::default();
+        for key in 0..200 {
+            dict.insert(key, ());
+        }
+
+        let mut seen = HashSet::new();
+        let (mut cursor, mut steps) = (0, 0);
+        loop {
+            cursor = dict.scan(cursor, 5, |&key, _| {
+                seen.insert(key);
+            });
+            if cursor == 0 {
+                break;
+            }
+
+            // Keys other than the first 200 come and go, growing the table
+            // eightfold and shrinking it back down between steps.
+            steps += 1;
+            if steps % 2 == 1 {
+                for key in 1000..3000 {
+                    dict.insert(key, ());
+                }
+            } else {
+                for key in 1000..3000 {
+                    dict.remove(&key);
+                }
+            }
+        }
+
+        assert!((0..200).all(|key| seen.contains(&key)));
+        assert!(steps > 2);
+    }
+
+    #[test]
+    fn scan_of_an_empty_table_finishes_at_once() {
+        let dict: Dict<u32, ()> = Dict::default();
+        assert_eq!(dict.scan(0, 10, |_, _| panic!("nothing to visit")), 0);
+        assert_eq!(dict.random(), None);
+    }
+}
@@ -0,0 +1,482 @@
+use anyhow::bail;
+
+use crate::data::{
+    Store,
+    record::{RecordType, WRONGTYPE},
+    zset::{ScoreRange, ZAddOptions, ZSetValue},
+};
+
+/// Bits of precision per coordinate in a geohash score.
+const STEP_MAX: u32 = 26;
+const LONGITUDE_RANGE: (f64, f64) = (-180.0, 180.0);
+/// Latitudes EPSG:3857 can map, the limits Redis accepts.
+const LATITUDE_RANGE: (f64, f64) = (-85.05112878, 85.05112878);
+/// Latitudes of a standard geohash, as GEOHASH reports them.
+const STANDARD_LATITUDE_RANGE: (f64, f64) = (-90.0, 90.0);
+const MERCATOR_MAX: f64 = 20037726.37;
+/// Earth's radius as Redis takes it for distances.
+const EARTH_RADIUS_IN_METERS: f64 = 6372797.560856;
+const GEOHASH_ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Whether Redis can index a point at this longitude and latitude.
+pub fn is_valid_position(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_RANGE.0..=LONGITUDE_RANGE.1).contains(&longitude)
+        && (LATITUDE_RANGE.0..=LATITUDE_RANGE.1).contains(&latitude)
+}
+
+/// Spreads the bits of `value` out to the even bits of the result.
+fn spread(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | (x << 16)) & 0x0000_ffff_0000_ffff;
+    x = (x | (x << 8)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// Gathers the even bits of `x` back together, undoing [`spread`].
+fn squash(x: u64) -> u32 {
+    let mut x = x & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | (x >> 4)) & 0x00ff_00ff_00ff_00ff;
+    x = (x | (x >> 8)) & 0x0000_ffff_0000_ffff;
+    ((x | (x >> 16)) & 0x0000_0000_ffff_ffff) as u32
+}
+
+/// Geohash cell of `step` bits per coordinate holding a point: latitude
+/// bits on the even positions and longitude bits on the odd ones.
+fn encode_in(longitude: f64, latitude: f64, step: u32, latitudes: (f64, f64)) -> u64 {
+    let cells = (1u64 << step) as f64;
+    let offset = |value: f64, (min, max): (f64, f64)| {
+        (((value - min) / (max - min)) * cells).min(cells - 1.0) as u32
+    };
+    spread(offset(latitude, latitudes)) | (spread(offset(longitude, LONGITUDE_RANGE)) << 1)
+}
+
+/// Longitudes and latitudes covered by a cell.
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    longitude: (f64, f64),
+    latitude: (f64, f64),
+}
+
+fn decode_cell(bits: u64, step: u32) -> Area {
+    let cells = (1u64 << step) as f64;
+    let range = |offset: u32, (min, max): (f64, f64)| {
+        let scale = max - min;
+        (
+            min + (offset as f64 / cells) * scale,
+            min + ((offset as f64 + 1.0) / cells) * scale,
+        )
+    };
+    Area {
+        longitude: range(squash(bits >> 1), LONGITUDE_RANGE),
+        latitude: range(squash(bits), LATITUDE_RANGE),
+    }
+}
+
+/// The score a point is stored under.
+pub fn encode(longitude: f64, latitude: f64) -> f64 {
+    encode_in(longitude, latitude, STEP_MAX, LATITUDE_RANGE) as f64
+}
+
+/// The longitude and latitude a score stands for: the center of its cell.
+pub fn decode(score: f64) -> (f64, f64) {
+    let area = decode_cell(score as u64, STEP_MAX);
+    let longitude = (area.longitude.0 + area.longitude.1) / 2.0;
+    let latitude = (area.latitude.0 + area.latitude.1) / 2.0;
+    (
+        longitude.clamp(LONGITUDE_RANGE.0, LONGITUDE_RANGE.1),
+        latitude.clamp(LATITUDE_RANGE.0, LATITUDE_RANGE.1),
+    )
+}
+
+/// The 11-character standard geohash of the point a score stands for.
+fn geohash_string(score: f64) -> String {
+    let (longitude, latitude) = decode(score);
+    let bits = encode_in(longitude, latitude, STEP_MAX, STANDARD_LATITUDE_RANGE);
+    (0..11)
+        .map(|i| {
+            // 52 bits fill ten characters and two bits of the eleventh,
+            // which Redis leaves as 0.
+            let index = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            GEOHASH_ALPHABET[index as usize] as char
+        })
+        .collect()
+}
+
+/// Great-circle distance in meters, by the haversine formula.
+pub fn distance(longitude1: f64, latitude1: f64, longitude2: f64, latitude2: f64) -> f64 {
+    let (latitude1, latitude2) = (latitude1.to_radians(), latitude2.to_radians());
+    let u = ((latitude2 - latitude1) / 2.0).sin();
+    let v = ((longitude2.to_radians() - longitude1.to_radians()) / 2.0).sin();
+    2.0 * EARTH_RADIUS_IN_METERS
+        * (u * u + latitude1.cos() * latitude2.cos() * v * v)
+            .sqrt()
+            .asin()
+}
+
+/// Moves a cell by `dx` cells east and `dy` cells north, wrapping around.
+fn neighbor(bits: u64, step: u32, dx: i8, dy: i8) -> u64 {
+    let shift = 64 - step * 2;
+    let step_along = |bits: u64, mask: u64, d: i8| {
+        let (along, across) = (bits & mask, bits & !mask);
+        let others = !mask >> shift;
+        let along = match d {
+            0 => return bits,
+            1.. => along.wrapping_add(others + 1),
+            _ => (along | others).wrapping_sub(others + 1),
+        };
+        (along & (mask >> shift)) | across
+    };
+    let bits = step_along(bits, 0xaaaa_aaaa_aaaa_aaaa, dx);
+    step_along(bits, 0x5555_5555_5555_5555, dy)
+}
+
+/// Cell size, in bits per coordinate, small enough that a cell and its
+/// eight neighbors cover a circle of `radius` meters.
+fn estimate_step(radius: f64, latitude: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+    let (mut range, mut step) = (radius, 1i32);
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    // Cells shrink towards the poles, so use bigger ones there.
+    step -= 2;
+    if !(-66.0..=66.0).contains(&latitude) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&latitude) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP_MAX as i32) as u32
+}
+
+/// Unit a distance is given or reported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoUnit {
+    Meters,
+    Kilometers,
+    Feet,
+    Miles,
+}
+
+impl GeoUnit {
+    pub fn meters(self) -> f64 {
+        match self {
+            Self::Meters => 1.0,
+            Self::Kilometers => 1000.0,
+            Self::Feet => 0.3048,
+            Self::Miles => 1609.34,
+        }
+    }
+}
+
+/// Where GEOSEARCH searches from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoOrigin {
+    Member(String),
+    Position(f64, f64),
+}
+
+/// Area GEOSEARCH covers around its origin, in its unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    /// Width and height.
+    Box(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoOrder {
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoSearch {
+    pub origin: GeoOrigin,
+    pub shape: GeoShape,
+    pub unit: GeoUnit,
+    pub order: Option<GeoOrder>,
+    /// Most results to return; with `any` set, the first ones found rather
+    /// than the closest.
+    pub count: Option<usize>,
+    pub any: bool,
+}
+
+/// A member GEOSEARCH found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoMatch {
+    pub member: String,
+    /// Distance from the origin, in the search's unit.
+    pub distance: f64,
+    pub score: f64,
+    pub longitude: f64,
+    pub latitude: f64,
+}
+
+impl GeoSearch {
+    /// Half the width and height of the shape in meters; a circle's box is
+    /// the square around it.
+    fn half_extent(&self) -> (f64, f64) {
+        let meters = self.unit.meters();
+        match self.shape {
+            GeoShape::Radius(radius) => (radius * meters, radius * meters),
+            GeoShape::Box(width, height) => (width * meters / 2.0, height * meters / 2.0),
+        }
+    }
+
+    /// Distance in meters of a point from `(longitude, latitude)`, if it is
+    /// within the shape.
+    fn distance_within(&self, (longitude, latitude): (f64, f64), point: (f64, f64)) -> Option<f64> {
+        let meters = self.unit.meters();
+        match self.shape {
+            GeoShape::Radius(radius) => {
+                let distance = distance(longitude, latitude, point.0, point.1);
+                (distance <= radius * meters).then_some(distance)
+            }
+            GeoShape::Box(width, height) => {
+                // Latitude distance is the cheaper one, so it goes first.
+                let latitude_distance =
+                    EARTH_RADIUS_IN_METERS * (point.1.to_radians() - latitude.to_radians()).abs();
+                if latitude_distance > height * meters / 2.0 {
+                    return None;
+                }
+                let longitude_distance = distance(point.0, point.1, longitude, point.1);
+                if longitude_distance > width * meters / 2.0 {
+                    return None;
+                }
+                Some(distance(longitude, latitude, point.0, point.1))
+            }
+        }
+    }
+
+    /// Score ranges of the cells that may hold points within the shape
+    /// around `(longitude, latitude)`: the cell of the center and those of
+    /// its neighbors that reach into the shape's bounding box.
+    fn score_ranges(&self, (longitude, latitude): (f64, f64)) -> Vec<ScoreRange> {
+        let (half_width, half_height) = self.half_extent();
+        let radius = match self.shape {
+            GeoShape::Radius(_) => half_width,
+            GeoShape::Box(..) => half_width.hypot(half_height),
+        };
+
+        let latitude_delta = (half_height / EARTH_RADIUS_IN_METERS).to_degrees();
+        let longitude_delta = |latitude: f64| {
+            (half_width / EARTH_RADIUS_IN_METERS / latitude.to_radians().cos()).to_degrees()
+        };
+        // The box is widest on the side closer to the pole.
+        let longitude_delta = if latitude < 0.0 {
+            longitude_delta(latitude - latitude_delta)
+        } else {
+            longitude_delta(latitude + latitude_delta)
+        };
+        let (min_longitude, max_longitude) =
+            (longitude - longitude_delta, longitude + longitude_delta);
+        let (min_latitude, max_latitude) = (latitude - latitude_delta, latitude + latitude_delta);
+
+        let mut step = estimate_step(radius, latitude);
+        let mut center = encode_in(longitude, latitude, step, LATITUDE_RANGE);
+        // Near the edge of its cell the box can reach past the neighbors,
+        // in which case cells twice as big are needed.
+        let area_of = |center: u64, step: u32, dx: i8, dy: i8| {
+            decode_cell(neighbor(center, step, dx, dy), step)
+        };
+        if step > 1
+            && (area_of(center, step, 0, 1).latitude.1 < max_latitude
+                || area_of(center, step, 0, -1).latitude.0 > min_latitude
+                || area_of(center, step, 1, 0).longitude.1 < max_longitude
+                || area_of(center, step, -1, 0).longitude.0 > min_longitude)
+        {
+            step -= 1;
+            center = encode_in(longitude, latitude, step, LATITUDE_RANGE);
+        }
+
+        // Neighbors on a side the center cell already covers past the box
+        // hold nothing in it.
+        let area = decode_cell(center, step);
+        let skip_south = step >= 2 && area.latitude.0 < min_latitude;
+        let skip_north = step >= 2 && area.latitude.1 > max_latitude;
+        let skip_west = step >= 2 && area.longitude.0 < min_longitude;
+        let skip_east = step >= 2 && area.longitude.1 > max_longitude;
+
+        let shift = 52 - step * 2;
+        let mut ranges: Vec<ScoreRange> = Vec::with_capacity(9);
+        for dy in [0, 1, -1] {
+            for dx in [0, 1, -1] {
+                if (dy == 1 && skip_north)
+                    || (dy == -1 && skip_south)
+                    || (dx == 1 && skip_east)
+                    || (dx == -1 && skip_west)
+                {
+                    continue;
+                }
+                let cell = neighbor(center, step, dx, dy);
+                let range = ScoreRange {
+                    min: (cell << shift) as f64,
+                    max: ((cell + 1) << shift) as f64,
+                    min_exclusive: false,
+                    max_exclusive: true,
+                };
+                // Big cells wrap onto each other.
+                if !ranges.contains(&range) {
+                    ranges.push(range);
+                }
+            }
+        }
+        ranges
+    }
+
+    /// The members of `zset` within the search's shape.
+    fn run(&self, zset: &ZSetValue) -> anyhow::Result<Vec<GeoMatch>> {
+        let origin = match &self.origin {
+            GeoOrigin::Position(longitude, latitude) => (*longitude, *latitude),
+            GeoOrigin::Member(member) => match zset.score(member) {
+                Some(score) => decode(score),
+                None => bail!("could not decode requested zset member"),
+            },
+        };
+
+        let meters = self.unit.meters();
+        let mut matches = Vec::new();
+        'cells: for range in self.score_ranges(origin) {
+            for (member, score) in zset.range_within(&range, false, (0, -1)) {
+                let point = decode(score);
+                let Some(distance) = self.distance_within(origin, point) else {
+                    continue;
+                };
+                matches.push(GeoMatch {
+                    member,
+                    distance: distance / meters,
+                    score,
+                    longitude: point.0,
+                    latitude: point.1,
+                });
+                if self.any && self.count.is_some_and(|count| matches.len() >= count) {
+                    break 'cells;
+                }
+            }
+        }
+
+        // A limit without ANY keeps the closest matches.
+        let order = match self.order {
+            None if self.count.is_some() && !self.any => Some(GeoOrder::Ascending),
+            order => order,
+        };
+        match order {
+            Some(GeoOrder::Ascending) => {
+                matches.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+            }
+            Some(GeoOrder::Descending) => {
+                matches.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+            }
+            None => {}
+        }
+        if let Some(count) = self.count {
+            matches.truncate(count);
+        }
+        Ok(matches)
+    }
+}
+
+impl Store {
+    /// Adds members at the given `(longitude, latitude, member)` positions,
+    /// as ZADD with their geohash as the score.
+    pub fn geoadd(
+        &self,
+        key: &str,
+        positions: Vec<(f64, f64, String)>,
+        options: ZAddOptions,
+    ) -> anyhow::Result<usize> {
+        let pairs = positions
+            .into_iter()
+            .map(|(longitude, latitude, member)| (encode(longitude, latitude), member))
+            .collect();
+        Ok(self.zadd(key, pairs, options)?.0)
+    }
+
+    /// Longitude and latitude of each member, as stored.
+    pub fn geopos(&self, key: &str, members: &[String]) -> anyhow::Result<Vec<Option<(f64, f64)>>> {
+        Ok(self
+            .zmscore(key, members)?
+            .into_iter()
+            .map(|score| score.map(decode))
+            .collect())
+    }
+
+    /// Distance in meters between two members, if both exist.
+    pub fn geodist(&self, key: &str, from: &str, to: &str) -> anyhow::Result<Option<f64>> {
+        let (Some(from), Some(to)) = (self.zscore(key, from)?, self.zscore(key, to)?) else {
+            return Ok(None);
+        };
+        let ((longitude1, latitude1), (longitude2, latitude2)) = (decode(from), decode(to));
+        Ok(Some(distance(longitude1, latitude1, longitude2, latitude2)))
+    }
+
+    /// Standard geohash string of each member.
+    pub fn geohash(&self, key: &str, members: &[String]) -> anyhow::Result<Vec<Option<String>>> {
+        Ok(self
+            .zmscore(key, members)?
+            .into_iter()
+            .map(|score| score.map(geohash_string))
+            .collect())
+    }
+
+    pub fn geosearch(&self, key: &str, search: &GeoSearch) -> anyhow::Result<Vec<GeoMatch>> {
+        self.with_zset(key, |zset| search.run(zset))?
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    /// Stores the members GEOSEARCH finds in `source` into `destination`,
+    /// scored by their position or, with `store_distance`, their distance,
+    /// and returns how many there are.
+    pub fn geosearchstore(
+        &self,
+        destination: &str,
+        source: &str,
+        search: &GeoSearch,
+        store_distance: bool,
+    ) -> anyhow::Result<usize> {
+        let len = {
+            let _guard = self.lock_exclusive();
+            self.expire_if_needed(source);
+            let matches = match self
+                .entries
+                .get(source)
+                .as_deref()
+                .map(|entry| &entry.record)
+            {
+                None => Vec::new(),
+                Some(RecordType::ZSet(zset)) => search.run(zset)?,
+                Some(_) => bail!(WRONGTYPE),
+            };
+            let elements = matches
+                .into_iter()
+                .map(|found| {
+                    let score = if store_distance {
+                        found.distance
+                    } else {
+                        found.score
+                    };
+                    (found.member, score)
+                })
+                .collect();
+            self.store_zset(destination, elements, "geosearchstore")
+        };
+
+        if len > 0 {
+            self.serve_blocked(destination);
+        }
+        Ok(len)
+    }
+}
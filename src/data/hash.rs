@@ -0,0 +1,673 @@
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::bail;
+use dashmap::mapref::entry::Entry;
+
+use crate::data::{
+    Store,
+    dict::Dict,
+    notify::NotifyFlags,
+    record::{RecordData, RecordType, WRONGTYPE},
+    string::{SetCondition, SetExpiration, format_float, parse_canonical_i64},
+};
+use crate::{glob, random};
+
+/// Fields of a hash paired with their values.
+pub type FieldValues = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Value of a hash key: its fields, and when those that have a TTL expire.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HashValue {
+    fields: Dict<Vec<u8>, Vec<u8>>,
+    /// Expiration in unix milliseconds of each field that has one.
+    expirations: HashMap<Vec<u8>, u64>,
+    /// The same expirations ordered by time, so due fields are found without
+    /// looking at the others.
+    deadlines: BTreeSet<(u64, Vec<u8>)>,
+}
+
+impl HashValue {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Vec<u8>> {
+        self.fields.get(field)
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    /// Sets `field` to `value`, dropping any TTL it had, and returns the
+    /// value it replaced.
+    pub fn insert(&mut self, field: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        self.persist(&field);
+        self.fields.insert(field, value)
+    }
+
+    /// The value of `field`, inserting `default()` first if there is none.
+    /// An existing field keeps its TTL.
+    pub fn get_or_insert_with(
+        &mut self,
+        field: Vec<u8>,
+        default: impl FnOnce() -> Vec<u8>,
+    ) -> &mut Vec<u8> {
+        self.fields.get_or_insert_with(field, default)
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        self.persist(field);
+        self.fields.remove(field)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<u8>)> {
+        self.fields.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.fields.keys()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.fields.values()
+    }
+
+    pub fn random(&self) -> Option<(&Vec<u8>, &Vec<u8>)> {
+        self.fields.random()
+    }
+
+    pub fn scan(&self, cursor: u64, count: usize, visit: impl FnMut(&Vec<u8>, &Vec<u8>)) -> u64 {
+        self.fields.scan(cursor, count, visit)
+    }
+
+    fn expiration(&self, field: &[u8]) -> Option<u64> {
+        self.expirations.get(field).copied()
+    }
+
+    /// Makes `field`, which must exist, expire at `at`.
+    fn set_expiration(&mut self, field: &[u8], at: u64) {
+        self.persist(field);
+        self.expirations.insert(field.to_vec(), at);
+        self.deadlines.insert((at, field.to_vec()));
+    }
+
+    /// Drops the TTL of `field`, returning whether it had one.
+    fn persist(&mut self, field: &[u8]) -> bool {
+        let Some(at) = self.expirations.remove(field) else {
+            return false;
+        };
+        self.deadlines.remove(&(at, field.to_vec()));
+        true
+    }
+
+    /// Whether a field's TTL has passed at `now`.
+    pub fn has_expired_fields(&self, now: u64) -> bool {
+        self.deadlines.first().is_some_and(|&(at, _)| now > at)
+    }
+
+    /// Removes the fields whose TTL has passed at `now`, returning how many.
+    fn remove_expired(&mut self, now: u64) -> usize {
+        let mut removed = 0;
+        while let Some((at, _)) = self.deadlines.first()
+            && now > *at
+        {
+            let (_, field) = self.deadlines.pop_first().expect("checked above");
+            self.expirations.remove(&field);
+            self.fields.remove(&field);
+            removed += 1;
+        }
+        removed
+    }
+}
+
+/// `NX` / `XX` / `GT` / `LT` guard on changing a TTL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCondition {
+    /// Only if there is no TTL yet.
+    IfNone,
+    /// Only if there is a TTL already.
+    IfSome,
+    /// Only if the new TTL is later; no TTL counts as never expiring.
+    Greater,
+    /// Only if the new TTL is sooner; no TTL counts as never expiring.
+    Less,
+}
+
+impl ExpireCondition {
+    fn allows(self, current: Option<u64>, at: u64) -> bool {
+        match (self, current) {
+            (Self::IfNone, current) => current.is_none(),
+            (Self::IfSome, current) => current.is_some(),
+            (Self::Greater, Some(current)) => at > current,
+            (Self::Greater, None) => false,
+            (Self::Less, Some(current)) => at < current,
+            (Self::Less, None) => true,
+        }
+    }
+}
+
+/// Latest expiration a hash field can be given, in unix milliseconds, as
+/// Redis limits it.
+pub const MAX_FIELD_EXPIRATION_MS: i64 = (1 << 46) - 1;
+
+/// Reply for a field that doesn't exist in HEXPIRE, HTTL and friends.
+const NO_FIELD: i64 = -2;
+/// Reply for a field without a TTL in HTTL, HPERSIST and friends.
+const NO_TTL: i64 = -1;
+
+impl Store {
+    /// Runs `f` over the hash at `key`, or returns `None` if there is none.
+    fn with_hash<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&HashValue) -> T,
+    ) -> anyhow::Result<Option<T>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(None);
+        };
+        let RecordType::Hash(hash) = &entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        Ok(Some(f(hash)))
+    }
+
+    /// Runs `f` over the hash at `key` for writing, or over a new empty one
+    /// that is only stored if `f` adds to it.
+    fn with_hash_or_new<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut HashValue) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        self.expire_if_needed(key);
+        match self.entries.entry(key.to_string()) {
+            Entry::Occupied(mut entry) => {
+                let RecordType::Hash(hash) = &mut entry.get_mut().record else {
+                    bail!(WRONGTYPE);
+                };
+                f(hash)
+            }
+            Entry::Vacant(entry) => {
+                let mut hash = HashValue::default();
+                let result = f(&mut hash);
+                if !hash.is_empty() {
+                    entry.insert(RecordData::new(RecordType::Hash(hash), None));
+                }
+                result
+            }
+        }
+    }
+
+    /// Sets each field to its value, returning how many fields are new.
+    pub fn hset(&self, key: &str, pairs: FieldValues) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        let added = self.with_hash_or_new(key, |hash| {
+            Ok(pairs
+                .into_iter()
+                .map(|(field, value)| hash.insert(field, value))
+                .filter(Option::is_none)
+                .count())
+        })?;
+
+        self.notify_keyspace_event(NotifyFlags::HASH, "hset", key);
+        Ok(added)
+    }
+
+    /// Sets `field` only if the hash doesn't have it yet, returning whether
+    /// it did so.
+    pub fn hsetnx(&self, key: &str, field: Vec<u8>, value: Vec<u8>) -> anyhow::Result<bool> {
+        let _guard = self.lock_shared();
+        let set = self.with_hash_or_new(key, |hash| {
+            if hash.contains_key(&field) {
+                return Ok(false);
+            }
+            hash.insert(field, value);
+            Ok(true)
+        })?;
+
+        if set {
+            self.notify_keyspace_event(NotifyFlags::HASH, "hset", key);
+        }
+        Ok(set)
+    }
+
+    pub fn hget(&self, key: &str, field: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .with_hash(key, |hash| hash.get(field).cloned())?
+            .flatten())
+    }
+
+    pub fn hmget(&self, key: &str, fields: &[Vec<u8>]) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+        Ok(self
+            .with_hash(key, |hash| {
+                fields
+                    .iter()
+                    .map(|field| hash.get(field).cloned())
+                    .collect()
+            })?
+            .unwrap_or_else(|| vec![None; fields.len()]))
+    }
+
+    /// Removes `fields`, returning how many existed.
+    pub fn hdel(&self, key: &str, fields: &[Vec<u8>]) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+        let removed = match self.entries.get_mut(key) {
+            None => 0,
+            Some(mut entry) => {
+                let RecordType::Hash(hash) = &mut entry.record else {
+                    bail!(WRONGTYPE);
+                };
+                fields
+                    .iter()
+                    .filter(|field| hash.remove(field).is_some())
+                    .count()
+            }
+        };
+
+        if removed > 0 {
+            self.notify_keyspace_event(NotifyFlags::HASH, "hdel", key);
+            self.delete_if_empty(key);
+        }
+        Ok(removed)
+    }
+
+    pub fn hgetall(&self, key: &str) -> anyhow::Result<FieldValues> {
+        Ok(self
+            .with_hash(key, |hash| {
+                hash.iter()
+                    .map(|(field, value)| (field.clone(), value.clone()))
+                    .collect()
+            })?
+            .unwrap_or_default())
+    }
+
+    pub fn hexists(&self, key: &str, field: &[u8]) -> anyhow::Result<bool> {
+        Ok(self
+            .with_hash(key, |hash| hash.contains_key(field))?
+            .unwrap_or(false))
+    }
+
+    pub fn hlen(&self, key: &str) -> anyhow::Result<usize> {
+        Ok(self.with_hash(key, HashValue::len)?.unwrap_or(0))
+    }
+
+    pub fn hkeys(&self, key: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+        Ok(self
+            .with_hash(key, |hash| hash.keys().cloned().collect())?
+            .unwrap_or_default())
+    }
+
+    pub fn hvals(&self, key: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+        Ok(self
+            .with_hash(key, |hash| hash.values().cloned().collect())?
+            .unwrap_or_default())
+    }
+
+    pub fn hstrlen(&self, key: &str, field: &[u8]) -> anyhow::Result<usize> {
+        Ok(self
+            .with_hash(key, |hash| hash.get(field).map_or(0, Vec::len))?
+            .unwrap_or(0))
+    }
+
+    /// Adds `delta` to the integer in `field`, starting from 0.
+    pub fn hincrby(&self, key: &str, field: Vec<u8>, delta: i64) -> anyhow::Result<i64> {
+        let _guard = self.lock_shared();
+        let value = self.with_hash_or_new(key, |hash| {
+            let current = hash.get_or_insert_with(field, || b"0".to_vec());
+            let Some(number) = parse_canonical_i64(current) else {
+                bail!("hash value is not an integer");
+            };
+            let Some(value) = number.checked_add(delta) else {
+                bail!("increment or decrement would overflow");
+            };
+            *current = value.to_string().into_bytes();
+            Ok(value)
+        })?;
+
+        self.notify_keyspace_event(NotifyFlags::HASH, "hincrby", key);
+        Ok(value)
+    }
+
+    /// Adds `delta` to the number in `field`, starting from 0, returning the
+    /// new value formatted the way it is stored.
+    pub fn hincrbyfloat(&self, key: &str, field: Vec<u8>, delta: f64) -> anyhow::Result<String> {
+        let _guard = self.lock_shared();
+        let value = self.with_hash_or_new(key, |hash| {
+            let number = match hash.get(&field) {
+                None => 0.0,
+                Some(current) => match std::str::from_utf8(current)
+                    .ok()
+                    .and_then(|current| current.parse::<f64>().ok())
+                    .filter(|n| !n.is_nan())
+                {
+                    Some(number) => number,
+                    None => bail!("hash value is not a float"),
+                },
+            };
+            let value = number + delta;
+            if !value.is_finite() {
+                bail!("increment would produce NaN or Infinity");
+            }
+            let formatted = format_float(value);
+            *hash.get_or_insert_with(field, Vec::new) = formatted.clone().into_bytes();
+            Ok(formatted)
+        })?;
+
+        self.notify_keyspace_event(NotifyFlags::HASH, "hincrbyfloat", key);
+        Ok(value)
+    }
+
+    /// Random fields with their values, or `None` if there is no hash. A
+    /// positive `count` returns that many distinct fields, or all of them if
+    /// the hash is smaller; a negative one returns `-count` fields that may
+    /// repeat.
+    pub fn hrandfield(&self, key: &str, count: i64) -> anyhow::Result<Option<FieldValues>> {
+        self.with_hash(key, |hash| {
+            let pair = |(field, value): (&Vec<u8>, &Vec<u8>)| (field.clone(), value.clone());
+            if count < 0 {
+                return (0..count.unsigned_abs())
+                    .filter_map(|_| hash.random().map(pair))
+                    .collect();
+            }
+
+            let count = count as usize;
+            let mut pairs: Vec<_> = hash.iter().map(pair).collect();
+            if count >= pairs.len() {
+                return pairs;
+            }
+            // Partial Fisher-Yates: the first `count` slots end up a uniform
+            // sample of the hash.
+            for i in 0..count {
+                let j = i + random::below(pairs.len() - i);
+                pairs.swap(i, j);
+            }
+            pairs.truncate(count);
+            pairs
+        })
+    }
+
+    /// One step of HSCAN: the fields matching `pattern` from about `count`
+    /// buckets starting at `cursor`, and the cursor to continue from.
+    pub fn hscan(
+        &self,
+        key: &str,
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: usize,
+    ) -> anyhow::Result<(u64, FieldValues)> {
+        Ok(self
+            .with_hash(key, |hash| {
+                let mut pairs = Vec::new();
+                let cursor = hash.scan(cursor, count, |field, value| {
+                    if pattern.is_none_or(|pattern| glob::matches_bytes(pattern, field)) {
+                        pairs.push((field.clone(), value.clone()));
+                    }
+                });
+                (cursor, pairs)
+            })?
+            .unwrap_or_default())
+    }
+
+    /// Removes the fields of the hash at `key` whose TTL has passed, firing
+    /// `hexpired`, and the key itself if that leaves it empty.
+    pub(super) fn expire_hash_fields(&self, key: &str, now: u64) {
+        if !self
+            .entries
+            .get(key)
+            .is_some_and(|entry| entry.record.has_expired_fields(now))
+        {
+            return;
+        }
+
+        let removed = match self
+            .entries
+            .get_mut(key)
+            .as_deref_mut()
+            .map(|entry| &mut entry.record)
+        {
+            Some(RecordType::Hash(hash)) => hash.remove_expired(now),
+            _ => 0,
+        };
+        if removed > 0 {
+            self.notify_keyspace_event(NotifyFlags::HASH, "hexpired", key);
+            self.delete_if_empty(key);
+        }
+    }
+
+    /// Makes `fields` expire at `at`, unix milliseconds. Each field gets 1 if
+    /// its TTL was set, 2 if it was deleted because `at` has passed, 0 if
+    /// `condition` didn't hold, or -2 if it doesn't exist.
+    pub fn hexpire(
+        &self,
+        key: &str,
+        at: u64,
+        condition: Option<ExpireCondition>,
+        fields: &[Vec<u8>],
+    ) -> anyhow::Result<Vec<i64>> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+        let now = self.clock().now_ms();
+        let replies = match self.entries.get_mut(key) {
+            None => vec![NO_FIELD; fields.len()],
+            Some(mut entry) => {
+                let RecordType::Hash(hash) = &mut entry.record else {
+                    bail!(WRONGTYPE);
+                };
+                fields
+                    .iter()
+                    .map(|field| {
+                        if !hash.contains_key(field) {
+                            NO_FIELD
+                        } else if !condition
+                            .is_none_or(|condition| condition.allows(hash.expiration(field), at))
+                        {
+                            0
+                        } else if at <= now {
+                            hash.remove(field);
+                            2
+                        } else {
+                            hash.set_expiration(field, at);
+                            self.track_deadline(key, at);
+                            1
+                        }
+                    })
+                    .collect()
+            }
+        };
+
+        if replies.contains(&1) {
+            self.notify_keyspace_event(NotifyFlags::HASH, "hexpire", key);
+        }
+        if replies.contains(&2) {
+            self.notify_keyspace_event(NotifyFlags::HASH, "hdel", key);
+            self.delete_if_empty(key);
+        }
+        Ok(replies)
+    }
+
+    /// When each of `fields` expires, in unix milliseconds, or -1 if it has
+    /// no TTL and -2 if it doesn't exist.
+    pub fn hexpiretime(&self, key: &str, fields: &[Vec<u8>]) -> anyhow::Result<Vec<i64>> {
+        Ok(self
+            .with_hash(key, |hash| {
+                fields
+                    .iter()
+                    .map(|field| match hash.expiration(field) {
+                        Some(at) => at as i64,
+                        None if hash.contains_key(field) => NO_TTL,
+                        None => NO_FIELD,
+                    })
+                    .collect()
+            })?
+            .unwrap_or_else(|| vec![NO_FIELD; fields.len()]))
+    }
+
+    /// Drops the TTL of `fields`. Each field gets 1 if it had one, -1 if it
+    /// didn't, or -2 if it doesn't exist.
+    pub fn hpersist(&self, key: &str, fields: &[Vec<u8>]) -> anyhow::Result<Vec<i64>> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+        let replies = match self.entries.get_mut(key) {
+            None => vec![NO_FIELD; fields.len()],
+            Some(mut entry) => {
+                let RecordType::Hash(hash) = &mut entry.record else {
+                    bail!(WRONGTYPE);
+                };
+                fields
+                    .iter()
+                    .map(|field| {
+                        if !hash.contains_key(field) {
+                            NO_FIELD
+                        } else if hash.persist(field) {
+                            1
+                        } else {
+                            NO_TTL
+                        }
+                    })
+                    .collect()
+            }
+        };
+
+        if replies.contains(&1) {
+            self.notify_keyspace_event(NotifyFlags::HASH, "hpersist", key);
+        }
+        Ok(replies)
+    }
+
+    /// Reads `fields` and updates the TTL of those that exist;
+    /// `SetExpiration::Keep` leaves it untouched, `SetExpiration::Clear`
+    /// persists them, and a time that has passed deletes them.
+    pub fn hgetex(
+        &self,
+        key: &str,
+        fields: &[Vec<u8>],
+        expiration: SetExpiration,
+    ) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+        let now = self.clock().now_ms();
+        let Some(mut entry) = self.entries.get_mut(key) else {
+            return Ok(vec![None; fields.len()]);
+        };
+        let RecordType::Hash(hash) = &mut entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        let values: Vec<_> = fields
+            .iter()
+            .map(|field| hash.get(field).cloned())
+            .collect();
+        let existing: Vec<_> = fields
+            .iter()
+            .filter(|field| hash.contains_key(field))
+            .collect();
+        let event = match expiration {
+            SetExpiration::Keep => None,
+            SetExpiration::Clear => existing
+                .into_iter()
+                .fold(false, |persisted, field| hash.persist(field) | persisted)
+                .then_some("hpersist"),
+            SetExpiration::At(_) if existing.is_empty() => None,
+            SetExpiration::At(at) if at <= now => {
+                existing.into_iter().for_each(|field| {
+                    hash.remove(field);
+                });
+                Some("hdel")
+            }
+            SetExpiration::At(at) => {
+                existing
+                    .into_iter()
+                    .for_each(|field| hash.set_expiration(field, at));
+                self.track_deadline(key, at);
+                Some("hexpire")
+            }
+        };
+        drop(entry);
+
+        if let Some(event) = event {
+            self.notify_keyspace_event(NotifyFlags::HASH, event, key);
+            self.delete_if_empty(key);
+        }
+        Ok(values)
+    }
+
+    /// Sets each field to its value with the TTL given by `expiration`,
+    /// unless `condition` doesn't hold for all of them. Returns whether the
+    /// fields were set.
+    pub fn hsetex(
+        &self,
+        key: &str,
+        pairs: FieldValues,
+        condition: Option<SetCondition>,
+        expiration: SetExpiration,
+    ) -> anyhow::Result<bool> {
+        let _guard = self.lock_shared();
+        let now = self.clock().now_ms();
+        // The condition is checked under the same entry guard as the write,
+        // so no other client can add or remove the fields in between.
+        let events = self.with_hash_or_new(key, |hash| {
+            let applies = match condition {
+                None => true,
+                Some(SetCondition::IfMissing) => {
+                    pairs.iter().all(|(field, _)| !hash.contains_key(field))
+                }
+                Some(SetCondition::IfExists) => {
+                    pairs.iter().all(|(field, _)| hash.contains_key(field))
+                }
+            };
+            if !applies {
+                return Ok(None);
+            }
+
+            if let SetExpiration::At(at) = expiration
+                && at <= now
+            {
+                // Fields set with a TTL that has already passed are gone at
+                // once.
+                let removed = pairs
+                    .iter()
+                    .filter(|(field, _)| hash.remove(field).is_some())
+                    .count();
+                return Ok(Some(if removed > 0 { &["hdel"][..] } else { &[] }));
+            }
+
+            for (field, value) in pairs {
+                match expiration {
+                    SetExpiration::Keep => {
+                        *hash.get_or_insert_with(field, Vec::new) = value;
+                    }
+                    SetExpiration::Clear => {
+                        hash.insert(field, value);
+                    }
+                    SetExpiration::At(at) => {
+                        hash.insert(field.clone(), value);
+                        hash.set_expiration(&field, at);
+                        self.track_deadline(key, at);
+                    }
+                }
+            }
+            Ok(Some(match expiration {
+                SetExpiration::At(_) => &["hset", "hexpire"][..],
+                _ => &["hset"],
+            }))
+        })?;
+        let Some(events) = events else {
+            return Ok(false);
+        };
+
+        for event in events {
+            self.notify_keyspace_event(NotifyFlags::HASH, event, key);
+        }
+        if events.contains(&"hdel") {
+            self.delete_if_empty(key);
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,653 @@
+//! HyperLogLog stored in plain strings, using the same layout as Redis so
+//! values can be moved between the two with GET and SET.
+//!
+//! A value starts with a 16-byte header: the `HYLL` magic, the encoding,
+//! three unused bytes and the cached cardinality as a little-endian integer
+//! whose most significant bit marks it stale. The 16384 6-bit registers
+//! follow, either packed (dense) or run-length encoded (sparse).
+
+use anyhow::bail;
+
+use crate::data::{
+    Store,
+    notify::NotifyFlags,
+    record::{RecordData, RecordType, WRONGTYPE},
+    string::StringValue,
+};
+
+const HLL_P: u32 = 14;
+const HLL_Q: u32 = 64 - HLL_P;
+const HLL_REGISTERS: usize = 1 << HLL_P;
+const HLL_BITS: usize = 6;
+const HLL_HDR_SIZE: usize = 16;
+const HLL_DENSE_SIZE: usize = HLL_HDR_SIZE + (HLL_REGISTERS * HLL_BITS).div_ceil(8);
+const HLL_DENSE: u8 = 0;
+const HLL_SPARSE: u8 = 1;
+const HLL_ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+const HLL_HASH_SEED: u64 = 0xadc83b19;
+
+const SPARSE_VAL_MAX_VALUE: u8 = 32;
+const SPARSE_VAL_MAX_LEN: usize = 4;
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_XZERO_MAX_LEN: usize = 16384;
+
+const NOT_HLL: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+const CORRUPTED: &str = "INVALIDOBJ Corrupted HLL object detected";
+
+/// One run of registers in the sparse encoding.
+#[derive(Debug, Clone, Copy)]
+enum Opcode {
+    /// `00xxxxxx`: up to 64 zero registers.
+    Zero(usize),
+    /// `01xxxxxx yyyyyyyy`: up to 16384 zero registers.
+    XZero(usize),
+    /// `1vvvvvxx`: up to 4 registers holding a value of up to 32.
+    Val(u8, usize),
+}
+
+impl Opcode {
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let first = *bytes.first()?;
+        Some(if first & 0x80 != 0 {
+            Self::Val((first >> 2 & 0x1f) + 1, (first & 0x03) as usize + 1)
+        } else if first & 0x40 != 0 {
+            Self::XZero((((first & 0x3f) as usize) << 8 | *bytes.get(1)? as usize) + 1)
+        } else {
+            Self::Zero(first as usize + 1)
+        })
+    }
+
+    /// The shortest opcode for a run of `len` zero registers.
+    fn zeros(len: usize) -> Self {
+        if len > SPARSE_ZERO_MAX_LEN {
+            Self::XZero(len)
+        } else {
+            Self::Zero(len)
+        }
+    }
+
+    fn run(self) -> (u8, usize) {
+        match self {
+            Self::Zero(len) | Self::XZero(len) => (0, len),
+            Self::Val(value, len) => (value, len),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::XZero(_) => 2,
+            _ => 1,
+        }
+    }
+
+    fn encode(self, out: &mut Vec<u8>) {
+        match self {
+            Self::Zero(len) => out.push((len - 1) as u8),
+            Self::XZero(len) => out.extend([((len - 1) >> 8) as u8 | 0x40, (len - 1) as u8]),
+            Self::Val(value, len) => out.push(0x80 | (value - 1) << 2 | (len - 1) as u8),
+        }
+    }
+}
+
+/// MurmurHash64A, the hash Redis feeds elements through.
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// The register `element` maps to and the value it proposes for it: the
+/// length of the run of zeros in the rest of the hash, plus one.
+fn pattern_len(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, HLL_HASH_SEED);
+    let index = hash as usize & (HLL_REGISTERS - 1);
+    let rest = hash >> HLL_P | 1 << HLL_Q;
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+fn new_hll() -> Vec<u8> {
+    let mut hll = Vec::with_capacity(HLL_HDR_SIZE + 2);
+    hll.extend_from_slice(b"HYLL");
+    hll.push(HLL_SPARSE);
+    hll.resize(HLL_HDR_SIZE, 0);
+    Opcode::XZero(SPARSE_XZERO_MAX_LEN).encode(&mut hll);
+    hll
+}
+
+fn is_valid_hll(bytes: &[u8]) -> bool {
+    bytes.len() >= HLL_HDR_SIZE
+        && bytes.starts_with(b"HYLL")
+        && match bytes[4] {
+            HLL_DENSE => bytes.len() == HLL_DENSE_SIZE,
+            HLL_SPARSE => true,
+            _ => false,
+        }
+}
+
+fn cached_cardinality(hll: &[u8]) -> Option<u64> {
+    let card: [u8; 8] = hll[8..HLL_HDR_SIZE].try_into().ok()?;
+    (card[7] & 0x80 == 0).then(|| u64::from_le_bytes(card))
+}
+
+fn invalidate_cache(hll: &mut [u8]) {
+    hll[HLL_HDR_SIZE - 1] |= 0x80;
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let (byte, shift) = (index * HLL_BITS / 8, index * HLL_BITS % 8);
+    let next = registers.get(byte + 1).copied().unwrap_or(0);
+    ((registers[byte] as u16 | (next as u16) << 8) >> shift) as u8 & 0x3f
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let (byte, shift) = (index * HLL_BITS / 8, index * HLL_BITS % 8);
+    let (mask, bits) = (0x3f_u16 << shift, (value as u16) << shift);
+    registers[byte] = registers[byte] & !(mask as u8) | bits as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next = *next & !((mask >> 8) as u8) | (bits >> 8) as u8;
+    }
+}
+
+/// Calls `f(first, value, len)` for every run of sparse registers, failing
+/// unless the runs cover exactly all registers.
+fn sparse_runs(sparse: &[u8], mut f: impl FnMut(usize, u8, usize)) -> anyhow::Result<()> {
+    let (mut p, mut index) = (0, 0);
+    while p < sparse.len() {
+        let Some(opcode) = Opcode::decode(&sparse[p..]) else {
+            bail!(CORRUPTED);
+        };
+        let (value, len) = opcode.run();
+        if index + len > HLL_REGISTERS {
+            bail!(CORRUPTED);
+        }
+        f(index, value, len);
+        index += len;
+        p += opcode.size();
+    }
+
+    if index != HLL_REGISTERS {
+        bail!(CORRUPTED);
+    }
+    Ok(())
+}
+
+fn to_dense(hll: &mut Vec<u8>) -> anyhow::Result<()> {
+    if hll[4] == HLL_DENSE {
+        return Ok(());
+    }
+
+    let mut dense = vec![0; HLL_DENSE_SIZE];
+    dense[..HLL_HDR_SIZE].copy_from_slice(&hll[..HLL_HDR_SIZE]);
+    dense[4] = HLL_DENSE;
+    let registers = &mut dense[HLL_HDR_SIZE..];
+    sparse_runs(&hll[HLL_HDR_SIZE..], |first, value, len| {
+        if value != 0 {
+            for index in first..first + len {
+                dense_set(registers, index, value);
+            }
+        }
+    })?;
+
+    *hll = dense;
+    Ok(())
+}
+
+/// Raises register `index` of a sparse HyperLogLog to `count`, splitting the
+/// run that covers it and merging equal neighbours afterwards. Switches to
+/// the dense encoding when the value no longer fits in a sparse opcode or
+/// the string would grow past `max_bytes`.
+fn sparse_set(
+    hll: &mut Vec<u8>,
+    index: usize,
+    count: u8,
+    max_bytes: usize,
+) -> anyhow::Result<bool> {
+    if count > SPARSE_VAL_MAX_VALUE {
+        return promote_and_set(hll, index, count);
+    }
+
+    let (mut p, mut prev, mut first) = (HLL_HDR_SIZE, None, 0);
+    let opcode = loop {
+        let Some(opcode) = Opcode::decode(&hll[p..]) else {
+            bail!(CORRUPTED);
+        };
+        if index < first + opcode.run().1 {
+            break opcode;
+        }
+        prev = Some(p);
+        p += opcode.size();
+        first += opcode.run().1;
+    };
+
+    match opcode {
+        Opcode::Val(value, _) if value >= count => return Ok(false),
+        Opcode::Val(_, 1) | Opcode::Zero(1) => {
+            let mut seq = Vec::with_capacity(1);
+            Opcode::Val(count, 1).encode(&mut seq);
+            hll.splice(p..p + 1, seq);
+        }
+        _ => {
+            let (value, len) = opcode.run();
+            let last = first + len - 1;
+            let run = |len| {
+                if value == 0 {
+                    Opcode::zeros(len)
+                } else {
+                    Opcode::Val(value, len)
+                }
+            };
+
+            let mut seq = Vec::with_capacity(5);
+            if index != first {
+                run(index - first).encode(&mut seq);
+            }
+            Opcode::Val(count, 1).encode(&mut seq);
+            if index != last {
+                run(last - index).encode(&mut seq);
+            }
+
+            if seq.len() > opcode.size() && hll.len() + seq.len() - opcode.size() > max_bytes {
+                return promote_and_set(hll, index, count);
+            }
+            hll.splice(p..p + opcode.size(), seq);
+        }
+    }
+
+    let mut p = prev.unwrap_or(HLL_HDR_SIZE);
+    for _ in 0..5 {
+        if p >= hll.len() {
+            break;
+        }
+        match Opcode::decode(&hll[p..]) {
+            Some(Opcode::Val(value, len)) => {
+                if let Some(Opcode::Val(next_value, next_len)) =
+                    hll.get(p + 1).and_then(|_| Opcode::decode(&hll[p + 1..]))
+                    && next_value == value
+                    && len + next_len <= SPARSE_VAL_MAX_LEN
+                {
+                    let mut merged = Vec::with_capacity(1);
+                    Opcode::Val(value, len + next_len).encode(&mut merged);
+                    hll.splice(p..p + 2, merged);
+                    continue;
+                }
+                p += 1;
+            }
+            Some(opcode) => p += opcode.size(),
+            None => break,
+        }
+    }
+
+    invalidate_cache(hll);
+    Ok(true)
+}
+
+fn promote_and_set(hll: &mut Vec<u8>, index: usize, count: u8) -> anyhow::Result<bool> {
+    to_dense(hll)?;
+    let registers = &mut hll[HLL_HDR_SIZE..];
+    if dense_get(registers, index) >= count {
+        return Ok(false);
+    }
+    dense_set(registers, index, count);
+    Ok(true)
+}
+
+/// Raises register `index` to `count` if it is lower, returning whether the
+/// register changed.
+fn set_register(
+    hll: &mut Vec<u8>,
+    index: usize,
+    count: u8,
+    max_bytes: usize,
+) -> anyhow::Result<bool> {
+    match hll[4] {
+        HLL_SPARSE => sparse_set(hll, index, count, max_bytes),
+        _ => {
+            let registers = &mut hll[HLL_HDR_SIZE..];
+            if dense_get(registers, index) >= count {
+                return Ok(false);
+            }
+            dense_set(registers, index, count);
+            Ok(true)
+        }
+    }
+}
+
+/// Folds the registers of `hll` into `max`, keeping the highest of each.
+fn merge_into(max: &mut [u8], hll: &[u8]) -> anyhow::Result<()> {
+    let registers = &hll[HLL_HDR_SIZE..];
+    match hll[4] {
+        HLL_SPARSE => sparse_runs(registers, |first, value, len| {
+            for register in &mut max[first..first + len] {
+                *register = (*register).max(value);
+            }
+        }),
+        _ => {
+            for (index, register) in max.iter_mut().enumerate() {
+                *register = (*register).max(dense_get(registers, index));
+            }
+            Ok(())
+        }
+    }
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// Cardinality estimate from a histogram of register values, using Otmar
+/// Ertl's improved estimator as Redis does.
+fn estimate(histogram: &[u32; 64]) -> u64 {
+    let m = HLL_REGISTERS as f64;
+    let mut z = m * tau((m - histogram[HLL_Q as usize + 1] as f64) / m);
+    for &n in histogram[1..=HLL_Q as usize].iter().rev() {
+        z += n as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (HLL_ALPHA_INF * m * m / z).round() as u64
+}
+
+fn count(hll: &[u8]) -> anyhow::Result<u64> {
+    let mut histogram = [0; 64];
+    let registers = &hll[HLL_HDR_SIZE..];
+    match hll[4] {
+        HLL_SPARSE => sparse_runs(registers, |_, value, len| {
+            histogram[value as usize] += len as u32;
+        })?,
+        _ => {
+            for index in 0..HLL_REGISTERS {
+                histogram[dense_get(registers, index) as usize] += 1;
+            }
+        }
+    }
+    Ok(estimate(&histogram))
+}
+
+/// The bytes of `record` if it holds a HyperLogLog.
+fn hll_mut(record: &mut RecordType) -> anyhow::Result<&mut Vec<u8>> {
+    let RecordType::String(value) = record else {
+        bail!(WRONGTYPE);
+    };
+    if !is_valid_hll(&value.to_bytes()) {
+        bail!(NOT_HLL);
+    }
+    Ok(value.bytes_mut())
+}
+
+impl Store {
+    /// Adds `elements` to the HyperLogLog at `key`, creating it if needed,
+    /// and returns whether any register changed.
+    pub fn pfadd(&self, key: String, elements: &[Vec<u8>]) -> anyhow::Result<bool> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(&key);
+
+        let mut created = false;
+        let mut entry = self.entries.entry(key.clone()).or_insert_with(|| {
+            created = true;
+            RecordData::new(RecordType::String(StringValue::Raw(new_hll())), None)
+        });
+        let hll = hll_mut(&mut entry.record)?;
+
+        let max_bytes = self.config().hll_sparse_max_bytes();
+        let mut updated = created;
+        for element in elements {
+            let (index, count) = pattern_len(element);
+            updated |= set_register(hll, index, count, max_bytes)?;
+        }
+        if updated {
+            invalidate_cache(hll);
+        }
+        drop(entry);
+
+        if updated {
+            self.notify_keyspace_event(NotifyFlags::STRING, "pfadd", &key);
+        }
+        Ok(updated)
+    }
+
+    /// Estimated cardinality of the union of the HyperLogLogs at `keys`.
+    /// A single key's estimate is cached in its header.
+    pub fn pfcount(&self, keys: &[String]) -> anyhow::Result<u64> {
+        let _guard = self.lock_shared();
+
+        if let [key] = keys {
+            self.expire_if_needed(key);
+            let Some(mut entry) = self.entries.get_mut(key) else {
+                return Ok(0);
+            };
+            let hll = hll_mut(&mut entry.record)?;
+            if let Some(cardinality) = cached_cardinality(hll) {
+                return Ok(cardinality);
+            }
+
+            let cardinality = count(hll)?;
+            hll[8..HLL_HDR_SIZE].copy_from_slice(&cardinality.to_le_bytes());
+            return Ok(cardinality);
+        }
+
+        let mut max = vec![0; HLL_REGISTERS];
+        for key in keys {
+            self.expire_if_needed(key);
+            let Some(mut entry) = self.entries.get_mut(key) else {
+                continue;
+            };
+            merge_into(&mut max, hll_mut(&mut entry.record)?)?;
+        }
+
+        let mut histogram = [0; 64];
+        for &register in &max {
+            histogram[register as usize] += 1;
+        }
+        Ok(estimate(&histogram))
+    }
+
+    /// Stores in `destination` the union of itself and the HyperLogLogs at
+    /// `keys`. The result is dense if any input was.
+    pub fn pfmerge(&self, destination: String, keys: &[String]) -> anyhow::Result<()> {
+        let _guard = self.lock_exclusive();
+
+        let mut max = vec![0; HLL_REGISTERS];
+        let mut use_dense = false;
+        for key in std::iter::once(&destination).chain(keys) {
+            self.expire_if_needed(key);
+            let Some(mut entry) = self.entries.get_mut(key) else {
+                continue;
+            };
+            let hll = hll_mut(&mut entry.record)?;
+            use_dense |= hll[4] == HLL_DENSE;
+            merge_into(&mut max, hll)?;
+        }
+
+        let mut entry = self.entries.entry(destination.clone()).or_insert_with(|| {
+            RecordData::new(RecordType::String(StringValue::Raw(new_hll())), None)
+        });
+        let hll = hll_mut(&mut entry.record)?;
+        if use_dense {
+            to_dense(hll)?;
+        }
+
+        let max_bytes = self.config().hll_sparse_max_bytes();
+        for (index, &count) in max.iter().enumerate() {
+            if count != 0 {
+                set_register(hll, index, count, max_bytes)?;
+            }
+        }
+        invalidate_cache(hll);
+        drop(entry);
+
+        self.notify_keyspace_event(NotifyFlags::STRING, "pfadd", &destination);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    /// Every register of `hll`, whichever its encoding.
+    fn registers(hll: &[u8]) -> Vec<u8> {
+        let mut registers = vec![0; HLL_REGISTERS];
+        match hll[4] {
+            HLL_SPARSE => sparse_runs(&hll[HLL_HDR_SIZE..], |first, value, len| {
+                registers[first..first + len].fill(value);
+            })
+            .unwrap(),
+            _ => {
+                for (index, register) in registers.iter_mut().enumerate() {
+                    *register = dense_get(&hll[HLL_HDR_SIZE..], index);
+                }
+            }
+        }
+        registers
+    }
+
+    fn add(hll: &mut Vec<u8>, model: &mut [u8], element: &[u8], max_bytes: usize) {
+        let (index, count) = pattern_len(element);
+        let changed = set_register(hll, index, count, max_bytes).unwrap();
+        assert_eq!(changed, count > model[index]);
+        model[index] = model[index].max(count);
+    }
+
+    #[test]
+    fn sparse_registers_match_a_plain_array() {
+        let mut hll = new_hll();
+        let mut model = vec![0; HLL_REGISTERS];
+        for i in 0..3000 {
+            add(
+                &mut hll,
+                &mut model,
+                format!("element:{i}").as_bytes(),
+                usize::MAX,
+            );
+            if i % 100 == 0 {
+                assert_eq!(hll[4], HLL_SPARSE);
+                assert_eq!(registers(&hll), model);
+            }
+        }
+        // Values past what a sparse opcode holds force the dense encoding.
+        for (index, count) in [(0, 33), (HLL_REGISTERS - 1, 40)] {
+            assert!(set_register(&mut hll, index, count, usize::MAX).unwrap());
+            model[index] = count;
+        }
+        assert_eq!(hll[4], HLL_DENSE);
+        assert_eq!(registers(&hll), model);
+    }
+
+    #[test]
+    fn promotion_to_dense_keeps_registers_and_count() {
+        let mut hll = new_hll();
+        let mut model = vec![0; HLL_REGISTERS];
+        for _ in 0..2000 {
+            let element = random::below(usize::MAX).to_le_bytes();
+            add(&mut hll, &mut model, &element, usize::MAX);
+        }
+        let sparse = hll.clone();
+
+        to_dense(&mut hll).unwrap();
+        assert_eq!(hll.len(), HLL_DENSE_SIZE);
+        assert_eq!(registers(&hll), model);
+        assert_eq!(count(&hll).unwrap(), count(&sparse).unwrap());
+
+        // Merging either encoding gives the same registers.
+        let (mut from_sparse, mut from_dense) = (vec![0; HLL_REGISTERS], vec![0; HLL_REGISTERS]);
+        merge_into(&mut from_sparse, &sparse).unwrap();
+        merge_into(&mut from_dense, &hll).unwrap();
+        assert_eq!(from_sparse, model);
+        assert_eq!(from_dense, model);
+    }
+
+    #[test]
+    fn outgrowing_the_sparse_limit_switches_to_dense() {
+        let mut hll = new_hll();
+        let mut model = vec![0; HLL_REGISTERS];
+        for i in 0..5000 {
+            add(&mut hll, &mut model, &(i as u64).to_le_bytes(), 3000);
+            assert!(hll[4] == HLL_DENSE || hll.len() <= 3000);
+        }
+        assert_eq!(hll[4], HLL_DENSE);
+        assert_eq!(registers(&hll), model);
+    }
+
+    #[test]
+    fn dense_registers_leave_their_neighbours_alone() {
+        let mut registers = vec![0; HLL_DENSE_SIZE - HLL_HDR_SIZE];
+        let mut model = vec![0; HLL_REGISTERS];
+        for _ in 0..20000 {
+            let (index, value) = (random::below(HLL_REGISTERS), random::below(64) as u8);
+            dense_set(&mut registers, index, value);
+            model[index] = value;
+        }
+        for (index, &value) in model.iter().enumerate() {
+            assert_eq!(dense_get(&registers, index), value);
+        }
+    }
+
+    #[test]
+    fn counts_within_the_standard_error() {
+        let mut hll = new_hll();
+        let mut model = vec![0; HLL_REGISTERS];
+        for added in 0..=100_000 {
+            if added > 0 && added % 25_000 == 0 {
+                let estimate = count(&hll).unwrap() as f64;
+                // The standard error is 0.81%; five of them is generous.
+                assert!((estimate - added as f64).abs() <= added as f64 * 0.05);
+            }
+            add(&mut hll, &mut model, format!("{added}").as_bytes(), 3000);
+        }
+        assert_eq!(count(&new_hll()).unwrap(), 0);
+    }
+}
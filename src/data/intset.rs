@@ -0,0 +1,207 @@
+/// Sorted integers stored at the narrowest width that fits all of them, as
+/// Redis keeps small sets of integers. Adding a wider value upgrades every
+/// element; removing it never narrows them again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Intset {
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+}
+
+impl Default for Intset {
+    fn default() -> Self {
+        Self::I16(Vec::new())
+    }
+}
+
+fn insert_sorted<T: Ord>(values: &mut Vec<T>, value: T) -> bool {
+    match values.binary_search(&value) {
+        Ok(_) => false,
+        Err(index) => {
+            values.insert(index, value);
+            true
+        }
+    }
+}
+
+fn remove_sorted<T: Ord>(values: &mut Vec<T>, value: T) -> bool {
+    match values.binary_search(&value) {
+        Ok(index) => {
+            values.remove(index);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+impl Intset {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::I16(values) => values.len(),
+            Self::I32(values) => values.len(),
+            Self::I64(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        match self {
+            Self::I16(values) => {
+                i16::try_from(value).is_ok_and(|v| values.binary_search(&v).is_ok())
+            }
+            Self::I32(values) => {
+                i32::try_from(value).is_ok_and(|v| values.binary_search(&v).is_ok())
+            }
+            Self::I64(values) => values.binary_search(&value).is_ok(),
+        }
+    }
+
+    /// Adds `value`, returning whether it was new.
+    pub fn insert(&mut self, value: i64) -> bool {
+        self.widen_for(value);
+        match self {
+            Self::I16(values) => insert_sorted(values, value as i16),
+            Self::I32(values) => insert_sorted(values, value as i32),
+            Self::I64(values) => insert_sorted(values, value),
+        }
+    }
+
+    /// Removes `value`, returning whether it was there.
+    pub fn remove(&mut self, value: i64) -> bool {
+        match self {
+            Self::I16(values) => i16::try_from(value).is_ok_and(|v| remove_sorted(values, v)),
+            Self::I32(values) => i32::try_from(value).is_ok_and(|v| remove_sorted(values, v)),
+            Self::I64(values) => remove_sorted(values, value),
+        }
+    }
+
+    /// The element at `index` in ascending order.
+    pub fn get(&self, index: usize) -> Option<i64> {
+        match self {
+            Self::I16(values) => values.get(index).map(|&v| v as i64),
+            Self::I32(values) => values.get(index).map(|&v| v as i64),
+            Self::I64(values) => values.get(index).copied(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> {
+        (0..self.len()).map_while(|index| self.get(index))
+    }
+
+    /// Upgrades the elements to a width that can also hold `value`.
+    fn widen_for(&mut self, value: i64) {
+        match self {
+            Self::I16(values) if i16::try_from(value).is_err() => {
+                *self = if i32::try_from(value).is_ok() {
+                    Self::I32(values.iter().map(|&v| v as i32).collect())
+                } else {
+                    Self::I64(values.iter().map(|&v| v as i64).collect())
+                };
+            }
+            Self::I32(values) if i32::try_from(value).is_err() => {
+                *self = Self::I64(values.iter().map(|&v| v as i64).collect());
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::random;
+
+    /// Values either side of each width's limits, and a few small ones.
+    const VALUES: [i64; 14] = [
+        i64::MIN,
+        i32::MIN as i64 - 1,
+        i32::MIN as i64,
+        i16::MIN as i64 - 1,
+        i16::MIN as i64,
+        -1,
+        0,
+        1,
+        2,
+        i16::MAX as i64,
+        i16::MAX as i64 + 1,
+        i32::MAX as i64,
+        i32::MAX as i64 + 1,
+        i64::MAX,
+    ];
+
+    fn width(intset: &Intset) -> u8 {
+        match intset {
+            Intset::I16(_) => 16,
+            Intset::I32(_) => 32,
+            Intset::I64(_) => 64,
+        }
+    }
+
+    fn width_for(value: i64) -> u8 {
+        if i16::try_from(value).is_ok() {
+            16
+        } else if i32::try_from(value).is_ok() {
+            32
+        } else {
+            64
+        }
+    }
+
+    #[test]
+    fn matches_a_sorted_set_and_never_narrows() {
+        let mut intset = Intset::default();
+        let mut model = BTreeSet::new();
+        let mut widest = 16;
+
+        for _ in 0..3000 {
+            // Mostly values of the current width, now and then a wider one.
+            let value = match random::below(8) {
+                0 => VALUES[random::below(VALUES.len())],
+                _ => random::below(200) as i64 - 100,
+            };
+            if random::below(3) == 0 {
+                assert_eq!(intset.remove(value), model.remove(&value));
+            } else {
+                assert_eq!(intset.insert(value), model.insert(value));
+                widest = widest.max(width_for(value));
+            }
+
+            assert_eq!(width(&intset), widest);
+            assert_eq!(intset.len(), model.len());
+            assert!(intset.iter().eq(model.iter().copied()));
+            for &value in &VALUES {
+                assert_eq!(intset.contains(value), model.contains(&value));
+            }
+        }
+        for (index, &value) in model.iter().enumerate() {
+            assert_eq!(intset.get(index), Some(value));
+        }
+        assert_eq!(intset.get(model.len()), None);
+    }
+
+    #[test]
+    fn widening_keeps_every_element() {
+        let mut intset = Intset::default();
+        for value in [-3, 7, i16::MAX as i64] {
+            intset.insert(value);
+        }
+        assert!(intset.insert(i32::MIN as i64));
+        assert_eq!(width(&intset), 32);
+        assert!(intset.insert(i64::MAX));
+        assert_eq!(width(&intset), 64);
+        assert!(
+            intset
+                .iter()
+                .eq([i32::MIN as i64, -3, 7, i16::MAX as i64, i64::MAX])
+        );
+
+        assert!(intset.remove(i64::MAX));
+        assert!(intset.remove(i32::MIN as i64));
+        assert_eq!(width(&intset), 64);
+    }
+}
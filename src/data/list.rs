@@ -0,0 +1,433 @@
+use std::time::Duration;
+
+use anyhow::bail;
+
+use crate::data::{
+    Store,
+    notify::NotifyFlags,
+    quicklist::{ListValue, ListpackLimit},
+    record::{RecordData, RecordType, WRONGTYPE},
+};
+
+/// End of a list that a push or pop works on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+impl ListEnd {
+    fn push_event(self) -> &'static str {
+        match self {
+            Self::Left => "lpush",
+            Self::Right => "rpush",
+        }
+    }
+
+    fn pop_event(self) -> &'static str {
+        match self {
+            Self::Left => "lpop",
+            Self::Right => "rpop",
+        }
+    }
+}
+
+/// Where LINSERT puts the new element relative to the pivot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertPosition {
+    Before,
+    After,
+}
+
+/// Resolves `index`, negative counting from the tail, into a position in a
+/// list of `len` elements.
+fn list_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+impl Store {
+    pub(super) fn listpack_limit(&self) -> ListpackLimit {
+        ListpackLimit::new(self.config().list_max_listpack_size())
+    }
+
+    /// Runs `f` over the list at `key`, or returns `None` if there is none.
+    fn with_list<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&ListValue) -> T,
+    ) -> anyhow::Result<Option<T>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(None);
+        };
+        let RecordType::List(list) = &entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        Ok(Some(f(list)))
+    }
+
+    /// Runs `f` over the list at `key` for writing, or returns `None` if
+    /// there is none.
+    fn with_list_mut<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut ListValue) -> T,
+    ) -> anyhow::Result<Option<T>> {
+        self.expire_if_needed(key);
+        let Some(mut entry) = self.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        let RecordType::List(list) = &mut entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        Ok(Some(f(list)))
+    }
+
+    /// Pushes `values` one by one onto `end` of the list at `key` and returns
+    /// its new length. With `only_if_exists` a missing list is left alone
+    /// and 0 is returned, as LPUSHX and RPUSHX do.
+    pub fn push(
+        &self,
+        key: String,
+        end: ListEnd,
+        values: Vec<Vec<u8>>,
+        only_if_exists: bool,
+    ) -> anyhow::Result<usize> {
+        let len = {
+            let _guard = self.lock_shared();
+            self.expire_if_needed(&key);
+            if only_if_exists && !self.entries.contains_key(&key) {
+                return Ok(0);
+            }
+
+            let mut entry = self
+                .entries
+                .entry(key.clone())
+                .or_insert_with(|| RecordData::new(RecordType::List(ListValue::default()), None));
+            let RecordType::List(list) = &mut entry.record else {
+                bail!(WRONGTYPE);
+            };
+            let limit = self.listpack_limit();
+            for value in &values {
+                list.push(end, value, limit);
+            }
+            list.len()
+        };
+
+        self.notify_keyspace_event(NotifyFlags::LIST, end.push_event(), &key);
+        self.serve_blocked(&key);
+        Ok(len)
+    }
+
+    /// Removes up to `count` elements from `end` of the list at `key`, or
+    /// returns `None` if there is no list.
+    pub fn pop(
+        &self,
+        key: &str,
+        end: ListEnd,
+        count: usize,
+    ) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+        let _guard = self.lock_shared();
+        let limit = self.listpack_limit();
+        let values = self.with_list_mut(key, |list| {
+            (0..count)
+                .map_while(|_| list.pop(end, limit))
+                .collect::<Vec<_>>()
+        })?;
+
+        if values.as_ref().is_some_and(|values| !values.is_empty()) {
+            self.notify_keyspace_event(NotifyFlags::LIST, end.pop_event(), key);
+            self.delete_if_empty(key);
+        }
+        Ok(values)
+    }
+
+    /// Pops up to `count` elements from `end` of the first non-empty list
+    /// among `keys`, returning its key along with them.
+    pub fn mpop(
+        &self,
+        keys: &[String],
+        end: ListEnd,
+        count: usize,
+    ) -> anyhow::Result<Option<(String, Vec<Vec<u8>>)>> {
+        for key in keys {
+            if let Some(values) = self.pop(key, end, count)?
+                && !values.is_empty()
+            {
+                return Ok(Some((key.clone(), values)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// [`Store::mpop`] that waits up to `timeout`, forever if `None`, for
+    /// one of `keys` to receive elements.
+    pub async fn bmpop(
+        &self,
+        client_id: u64,
+        keys: Vec<String>,
+        end: ListEnd,
+        count: usize,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<Option<(String, Vec<Vec<u8>>)>> {
+        let attempt_keys = keys.clone();
+        self.block_on(client_id, keys, None, timeout, move |store| {
+            store.mpop(&attempt_keys, end, count)
+        })
+        .await
+    }
+
+    /// Pops an element from `from` of `source` and pushes it onto `to` of
+    /// `destination` as one step, returning the element moved.
+    pub fn lmove(
+        &self,
+        source: &str,
+        destination: &str,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let value = self.move_element(source, destination, from, to)?;
+        if value.is_some() {
+            self.serve_blocked(destination);
+        }
+        Ok(value)
+    }
+
+    /// LMOVE that waits up to `timeout`, forever if `None`, for `source` to
+    /// receive an element.
+    pub async fn blmove(
+        &self,
+        client_id: u64,
+        source: String,
+        destination: String,
+        from: ListEnd,
+        to: ListEnd,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let keys = vec![source.clone()];
+        let target = destination.clone();
+        self.block_on(client_id, keys, Some(target), timeout, move |store| {
+            store.move_element(&source, &destination, from, to)
+        })
+        .await
+    }
+
+    /// The move behind LMOVE. Both keys are checked before anything changes,
+    /// and the keyspace is held exclusively so no client sees the element in
+    /// neither or both lists.
+    fn move_element(
+        &self,
+        source: &str,
+        destination: &str,
+        from: ListEnd,
+        to: ListEnd,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let _guard = self.lock_exclusive();
+        if self.with_list(source, |_| ())?.is_none() {
+            return Ok(None);
+        }
+        self.with_list(destination, |_| ())?;
+
+        let limit = self.listpack_limit();
+        let value = self.with_list_mut(source, |list| list.pop(from, limit))?;
+        let Some(value) = value.flatten() else {
+            return Ok(None);
+        };
+        self.notify_keyspace_event(NotifyFlags::LIST, from.pop_event(), source);
+        self.delete_if_empty(source);
+
+        let mut entry = self
+            .entries
+            .entry(destination.to_string())
+            .or_insert_with(|| RecordData::new(RecordType::List(ListValue::default()), None));
+        if let RecordType::List(list) = &mut entry.record {
+            list.push(to, &value, limit);
+        }
+        drop(entry);
+
+        self.notify_keyspace_event(NotifyFlags::LIST, to.push_event(), destination);
+        Ok(Some(value))
+    }
+
+    /// Elements between `start` and `stop` inclusive, where negative offsets
+    /// count from the tail.
+    pub fn lrange(&self, key: &str, start: i64, stop: i64) -> anyhow::Result<Vec<Vec<u8>>> {
+        Ok(self
+            .with_list(key, |list| {
+                let len = list.len() as i64;
+                let start = if start < 0 { len + start } else { start }.max(0);
+                let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+                if start > stop {
+                    return Vec::new();
+                }
+                list.iter()
+                    .skip(start as usize)
+                    .take((stop - start) as usize + 1)
+                    .map(<[u8]>::to_vec)
+                    .collect()
+            })?
+            .unwrap_or_default())
+    }
+
+    pub fn llen(&self, key: &str) -> anyhow::Result<usize> {
+        Ok(self.with_list(key, ListValue::len)?.unwrap_or(0))
+    }
+
+    pub fn lindex(&self, key: &str, index: i64) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .with_list(key, |list| {
+                list_index(list.len(), index)
+                    .and_then(|index| list.get(index))
+                    .map(<[u8]>::to_vec)
+            })?
+            .flatten())
+    }
+
+    pub fn lset(&self, key: &str, index: i64, value: Vec<u8>) -> anyhow::Result<()> {
+        let _guard = self.lock_shared();
+        let limit = self.listpack_limit();
+        let found = self.with_list_mut(key, |list| {
+            let index = list_index(list.len(), index)?;
+            list.set(index, &value, limit);
+            Some(())
+        })?;
+
+        match found {
+            None => bail!("no such key"),
+            Some(None) => bail!("index out of range"),
+            Some(Some(())) => {
+                self.notify_keyspace_event(NotifyFlags::LIST, "lset", key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Inserts `value` next to the first occurrence of `pivot`, returning the
+    /// new length, -1 if `pivot` was not found, or 0 if there is no list.
+    pub fn linsert(
+        &self,
+        key: &str,
+        position: InsertPosition,
+        pivot: &[u8],
+        value: Vec<u8>,
+    ) -> anyhow::Result<i64> {
+        let _guard = self.lock_shared();
+        let limit = self.listpack_limit();
+        let len = self.with_list_mut(key, |list| {
+            let index = list.iter().position(|element| element == pivot)?;
+            let index = match position {
+                InsertPosition::Before => index,
+                InsertPosition::After => index + 1,
+            };
+            list.insert(index, &value, limit);
+            Some(list.len() as i64)
+        })?;
+
+        match len {
+            None => Ok(0),
+            Some(None) => Ok(-1),
+            Some(Some(len)) => {
+                self.notify_keyspace_event(NotifyFlags::LIST, "linsert", key);
+                Ok(len)
+            }
+        }
+    }
+
+    /// Removes occurrences of `value`: the first `count` from the head when
+    /// positive, the last `-count` from the tail when negative, or all of
+    /// them when zero. Returns how many were removed.
+    pub fn lrem(&self, key: &str, count: i64, value: &[u8]) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        let limit = self.listpack_limit();
+        let removed = self
+            .with_list_mut(key, |list| {
+                let max = if count == 0 {
+                    usize::MAX
+                } else {
+                    count.unsigned_abs() as usize
+                };
+                let elements: Box<dyn Iterator<Item = (usize, &[u8])>> = if count < 0 {
+                    Box::new((0..list.len()).rev().zip(list.iter().rev()))
+                } else {
+                    Box::new(list.iter().enumerate())
+                };
+                let mut matches: Vec<usize> = elements
+                    .filter(|&(_, element)| element == value)
+                    .map(|(index, _)| index)
+                    .take(max)
+                    .collect();
+                if matches.is_empty() {
+                    return 0;
+                }
+
+                matches.sort_unstable();
+                let mut next = matches.iter().peekable();
+                list.retain(|index, _| next.next_if_eq(&&index).is_none(), limit);
+                matches.len()
+            })?
+            .unwrap_or(0);
+
+        if removed > 0 {
+            self.notify_keyspace_event(NotifyFlags::LIST, "lrem", key);
+            self.delete_if_empty(key);
+        }
+        Ok(removed)
+    }
+
+    /// Keeps only the elements between `start` and `stop` inclusive, where
+    /// negative offsets count from the tail.
+    pub fn ltrim(&self, key: &str, start: i64, stop: i64) -> anyhow::Result<()> {
+        let _guard = self.lock_shared();
+        let limit = self.listpack_limit();
+        let trimmed = self.with_list_mut(key, |list| {
+            let len = list.len() as i64;
+            let start = if start < 0 { len + start } else { start }.max(0);
+            let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+            list.retain(|index, _| (start..=stop).contains(&(index as i64)), limit);
+        })?;
+
+        if trimmed.is_some() {
+            self.notify_keyspace_event(NotifyFlags::LIST, "ltrim", key);
+            self.delete_if_empty(key);
+        }
+        Ok(())
+    }
+
+    /// Positions of the elements equal to `value`. A positive `rank` skips
+    /// the first `rank - 1` matches from the head, a negative one scans from
+    /// the tail instead. At most `count` positions are returned (all of them
+    /// when zero) and at most `maxlen` elements are compared (all of them
+    /// when zero).
+    pub fn lpos(
+        &self,
+        key: &str,
+        value: &[u8],
+        rank: i64,
+        count: usize,
+        maxlen: usize,
+    ) -> anyhow::Result<Vec<usize>> {
+        let count = if count == 0 { usize::MAX } else { count };
+        let maxlen = if maxlen == 0 { usize::MAX } else { maxlen };
+        let skip = rank.unsigned_abs() as usize - 1;
+
+        Ok(self
+            .with_list(key, |list| {
+                let elements: Box<dyn Iterator<Item = (usize, &[u8])>> = if rank < 0 {
+                    Box::new((0..list.len()).rev().zip(list.iter().rev()))
+                } else {
+                    Box::new(list.iter().enumerate())
+                };
+                elements
+                    .take(maxlen)
+                    .filter(|&(_, element)| element == value)
+                    .map(|(index, _)| index)
+                    .skip(skip)
+                    .take(count)
+                    .collect()
+            })?
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,353 @@
+use std::ops::Range;
+
+/// Bytes Redis spends on a listpack's header and terminator, counted so
+/// sizes compare against `list-max-listpack-size` the way they do there.
+const HEADER_SIZE: usize = 7;
+
+/// Byte strings packed back to back into a single buffer, as Redis stores
+/// small collections.
+///
+/// Each entry is its length as a varint, its bytes, then the size of those
+/// two written so it can be read from its last byte. That trailer is what
+/// lets the buffer be walked from either end, so both ends are cheap to
+/// reach even though entries have no fixed size.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listpack {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+fn varint_len(mut n: usize) -> usize {
+    let mut len = 1;
+    while n >= 0x80 {
+        n >>= 7;
+        len += 1;
+    }
+    len
+}
+
+/// Bytes an entry holding `len` bytes takes up in a listpack.
+pub fn entry_size(len: usize) -> usize {
+    let size = varint_len(len) + len;
+    size + varint_len(size)
+}
+
+fn encode_entry(value: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(entry_size(value.len()));
+    let mut len = value.len();
+    while len >= 0x80 {
+        entry.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    entry.push(len as u8);
+    entry.extend_from_slice(value);
+
+    // Most significant digit first, with the continuation bit on every
+    // byte but the first, so reading backwards stops at the right place.
+    let size = entry.len();
+    let digits = varint_len(size);
+    for digit in (0..digits).rev() {
+        let continuation = if digit == digits - 1 { 0 } else { 0x80 };
+        entry.push((size >> (7 * digit)) as u8 & 0x7f | continuation);
+    }
+    entry
+}
+
+impl Listpack {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Size of the listpack, including the header Redis' listpacks carry.
+    pub fn bytes(&self) -> usize {
+        self.buf.len() + HEADER_SIZE
+    }
+
+    /// Where the bytes of the entry starting at `offset` are, and the offset
+    /// of the entry after it.
+    fn entry_at(&self, offset: usize) -> (Range<usize>, usize) {
+        let (mut len, mut shift, mut start) = (0, 0, offset);
+        loop {
+            let byte = self.buf[start];
+            len |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            start += 1;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let end = start + len;
+        (start..end, end + varint_len(end - offset))
+    }
+
+    /// Offset of the entry that ends right before `end`.
+    fn entry_before(&self, end: usize) -> usize {
+        let (mut size, mut shift, mut start) = (0, 0, end);
+        loop {
+            start -= 1;
+            let byte = self.buf[start];
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        start - size
+    }
+
+    /// Offset of entry `index`, or the end of the buffer for `len`, walking
+    /// from whichever end is closer.
+    fn offset_of(&self, index: usize) -> usize {
+        if index <= self.len / 2 {
+            (0..index).fold(0, |offset, _| self.entry_at(offset).1)
+        } else {
+            (index..self.len).fold(self.buf.len(), |end, _| self.entry_before(end))
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len {
+            return None;
+        }
+        let (range, _) = self.entry_at(self.offset_of(index));
+        Some(&self.buf[range])
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            listpack: self,
+            front: 0,
+            back: self.buf.len(),
+            remaining: self.len,
+        }
+    }
+
+    /// Inserts `value` so it becomes entry `index`; `len` appends.
+    pub fn insert(&mut self, index: usize, value: &[u8]) {
+        let offset = self.offset_of(index);
+        self.buf.splice(offset..offset, encode_entry(value));
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Vec<u8>> {
+        if index >= self.len {
+            return None;
+        }
+        let offset = self.offset_of(index);
+        let (range, next) = self.entry_at(offset);
+        let value = self.buf[range].to_vec();
+        self.buf.drain(offset..next);
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Replaces entry `index`, which must exist, with `value`.
+    pub fn replace(&mut self, index: usize, value: &[u8]) {
+        let offset = self.offset_of(index);
+        let (_, next) = self.entry_at(offset);
+        self.buf.splice(offset..next, encode_entry(value));
+    }
+
+    pub fn push_front(&mut self, value: &[u8]) {
+        self.insert(0, value);
+    }
+
+    pub fn push_back(&mut self, value: &[u8]) {
+        self.buf.extend(encode_entry(value));
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<Vec<u8>> {
+        self.remove(0)
+    }
+
+    pub fn pop_back(&mut self) -> Option<Vec<u8>> {
+        let index = self.len.checked_sub(1)?;
+        self.remove(index)
+    }
+
+    /// Moves the entries from `at` on into a new listpack.
+    pub fn split_off(&mut self, at: usize) -> Listpack {
+        let offset = self.offset_of(at.min(self.len));
+        let tail = Listpack {
+            buf: self.buf.split_off(offset),
+            len: self.len.saturating_sub(at),
+        };
+        self.len -= tail.len;
+        tail
+    }
+}
+
+/// Entries of a [`Listpack`], from either end.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    listpack: &'a Listpack,
+    front: usize,
+    back: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (range, next) = self.listpack.entry_at(self.front);
+        self.front = next;
+        self.remaining -= 1;
+        Some(&self.listpack.buf[range])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.back = self.listpack.entry_before(self.back);
+        self.remaining -= 1;
+        let (range, _) = self.listpack.entry_at(self.back);
+        Some(&self.listpack.buf[range])
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    /// Lengths either side of where an entry's length, or its trailer,
+    /// needs another varint byte.
+    const BOUNDARY_LENGTHS: [usize; 11] =
+        [0, 1, 125, 126, 127, 128, 129, 16380, 16381, 16383, 16384];
+
+    fn value(len: usize, seed: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 + seed) as u8).collect()
+    }
+
+    fn assert_matches(listpack: &Listpack, model: &[Vec<u8>]) {
+        assert_eq!(listpack.len(), model.len());
+        assert_eq!(
+            listpack.bytes(),
+            HEADER_SIZE
+                + model
+                    .iter()
+                    .map(|value| entry_size(value.len()))
+                    .sum::<usize>()
+        );
+        assert!(listpack.iter().eq(model.iter().map(Vec::as_slice)));
+        assert!(
+            listpack
+                .iter()
+                .rev()
+                .eq(model.iter().rev().map(Vec::as_slice))
+        );
+        for (index, value) in model.iter().enumerate() {
+            assert_eq!(listpack.get(index), Some(value.as_slice()));
+        }
+        assert_eq!(listpack.get(model.len()), None);
+    }
+
+    #[test]
+    fn entries_round_trip_from_both_ends() {
+        let mut listpack = Listpack::default();
+        let model: Vec<_> = BOUNDARY_LENGTHS
+            .iter()
+            .enumerate()
+            .map(|(seed, &len)| value(len, seed))
+            .collect();
+        for value in &model {
+            listpack.push_back(value);
+        }
+        assert_matches(&listpack, &model);
+
+        // Walking in from both ends meets in the middle without overlap.
+        let mut iter = listpack.iter();
+        let mut seen = Vec::new();
+        while let Some(front) = iter.next() {
+            seen.push(front);
+            if let Some(back) = iter.next_back() {
+                seen.push(back);
+            }
+        }
+        assert_eq!(seen.len(), model.len());
+    }
+
+    #[test]
+    fn matches_a_vec_under_random_edits() {
+        let mut listpack = Listpack::default();
+        let mut model: Vec<Vec<u8>> = Vec::new();
+
+        for round in 0..2000 {
+            let len = BOUNDARY_LENGTHS[random::below(8)];
+            let value = value(len, round);
+            match random::below(7) {
+                0 => {
+                    listpack.push_front(&value);
+                    model.insert(0, value);
+                }
+                1 => {
+                    listpack.push_back(&value);
+                    model.push(value);
+                }
+                2 => {
+                    let index = random::below(model.len() + 1);
+                    listpack.insert(index, &value);
+                    model.insert(index, value);
+                }
+                3 if !model.is_empty() => {
+                    let index = random::below(model.len());
+                    listpack.replace(index, &value);
+                    model[index] = value;
+                }
+                4 => {
+                    let index = random::below(model.len() + 1);
+                    let expected = (index < model.len()).then(|| model.remove(index));
+                    assert_eq!(listpack.remove(index), expected);
+                }
+                5 => assert_eq!(
+                    listpack.pop_front(),
+                    (!model.is_empty()).then(|| model.remove(0))
+                ),
+                _ => assert_eq!(listpack.pop_back(), model.pop()),
+            }
+            if round % 50 == 0 {
+                assert_matches(&listpack, &model);
+            }
+        }
+        assert_matches(&listpack, &model);
+    }
+
+    #[test]
+    fn split_off_keeps_both_halves_walkable() {
+        let model: Vec<_> = BOUNDARY_LENGTHS
+            .iter()
+            .chain(BOUNDARY_LENGTHS.iter().rev())
+            .enumerate()
+            .map(|(seed, &len)| value(len, seed))
+            .collect();
+
+        for at in 0..=model.len() + 1 {
+            let mut head = Listpack::default();
+            for value in &model {
+                head.push_back(value);
+            }
+            let tail = head.split_off(at);
+            let at = at.min(model.len());
+            assert_matches(&head, &model[..at]);
+            assert_matches(&tail, &model[at..]);
+        }
+    }
+}
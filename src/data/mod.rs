@@ -1,5 +1,39 @@
+mod bitmap;
+mod blocking;
+mod consumer_group;
+mod dict;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod intset;
+mod list;
+mod listpack;
+mod notify;
+mod quicklist;
 mod record;
+mod set;
+mod skiplist;
+mod sort;
 mod store;
 mod stream;
+mod string;
+mod zset;
 
+pub use bitmap::{
+    BitOperation, BitUnit, BitfieldOp, BitfieldOverflow, BitfieldType, MAX_BIT_OFFSET,
+};
+pub use blocking::UnblockMode;
+pub use consumer_group::{ClaimOptions, GroupReadFrom, GroupReadOptions, PendingFilter};
+pub use geo::{GeoOrder, GeoOrigin, GeoSearch, GeoShape, GeoUnit, is_valid_position};
+pub use hash::{ExpireCondition, MAX_FIELD_EXPIRATION_MS};
+pub use list::{InsertPosition, ListEnd};
+pub use notify::NotifyFlags;
+pub use set::SetOperation;
+pub use sort::SortOptions;
 pub use store::Store;
+pub use stream::{ReadFrom, StramValue, StreamEntryID, StreamInfo, TrimOptions, TrimStrategy};
+pub use string::{SetCondition, SetExpiration, SetOptions};
+pub use zset::{
+    Aggregate, LexBound, LexRange, ScoreComparison, ScoreEnd, ScoreRange, ZAddOptions, ZRangeBy,
+    ZRangeSpec,
+};
@@ -0,0 +1,126 @@
+use std::{fmt, ops::BitOr, str::FromStr};
+
+use thiserror::Error;
+
+/// Event classes selected by the `notify-keyspace-events` setting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NotifyFlags(u32);
+
+impl NotifyFlags {
+    pub const KEYSPACE: Self = Self(1 << 0);
+    pub const KEYEVENT: Self = Self(1 << 1);
+    pub const GENERIC: Self = Self(1 << 2);
+    pub const STRING: Self = Self(1 << 3);
+    pub const LIST: Self = Self(1 << 4);
+    pub const SET: Self = Self(1 << 5);
+    pub const HASH: Self = Self(1 << 6);
+    pub const ZSET: Self = Self(1 << 7);
+    pub const EXPIRED: Self = Self(1 << 8);
+    pub const EVICTED: Self = Self(1 << 9);
+    pub const STREAM: Self = Self(1 << 10);
+    pub const KEY_MISS: Self = Self(1 << 11);
+    pub const MODULE: Self = Self(1 << 12);
+    pub const NEW: Self = Self(1 << 13);
+    /// Everything `A` stands for, which excludes key-miss and new-key events.
+    pub const ALL: Self = Self(
+        Self::GENERIC.0
+            | Self::STRING.0
+            | Self::LIST.0
+            | Self::SET.0
+            | Self::HASH.0
+            | Self::ZSET.0
+            | Self::EXPIRED.0
+            | Self::EVICTED.0
+            | Self::STREAM.0
+            | Self::MODULE.0,
+    );
+
+    const CLASSES: [(char, Self); 10] = [
+        ('g', Self::GENERIC),
+        ('$', Self::STRING),
+        ('l', Self::LIST),
+        ('s', Self::SET),
+        ('h', Self::HASH),
+        ('z', Self::ZSET),
+        ('x', Self::EXPIRED),
+        ('e', Self::EVICTED),
+        ('t', Self::STREAM),
+        ('d', Self::MODULE),
+    ];
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for NotifyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl FromStr for NotifyFlags {
+    type Err = NotifyFlagsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = Self::default();
+        for c in s.chars() {
+            flags = flags
+                | match c {
+                    'A' => Self::ALL,
+                    'K' => Self::KEYSPACE,
+                    'E' => Self::KEYEVENT,
+                    'm' => Self::KEY_MISS,
+                    'n' => Self::NEW,
+                    _ => Self::CLASSES
+                        .iter()
+                        .find(|(class, _)| *class == c)
+                        .map(|(_, flag)| *flag)
+                        .ok_or(NotifyFlagsError::InvalidClass)?,
+                };
+        }
+        Ok(flags)
+    }
+}
+
+impl fmt::Display for NotifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.contains(Self::ALL) {
+            f.write_str("A")?;
+        } else {
+            for (class, flag) in Self::CLASSES {
+                if self.contains(flag) {
+                    write!(f, "{class}")?;
+                }
+            }
+        }
+
+        for (class, flag) in [
+            ('K', Self::KEYSPACE),
+            ('E', Self::KEYEVENT),
+            ('m', Self::KEY_MISS),
+            ('n', Self::NEW),
+        ] {
+            if self.contains(flag) {
+                write!(f, "{class}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum NotifyFlagsError {
+    #[error("Invalid event class character. Use 'Ag$lshzxeKEtmdn'.")]
+    InvalidClass,
+}
@@ -0,0 +1,430 @@
+use std::collections::VecDeque;
+
+use crate::data::{
+    list::ListEnd,
+    listpack::{self, Listpack},
+};
+
+/// Largest listpack allowed when `list-max-listpack-size` counts entries.
+const SIZE_SAFETY_LIMIT: usize = 8192;
+
+/// Listpack sizes selected by `list-max-listpack-size` -1 through -5.
+const SIZE_LEVELS: [usize; 5] = [4096, 8192, 16384, 32768, 65536];
+
+/// How large a single listpack may grow, as set by `list-max-listpack-size`:
+/// a number of entries when positive, or a size in bytes from 4KB (-1) to
+/// 64KB (-5) when negative.
+#[derive(Debug, Clone, Copy)]
+pub struct ListpackLimit {
+    bytes: usize,
+    entries: usize,
+}
+
+impl ListpackLimit {
+    pub fn new(fill: i64) -> Self {
+        if fill >= 0 {
+            Self {
+                bytes: SIZE_SAFETY_LIMIT,
+                entries: (fill as usize).max(1),
+            }
+        } else {
+            let level = (fill.unsigned_abs() as usize - 1).min(SIZE_LEVELS.len() - 1);
+            Self {
+                bytes: SIZE_LEVELS[level],
+                entries: usize::MAX,
+            }
+        }
+    }
+
+    fn exceeded_by(self, bytes: usize, entries: usize) -> bool {
+        bytes > self.bytes || entries > self.entries
+    }
+
+    /// Whether `node` can take `value` without going over the limit.
+    fn fits(self, node: &Listpack, value: &[u8]) -> bool {
+        !self.exceeded_by(
+            node.bytes() + listpack::entry_size(value.len()),
+            node.len() + 1,
+        )
+    }
+}
+
+/// Value of a list key.
+///
+/// Small lists are a single [`Listpack`], which costs a few bytes per
+/// element instead of an allocation each. Once one outgrows
+/// `list-max-listpack-size` it becomes a [`Quicklist`] of listpacks, so
+/// pushes and pops stay cheap however long it gets. It converts back once
+/// it fits in half the limit again, so a list hovering around the limit
+/// doesn't flip on every push and pop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListValue {
+    Listpack(Listpack),
+    Quicklist(Quicklist),
+}
+
+impl Default for ListValue {
+    fn default() -> Self {
+        Self::Listpack(Listpack::default())
+    }
+}
+
+impl ListValue {
+    /// Builds a list holding `values` in order.
+    fn from_values<'a>(values: impl Iterator<Item = &'a [u8]>, limit: ListpackLimit) -> Self {
+        let mut list = Self::default();
+        for value in values {
+            list.push(ListEnd::Right, value, limit);
+        }
+        list
+    }
+
+    /// Name of the encoding, as OBJECT ENCODING reports it.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::Listpack(_) => "listpack",
+            Self::Quicklist(_) => "quicklist",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Listpack(listpack) => listpack.len(),
+            Self::Quicklist(quicklist) => quicklist.len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        match self {
+            Self::Listpack(listpack) => listpack.get(index),
+            Self::Quicklist(quicklist) => quicklist.get(index),
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &[u8]> {
+        let (front, back) = match self {
+            Self::Listpack(listpack) => (std::slice::from_ref(listpack), &[][..]),
+            Self::Quicklist(quicklist) => quicklist.nodes.as_slices(),
+        };
+        front.iter().chain(back).flat_map(Listpack::iter)
+    }
+
+    pub fn push(&mut self, end: ListEnd, value: &[u8], limit: ListpackLimit) {
+        self.grow(Some(value), limit);
+        match self {
+            Self::Listpack(listpack) => match end {
+                ListEnd::Left => listpack.push_front(value),
+                ListEnd::Right => listpack.push_back(value),
+            },
+            Self::Quicklist(quicklist) => quicklist.push(end, value, limit),
+        }
+    }
+
+    pub fn pop(&mut self, end: ListEnd, limit: ListpackLimit) -> Option<Vec<u8>> {
+        let value = match self {
+            Self::Listpack(listpack) => match end {
+                ListEnd::Left => listpack.pop_front(),
+                ListEnd::Right => listpack.pop_back(),
+            },
+            Self::Quicklist(quicklist) => quicklist.pop(end),
+        };
+        self.shrink(limit);
+        value
+    }
+
+    /// Inserts `value` so it becomes element `index`; `len` appends.
+    pub fn insert(&mut self, index: usize, value: &[u8], limit: ListpackLimit) {
+        self.grow(Some(value), limit);
+        match self {
+            Self::Listpack(listpack) => listpack.insert(index, value),
+            Self::Quicklist(quicklist) => quicklist.insert(index, value, limit),
+        }
+    }
+
+    /// Replaces element `index`, which must exist, with `value`.
+    pub fn set(&mut self, index: usize, value: &[u8], limit: ListpackLimit) {
+        match self {
+            Self::Listpack(listpack) => listpack.replace(index, value),
+            Self::Quicklist(quicklist) => quicklist.set(index, value, limit),
+        }
+        self.grow(None, limit);
+    }
+
+    /// Keeps only the elements for which `keep` returns true, given their
+    /// position and bytes.
+    pub fn retain(&mut self, mut keep: impl FnMut(usize, &[u8]) -> bool, limit: ListpackLimit) {
+        let kept = self
+            .iter()
+            .enumerate()
+            .filter(|&(index, value)| keep(index, value))
+            .map(|(_, value)| value);
+        *self = Self::from_values(kept, limit);
+    }
+
+    /// Switches a listpack to a quicklist if it can't take `added`, or with
+    /// `None` if it has already outgrown the limit.
+    fn grow(&mut self, added: Option<&[u8]>, limit: ListpackLimit) {
+        let Self::Listpack(listpack) = self else {
+            return;
+        };
+        let exceeded = match added {
+            Some(value) => !limit.fits(listpack, value),
+            None => limit.exceeded_by(listpack.bytes(), listpack.len()),
+        };
+        if exceeded {
+            let mut quicklist = Quicklist::default();
+            for value in listpack.iter() {
+                quicklist.push(ListEnd::Right, value, limit);
+            }
+            *self = Self::Quicklist(quicklist);
+        }
+    }
+
+    /// Switches a quicklist down to a single node back to a listpack, once
+    /// that node fits in half the limit.
+    fn shrink(&mut self, limit: ListpackLimit) {
+        let half = ListpackLimit {
+            bytes: limit.bytes / 2,
+            entries: limit.entries / 2,
+        };
+        if let Self::Quicklist(quicklist) = self
+            && quicklist.nodes.len() <= 1
+            && quicklist
+                .nodes
+                .front()
+                .is_none_or(|node| !half.exceeded_by(node.bytes(), node.len()))
+        {
+            *self = Self::Listpack(quicklist.nodes.pop_front().unwrap_or_default());
+        }
+    }
+}
+
+/// A list split into listpack nodes, each kept within the listpack limit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quicklist {
+    nodes: VecDeque<Listpack>,
+    len: usize,
+}
+
+impl Quicklist {
+    /// The node holding element `index`, which must exist, and the element's
+    /// position within it, searching from whichever end is closer.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        if index < self.len / 2 {
+            let mut index = index;
+            for (node_index, node) in self.nodes.iter().enumerate() {
+                if index < node.len() {
+                    return (node_index, index);
+                }
+                index -= node.len();
+            }
+        } else {
+            let mut from_tail = self.len - 1 - index;
+            for (node_index, node) in self.nodes.iter().enumerate().rev() {
+                if from_tail < node.len() {
+                    return (node_index, node.len() - 1 - from_tail);
+                }
+                from_tail -= node.len();
+            }
+        }
+        unreachable!("index {index} is within the list")
+    }
+
+    fn get(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len {
+            return None;
+        }
+        let (node, index) = self.locate(index);
+        self.nodes[node].get(index)
+    }
+
+    fn push(&mut self, end: ListEnd, value: &[u8], limit: ListpackLimit) {
+        let node = match end {
+            ListEnd::Left => self.nodes.front_mut(),
+            ListEnd::Right => self.nodes.back_mut(),
+        };
+        match node {
+            Some(node) if limit.fits(node, value) => match end {
+                ListEnd::Left => node.push_front(value),
+                ListEnd::Right => node.push_back(value),
+            },
+            _ => {
+                let mut node = Listpack::default();
+                node.push_back(value);
+                match end {
+                    ListEnd::Left => self.nodes.push_front(node),
+                    ListEnd::Right => self.nodes.push_back(node),
+                }
+            }
+        }
+        self.len += 1;
+    }
+
+    fn pop(&mut self, end: ListEnd) -> Option<Vec<u8>> {
+        let value = match end {
+            ListEnd::Left => {
+                let node = self.nodes.front_mut()?;
+                let value = node.pop_front();
+                if node.is_empty() {
+                    self.nodes.pop_front();
+                }
+                value
+            }
+            ListEnd::Right => {
+                let node = self.nodes.back_mut()?;
+                let value = node.pop_back();
+                if node.is_empty() {
+                    self.nodes.pop_back();
+                }
+                value
+            }
+        };
+        self.len -= 1;
+        value
+    }
+
+    fn insert(&mut self, index: usize, value: &[u8], limit: ListpackLimit) {
+        if index == self.len {
+            return self.push(ListEnd::Right, value, limit);
+        }
+
+        let (node_index, index) = self.locate(index);
+        self.len += 1;
+        if limit.fits(&self.nodes[node_index], value) {
+            self.nodes[node_index].insert(index, value);
+            return;
+        }
+
+        // The node is full: split it where the value goes and put the value
+        // at the end of the head, the start of the tail, or in a node of its
+        // own between them.
+        let head = &mut self.nodes[node_index];
+        let mut tail = head.split_off(index);
+        let mut at = node_index + 1;
+        if !head.is_empty() && limit.fits(head, value) {
+            head.push_back(value);
+        } else if limit.fits(&tail, value) {
+            tail.push_front(value);
+        } else {
+            let mut node = Listpack::default();
+            node.push_back(value);
+            self.nodes.insert(at, node);
+            at += 1;
+        }
+        self.nodes.insert(at, tail);
+        if self.nodes[node_index].is_empty() {
+            self.nodes.remove(node_index);
+        }
+    }
+
+    fn set(&mut self, index: usize, value: &[u8], limit: ListpackLimit) {
+        let (node_index, index) = self.locate(index);
+        let node = &mut self.nodes[node_index];
+        node.replace(index, value);
+        if node.len() > 1 && limit.exceeded_by(node.bytes(), node.len()) {
+            let tail = node.split_off(node.len() / 2);
+            self.nodes.insert(node_index + 1, tail);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random;
+
+    fn assert_matches(list: &ListValue, model: &[Vec<u8>]) {
+        assert_eq!(list.len(), model.len());
+        assert!(list.iter().eq(model.iter().map(Vec::as_slice)));
+        assert!(list.iter().rev().eq(model.iter().rev().map(Vec::as_slice)));
+        for (index, value) in model.iter().enumerate() {
+            assert_eq!(list.get(index), Some(value.as_slice()));
+        }
+        assert_eq!(list.get(model.len()), None);
+
+        if let ListValue::Quicklist(quicklist) = list {
+            assert!(quicklist.nodes.iter().all(|node| !node.is_empty()));
+            assert_eq!(
+                quicklist.nodes.iter().map(Listpack::len).sum::<usize>(),
+                quicklist.len
+            );
+        }
+    }
+
+    fn random_edits(limit: ListpackLimit, max_len: usize) {
+        let mut list = ListValue::default();
+        let mut model: Vec<Vec<u8>> = Vec::new();
+        let mut converted = false;
+
+        for round in 0..3000 {
+            let value = vec![round as u8; random::below(max_len + 1)];
+            let end = if random::below(2) == 0 {
+                ListEnd::Left
+            } else {
+                ListEnd::Right
+            };
+            // Grow for the first half, then mostly shrink.
+            let grow = round < 1500;
+            match random::below(6) {
+                0 | 1 if grow || random::below(4) == 0 => {
+                    list.push(end, &value, limit);
+                    match end {
+                        ListEnd::Left => model.insert(0, value),
+                        ListEnd::Right => model.push(value),
+                    }
+                }
+                2 if grow => {
+                    let index = random::below(model.len() + 1);
+                    list.insert(index, &value, limit);
+                    model.insert(index, value);
+                }
+                3 if !model.is_empty() => {
+                    let index = random::below(model.len());
+                    list.set(index, &value, limit);
+                    model[index] = value;
+                }
+                4 if random::below(20) == 0 => {
+                    let byte = random::below(256) as u8;
+                    list.retain(|_, value| value.first() != Some(&byte), limit);
+                    model.retain(|value| value.first() != Some(&byte));
+                }
+                _ => {
+                    let expected = match end {
+                        ListEnd::Left => (!model.is_empty()).then(|| model.remove(0)),
+                        ListEnd::Right => model.pop(),
+                    };
+                    assert_eq!(list.pop(end, limit), expected);
+                }
+            }
+            converted |= list.encoding() == "quicklist";
+            if round % 50 == 0 {
+                assert_matches(&list, &model);
+            }
+        }
+        assert_matches(&list, &model);
+        assert!(converted);
+
+        for value in model {
+            assert_eq!(list.pop(ListEnd::Left, limit), Some(value));
+        }
+        assert!(list.is_empty());
+        assert_eq!(list.encoding(), "listpack");
+    }
+
+    #[test]
+    fn matches_a_vec_when_limited_by_entries() {
+        random_edits(ListpackLimit::new(8), 20);
+    }
+
+    #[test]
+    fn matches_a_vec_when_limited_by_bytes() {
+        // Values up to a quarter of the limit, with the odd one bigger than
+        // a whole node.
+        random_edits(ListpackLimit::new(-1), 1024);
+        random_edits(ListpackLimit::new(-1), 5000);
+    }
+}
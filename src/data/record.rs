@@ -1,28 +1,76 @@
-use std::{collections::VecDeque, time::SystemTime};
+use crate::data::{
+    hash::HashValue, quicklist::ListValue, set::SetValue, stream::StreamRecord,
+    string::StringValue, zset::ZSetValue,
+};
 
-use crate::data::stream::StreamRecord;
+/// Error for a command run against a key of another type.
+pub const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RecordType {
-    String(String),
-    List(VecDeque<String>),
+    String(StringValue),
+    List(ListValue),
+    Hash(HashValue),
+    Set(SetValue),
+    ZSet(ZSetValue),
     Stream(StreamRecord),
 }
 
+impl RecordType {
+    /// Whether this is a collection with nothing left in it. Such keys are
+    /// deleted, so an empty collection never shows up as existing. Streams
+    /// are kept, as they carry their last ID.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            RecordType::List(list) => list.is_empty(),
+            RecordType::Hash(hash) => hash.is_empty(),
+            RecordType::Set(set) => set.is_empty(),
+            RecordType::ZSet(zset) => zset.is_empty(),
+            RecordType::String(_) | RecordType::Stream(_) => false,
+        }
+    }
+
+    /// Whether this is a hash with fields whose TTL has passed at `now`.
+    pub fn has_expired_fields(&self, now: u64) -> bool {
+        matches!(self, RecordType::Hash(hash) if hash.has_expired_fields(now))
+    }
+
+    /// How the value is laid out in memory, as OBJECT ENCODING reports it.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            RecordType::String(string) => string.encoding(),
+            RecordType::List(list) => list.encoding(),
+            RecordType::Hash(_) => "hashtable",
+            RecordType::Set(set) => set.encoding(),
+            RecordType::ZSet(_) => "skiplist",
+            RecordType::Stream(_) => "stream",
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RecordData {
     pub record: RecordType,
-    expiration: Option<SystemTime>,
+    /// Absolute expiration time in unix milliseconds.
+    expiration: Option<u64>,
 }
 
 impl RecordData {
-    pub fn new(record: RecordType, expiration: Option<SystemTime>) -> Self {
+    pub fn new(record: RecordType, expiration: Option<u64>) -> Self {
         Self { record, expiration }
     }
 
-    pub fn is_expired(&self) -> bool {
+    pub fn expiration(&self) -> Option<u64> {
+        self.expiration
+    }
+
+    pub fn set_expiration(&mut self, expiration: Option<u64>) {
+        self.expiration = expiration;
+    }
+
+    pub fn is_expired(&self, now_ms: u64) -> bool {
         if let Some(expiration) = self.expiration {
-            return SystemTime::now() > expiration;
+            return now_ms > expiration;
         }
         false
     }
@@ -31,6 +79,9 @@ impl RecordData {
         match self.record {
             RecordType::String(_) => "string",
             RecordType::List(_) => "list",
+            RecordType::Hash(_) => "hash",
+            RecordType::Set(_) => "set",
+            RecordType::ZSet(_) => "zset",
             RecordType::Stream(_) => "stream",
         }
     }
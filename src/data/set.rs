@@ -0,0 +1,485 @@
+use std::collections::HashSet;
+
+use anyhow::bail;
+
+use crate::data::{
+    Store,
+    dict::Dict,
+    intset::Intset,
+    notify::NotifyFlags,
+    record::{RecordData, RecordType, WRONGTYPE},
+    string::parse_canonical_i64,
+};
+use crate::{glob, random};
+
+/// Value of a set key. Sets whose members are all integers start out as a
+/// sorted intset and become a hash table once a member isn't an integer or
+/// they outgrow `set-max-intset-entries`; they never go back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SetValue {
+    Intset(Intset),
+    Hashtable(Dict<Vec<u8>, ()>),
+}
+
+impl Default for SetValue {
+    fn default() -> Self {
+        Self::Intset(Intset::default())
+    }
+}
+
+impl SetValue {
+    /// A set of `members`, as compact as `max_intset_entries` allows.
+    fn from_members(members: impl IntoIterator<Item = Vec<u8>>, max_intset_entries: usize) -> Self {
+        let mut set = Self::default();
+        for member in members {
+            set.insert(member, max_intset_entries);
+        }
+        set
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::Intset(_) => "intset",
+            Self::Hashtable(_) => "hashtable",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Intset(intset) => intset.len(),
+            Self::Hashtable(dict) => dict.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Self::Intset(intset) => parse_canonical_i64(member).is_some_and(|n| intset.contains(n)),
+            Self::Hashtable(dict) => dict.contains_key(member),
+        }
+    }
+
+    /// Adds `member`, returning whether it was new.
+    pub fn insert(&mut self, member: Vec<u8>, max_intset_entries: usize) -> bool {
+        if let Self::Intset(intset) = self {
+            match parse_canonical_i64(&member) {
+                Some(n) if intset.contains(n) => return false,
+                Some(n) if intset.len() < max_intset_entries => return intset.insert(n),
+                _ => {
+                    let mut dict = Dict::default();
+                    for n in intset.iter() {
+                        dict.insert(n.to_string().into_bytes(), ());
+                    }
+                    *self = Self::Hashtable(dict);
+                }
+            }
+        }
+        let Self::Hashtable(dict) = self else {
+            unreachable!("converted above");
+        };
+        dict.insert(member, ()).is_none()
+    }
+
+    /// Removes `member`, returning whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Self::Intset(intset) => parse_canonical_i64(member).is_some_and(|n| intset.remove(n)),
+            Self::Hashtable(dict) => dict.remove(member).is_some(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> {
+        let (integers, strings) = match self {
+            Self::Intset(intset) => (Some(intset.iter()), None),
+            Self::Hashtable(dict) => (None, Some(dict.keys())),
+        };
+        integers
+            .into_iter()
+            .flatten()
+            .map(|n| n.to_string().into_bytes())
+            .chain(strings.into_iter().flatten().cloned())
+    }
+
+    pub fn random(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::Intset(intset) if intset.is_empty() => None,
+            Self::Intset(intset) => intset
+                .get(random::below(intset.len()))
+                .map(|n| n.to_string().into_bytes()),
+            Self::Hashtable(dict) => dict.random().map(|(member, _)| member.clone()),
+        }
+    }
+
+    /// One SCAN step from `cursor`. An intset is small enough to be returned
+    /// whole, as Redis does.
+    pub fn scan(&self, cursor: u64, count: usize, mut visit: impl FnMut(&[u8])) -> u64 {
+        match self {
+            Self::Intset(intset) => {
+                intset.iter().for_each(|n| visit(n.to_string().as_bytes()));
+                0
+            }
+            Self::Hashtable(dict) => dict.scan(cursor, count, |member, _| visit(member)),
+        }
+    }
+}
+
+/// How SINTER, SUNION and SDIFF combine their sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOperation {
+    Inter,
+    Union,
+    /// Members of the first set that are in none of the others.
+    Diff,
+}
+
+impl SetOperation {
+    fn store_event(self) -> &'static str {
+        match self {
+            Self::Inter => "sinterstore",
+            Self::Union => "sunionstore",
+            Self::Diff => "sdiffstore",
+        }
+    }
+}
+
+impl Store {
+    fn intset_max_entries(&self) -> usize {
+        self.config().set_max_intset_entries()
+    }
+
+    /// Runs `f` over the set at `key`, or returns `None` if there is none.
+    fn with_set<T>(&self, key: &str, f: impl FnOnce(&SetValue) -> T) -> anyhow::Result<Option<T>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(None);
+        };
+        let RecordType::Set(set) = &entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        Ok(Some(f(set)))
+    }
+
+    /// Runs `f` over the set at `key` for writing, or returns `None` if there
+    /// is none.
+    fn with_set_mut<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&mut SetValue) -> T,
+    ) -> anyhow::Result<Option<T>> {
+        self.expire_if_needed(key);
+        let Some(mut entry) = self.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        let RecordType::Set(set) = &mut entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        Ok(Some(f(set)))
+    }
+
+    /// Adds `members`, returning how many are new.
+    pub fn sadd(&self, key: &str, members: Vec<Vec<u8>>) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+        let max_intset_entries = self.intset_max_entries();
+        let mut entry = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| RecordData::new(RecordType::Set(SetValue::default()), None));
+        let RecordType::Set(set) = &mut entry.record else {
+            bail!(WRONGTYPE);
+        };
+        let added = members
+            .into_iter()
+            .map(|member| set.insert(member, max_intset_entries))
+            .filter(|&added| added)
+            .count();
+        drop(entry);
+
+        if added > 0 {
+            self.notify_keyspace_event(NotifyFlags::SET, "sadd", key);
+        }
+        Ok(added)
+    }
+
+    /// Removes `members`, returning how many were there.
+    pub fn srem(&self, key: &str, members: &[Vec<u8>]) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        let removed = self
+            .with_set_mut(key, |set| {
+                members.iter().filter(|member| set.remove(member)).count()
+            })?
+            .unwrap_or(0);
+
+        if removed > 0 {
+            self.notify_keyspace_event(NotifyFlags::SET, "srem", key);
+            self.delete_if_empty(key);
+        }
+        Ok(removed)
+    }
+
+    pub fn smembers(&self, key: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+        Ok(self
+            .with_set(key, |set| set.iter().collect())?
+            .unwrap_or_default())
+    }
+
+    pub fn sismember(&self, key: &str, member: &[u8]) -> anyhow::Result<bool> {
+        Ok(self
+            .with_set(key, |set| set.contains(member))?
+            .unwrap_or(false))
+    }
+
+    pub fn smismember(&self, key: &str, members: &[Vec<u8>]) -> anyhow::Result<Vec<bool>> {
+        Ok(self
+            .with_set(key, |set| {
+                members.iter().map(|member| set.contains(member)).collect()
+            })?
+            .unwrap_or_else(|| vec![false; members.len()]))
+    }
+
+    pub fn scard(&self, key: &str) -> anyhow::Result<usize> {
+        Ok(self.with_set(key, SetValue::len)?.unwrap_or(0))
+    }
+
+    /// Removes and returns up to `count` random members, or `None` if there
+    /// is no set.
+    pub fn spop(&self, key: &str, count: usize) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+        let _guard = self.lock_shared();
+        let popped = self.with_set_mut(key, |set| {
+            if count >= set.len() {
+                return std::mem::take(set).iter().collect();
+            }
+            (0..count)
+                .filter_map(|_| {
+                    let member = set.random()?;
+                    set.remove(&member);
+                    Some(member)
+                })
+                .collect::<Vec<_>>()
+        })?;
+
+        if popped.as_ref().is_some_and(|popped| !popped.is_empty()) {
+            self.notify_keyspace_event(NotifyFlags::SET, "spop", key);
+            self.delete_if_empty(key);
+        }
+        Ok(popped)
+    }
+
+    /// Random members, or `None` if there is no set. A positive `count`
+    /// returns that many distinct members, or all of them if the set is
+    /// smaller; a negative one returns `-count` members that may repeat.
+    pub fn srandmember(&self, key: &str, count: i64) -> anyhow::Result<Option<Vec<Vec<u8>>>> {
+        self.with_set(key, |set| {
+            if count < 0 {
+                return (0..count.unsigned_abs())
+                    .filter_map(|_| set.random())
+                    .collect();
+            }
+
+            let count = count as usize;
+            let mut members: Vec<_> = set.iter().collect();
+            if count >= members.len() {
+                return members;
+            }
+            // Partial Fisher-Yates: the first `count` slots end up a uniform
+            // sample of the set.
+            for i in 0..count {
+                let j = i + random::below(members.len() - i);
+                members.swap(i, j);
+            }
+            members.truncate(count);
+            members
+        })
+    }
+
+    /// Moves `member` from the set at `source` to the one at `destination`,
+    /// returning whether `source` had it.
+    pub fn smove(&self, source: &str, destination: &str, member: Vec<u8>) -> anyhow::Result<bool> {
+        let _guard = self.lock_exclusive();
+        let Some(present) = self.with_set(source, |set| set.contains(&member))? else {
+            return Ok(false);
+        };
+        self.with_set(destination, |_| ())?;
+        if source == destination || !present {
+            return Ok(present);
+        }
+
+        self.with_set_mut(source, |set| set.remove(&member))?;
+        self.notify_keyspace_event(NotifyFlags::SET, "srem", source);
+        self.delete_if_empty(source);
+
+        let max_intset_entries = self.intset_max_entries();
+        let mut entry = self
+            .entries
+            .entry(destination.to_string())
+            .or_insert_with(|| RecordData::new(RecordType::Set(SetValue::default()), None));
+        let added = match &mut entry.record {
+            RecordType::Set(set) => set.insert(member, max_intset_entries),
+            _ => false,
+        };
+        drop(entry);
+
+        if added {
+            self.notify_keyspace_event(NotifyFlags::SET, "sadd", destination);
+        }
+        Ok(true)
+    }
+
+    /// Members resulting from `operation` over the sets at `keys`, where a
+    /// missing key counts as an empty set.
+    fn combine(&self, operation: SetOperation, keys: &[String]) -> anyhow::Result<Vec<Vec<u8>>> {
+        // Every key is checked up front, so a key of another type is an
+        // error even when the result is already known to be empty.
+        let mut sizes = Vec::with_capacity(keys.len());
+        for key in keys {
+            sizes.push(self.with_set(key, SetValue::len)?);
+        }
+
+        let members_of = |key: &String| {
+            self.with_set(key, |set| set.iter().collect::<Vec<_>>())
+                .map(Option::unwrap_or_default)
+        };
+        let members = match operation {
+            SetOperation::Inter => {
+                if sizes.contains(&None) {
+                    return Ok(Vec::new());
+                }
+                // Start from the smallest set, so each other one is probed
+                // as few times as possible.
+                let smallest = (0..keys.len()).min_by_key(|&i| sizes[i]).unwrap_or(0);
+                let mut members = members_of(&keys[smallest])?;
+                for (i, key) in keys.iter().enumerate() {
+                    if i != smallest && !members.is_empty() {
+                        self.with_set(key, |set| members.retain(|member| set.contains(member)))?;
+                    }
+                }
+                members
+            }
+            SetOperation::Union => {
+                let mut union = HashSet::new();
+                for key in keys {
+                    union.extend(members_of(key)?);
+                }
+                union.into_iter().collect()
+            }
+            SetOperation::Diff => {
+                let mut members = members_of(&keys[0])?;
+                for key in &keys[1..] {
+                    if !members.is_empty() {
+                        self.with_set(key, |set| members.retain(|member| !set.contains(member)))?;
+                    }
+                }
+                members
+            }
+        };
+        Ok(members)
+    }
+
+    /// SINTER, SUNION or SDIFF over the sets at `keys`.
+    pub fn set_operation(
+        &self,
+        operation: SetOperation,
+        keys: &[String],
+    ) -> anyhow::Result<Vec<Vec<u8>>> {
+        let _guard = self.lock_shared();
+        self.combine(operation, keys)
+    }
+
+    /// Stores the result of `operation` over the sets at `keys` in
+    /// `destination`, replacing whatever was there, and returns its size.
+    pub fn set_operation_store(
+        &self,
+        operation: SetOperation,
+        destination: &str,
+        keys: &[String],
+    ) -> anyhow::Result<usize> {
+        let _guard = self.lock_exclusive();
+        let members = self.combine(operation, keys)?;
+
+        self.expire_if_needed(destination);
+        if members.is_empty() {
+            if self.entries.remove(destination).is_some() {
+                self.notify_keyspace_event(NotifyFlags::GENERIC, "del", destination);
+            }
+            return Ok(0);
+        }
+
+        let len = members.len();
+        let set = SetValue::from_members(members, self.intset_max_entries());
+        self.entries.insert(
+            destination.to_string(),
+            RecordData::new(RecordType::Set(set), None),
+        );
+        self.notify_keyspace_event(NotifyFlags::SET, operation.store_event(), destination);
+        Ok(len)
+    }
+
+    /// Size of the intersection of the sets at `keys`, counting no further
+    /// than `limit` if it isn't 0.
+    pub fn sintercard(&self, keys: &[String], limit: usize) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        let mut sizes = Vec::with_capacity(keys.len());
+        for key in keys {
+            sizes.push(self.with_set(key, SetValue::len)?);
+        }
+        if sizes.contains(&None) {
+            return Ok(0);
+        }
+
+        // Read guards never wait on one another, so every set can be held at
+        // once while the smallest is walked and the others probed.
+        let entries: Vec<_> = keys
+            .iter()
+            .filter_map(|key| self.entries.get(key))
+            .collect();
+        let sets: Vec<_> = entries
+            .iter()
+            .filter_map(|entry| match &entry.record {
+                RecordType::Set(set) => Some(set),
+                _ => None,
+            })
+            .collect();
+        if sets.len() < keys.len() {
+            return Ok(0);
+        }
+
+        let smallest = (0..sets.len()).min_by_key(|&i| sets[i].len()).unwrap_or(0);
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        Ok(sets[smallest]
+            .iter()
+            .filter(|member| {
+                sets.iter()
+                    .enumerate()
+                    .all(|(i, set)| i == smallest || set.contains(member))
+            })
+            .take(limit)
+            .count())
+    }
+
+    /// One step of SSCAN: the members matching `pattern` from about `count`
+    /// buckets starting at `cursor`, and the cursor to continue from.
+    pub fn sscan(
+        &self,
+        key: &str,
+        cursor: u64,
+        pattern: Option<&[u8]>,
+        count: usize,
+    ) -> anyhow::Result<(u64, Vec<Vec<u8>>)> {
+        Ok(self
+            .with_set(key, |set| {
+                let mut members = Vec::new();
+                let cursor = set.scan(cursor, count, |member| {
+                    if pattern.is_none_or(|pattern| glob::matches_bytes(pattern, member)) {
+                        members.push(member.to_vec());
+                    }
+                });
+                (cursor, members)
+            })?
+            .unwrap_or_default())
+    }
+}
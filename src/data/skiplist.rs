@@ -0,0 +1,408 @@
+use crate::random;
+
+/// Most levels a node can have, as in Redis.
+const MAX_LEVEL: usize = 32;
+/// Index of the header node, which holds no element.
+const HEADER: usize = 0;
+/// Link to no node.
+const NIL: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    forward: usize,
+    /// Elements the link skips over, counting the one it lands on, which is
+    /// what makes ranks cheap to find.
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: String,
+    score: f64,
+    backward: usize,
+    levels: Vec<Level>,
+}
+
+/// Bounds of a range of elements in a [`SkipList`], such as a range of
+/// scores or, for elements sharing a score, of members.
+pub trait Bounds {
+    /// Whether an element is at or past the start of the range.
+    fn above_min(&self, score: f64, member: &str) -> bool;
+    /// Whether an element is at or before the end of the range.
+    fn below_max(&self, score: f64, member: &str) -> bool;
+}
+
+/// Elements ordered by score, then member, as Redis keeps sorted sets.
+///
+/// Nodes live in a vector and link to each other by index. Every link
+/// records how many elements it skips, so finding an element's rank, or the
+/// element at a rank, takes the same O(log n) walk as finding it by score.
+#[derive(Debug, Clone)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    /// Slots in `nodes` left behind by removed elements.
+    free: Vec<usize>,
+    len: usize,
+    /// Levels in use by the tallest node.
+    level: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        let header = Node {
+            member: String::new(),
+            score: 0.0,
+            backward: NIL,
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0,
+                };
+                MAX_LEVEL
+            ],
+        };
+        Self {
+            nodes: vec![header],
+            free: Vec::new(),
+            len: 0,
+            level: 1,
+        }
+    }
+}
+
+/// A level count where each level is a quarter as likely as the one below.
+fn random_level() -> usize {
+    let mut level = 1;
+    while level < MAX_LEVEL && random::below(4) == 0 {
+        level += 1;
+    }
+    level
+}
+
+impl SkipList {
+    fn forward(&self, node: usize, level: usize) -> usize {
+        self.nodes[node].levels[level].forward
+    }
+
+    /// Whether `node` sorts before the element `(score, member)`.
+    fn is_before(&self, node: usize, score: f64, member: &str) -> bool {
+        let node = &self.nodes[node];
+        node.score < score || (node.score == score && node.member.as_str() < member)
+    }
+
+    /// The last node on each level that sorts before `(score, member)`,
+    /// along with the rank of each.
+    fn predecessors(&self, score: f64, member: &str) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let (mut update, mut rank) = ([HEADER; MAX_LEVEL], [0; MAX_LEVEL]);
+        let mut node = HEADER;
+        for i in (0..self.level).rev() {
+            rank[i] = rank.get(i + 1).copied().unwrap_or(0);
+            loop {
+                let next = self.forward(node, i);
+                if next == NIL || !self.is_before(next, score, member) {
+                    break;
+                }
+                rank[i] += self.nodes[node].levels[i].span;
+                node = next;
+            }
+            update[i] = node;
+        }
+        (update, rank)
+    }
+
+    /// Adds an element whose member isn't in the list yet.
+    pub fn insert(&mut self, score: f64, member: String) {
+        let (mut update, mut rank) = self.predecessors(score, &member);
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEADER;
+                self.nodes[HEADER].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            backward: if update[0] == HEADER { NIL } else { update[0] },
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0,
+                };
+                level
+            ],
+        };
+        let new = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let previous = self.nodes[update[i]].levels[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[new].levels[i] = Level {
+                forward: previous.forward,
+                span: previous.span - skipped,
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: new,
+                span: skipped + 1,
+            };
+        }
+        for (i, &previous) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[previous].levels[i].span += 1;
+        }
+
+        let next = self.forward(new, 0);
+        if next != NIL {
+            self.nodes[next].backward = new;
+        }
+        self.len += 1;
+    }
+
+    /// Removes the element `(score, member)`, returning whether it was there.
+    pub fn remove(&mut self, score: f64, member: &str) -> bool {
+        let (update, _) = self.predecessors(score, member);
+        let node = self.forward(update[0], 0);
+        if node == NIL || self.nodes[node].score != score || self.nodes[node].member != member {
+            return false;
+        }
+
+        for (i, &previous) in update.iter().enumerate().take(self.level) {
+            if self.forward(previous, i) == node {
+                let removed = self.nodes[node].levels[i];
+                let link = &mut self.nodes[previous].levels[i];
+                link.span = link.span + removed.span - 1;
+                link.forward = removed.forward;
+            } else {
+                self.nodes[previous].levels[i].span -= 1;
+            }
+        }
+        let next = self.forward(node, 0);
+        if next != NIL {
+            self.nodes[next].backward = self.nodes[node].backward;
+        }
+        while self.level > 1 && self.forward(HEADER, self.level - 1) == NIL {
+            self.level -= 1;
+        }
+
+        self.nodes[node].member = String::new();
+        self.free.push(node);
+        self.len -= 1;
+        true
+    }
+
+    /// Zero-based rank of the element `(score, member)`, if it is there.
+    pub fn rank(&self, score: f64, member: &str) -> Option<usize> {
+        let (update, rank) = self.predecessors(score, member);
+        let node = self.forward(update[0], 0);
+        (node != NIL && self.nodes[node].score == score && self.nodes[node].member == member)
+            .then_some(rank[0])
+    }
+
+    /// Node holding the element at zero-based `rank`.
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        let (mut node, mut traversed) = (HEADER, 0);
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(node, i);
+                let span = self.nodes[node].levels[i].span;
+                if next == NIL || traversed + span > rank + 1 {
+                    break;
+                }
+                traversed += span;
+                node = next;
+            }
+            if traversed == rank + 1 {
+                return Some(node);
+            }
+        }
+        None
+    }
+
+    /// Rank of the first element within `bounds`.
+    pub fn first_in(&self, bounds: &impl Bounds) -> Option<usize> {
+        let (mut node, mut rank) = (HEADER, 0);
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(node, i);
+                if next == NIL || bounds.above_min(self.nodes[next].score, &self.nodes[next].member)
+                {
+                    break;
+                }
+                rank += self.nodes[node].levels[i].span;
+                node = next;
+            }
+        }
+        let first = self.forward(node, 0);
+        (first != NIL && bounds.below_max(self.nodes[first].score, &self.nodes[first].member))
+            .then_some(rank)
+    }
+
+    /// Rank of the last element within `bounds`.
+    pub fn last_in(&self, bounds: &impl Bounds) -> Option<usize> {
+        let (mut node, mut rank) = (HEADER, 0);
+        for i in (0..self.level).rev() {
+            loop {
+                let next = self.forward(node, i);
+                if next == NIL
+                    || !bounds.below_max(self.nodes[next].score, &self.nodes[next].member)
+                {
+                    break;
+                }
+                rank += self.nodes[node].levels[i].span;
+                node = next;
+            }
+        }
+        (node != HEADER && bounds.above_min(self.nodes[node].score, &self.nodes[node].member))
+            .then(|| rank - 1)
+    }
+
+    /// Elements from zero-based `rank` on, towards the tail or, if `reverse`
+    /// is set, towards the head.
+    pub fn iter_from(&self, rank: usize, reverse: bool) -> Iter<'_> {
+        Iter {
+            list: self,
+            node: self.node_at(rank).unwrap_or(NIL),
+            reverse,
+        }
+    }
+}
+
+/// Elements of a [`SkipList`] walked from a given rank.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    node: usize,
+    reverse: bool,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.node == NIL {
+            return None;
+        }
+        let node = &self.list.nodes[self.node];
+        self.node = if self.reverse {
+            node.backward
+        } else {
+            node.levels[0].forward
+        };
+        Some((&node.member, node.score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scores from `min` to `max` inclusive, whatever the member.
+    struct ScoreBetween(f64, f64);
+
+    impl Bounds for ScoreBetween {
+        fn above_min(&self, score: f64, _: &str) -> bool {
+            score >= self.0
+        }
+
+        fn below_max(&self, score: f64, _: &str) -> bool {
+            score <= self.1
+        }
+    }
+
+    fn elements(iter: Iter<'_>) -> Vec<(f64, String)> {
+        iter.map(|(member, score)| (score, member.to_string()))
+            .collect()
+    }
+
+    /// Checks `list` against `model`, which is sorted by score, then member.
+    fn assert_matches(list: &SkipList, model: &[(f64, String)]) {
+        assert_eq!(list.len, model.len());
+        assert_eq!(elements(list.iter_from(0, false)), model);
+        let reversed: Vec<_> = model.iter().rev().cloned().collect();
+        assert_eq!(
+            elements(list.iter_from(model.len().wrapping_sub(1), true)),
+            reversed
+        );
+
+        for (rank, (score, member)) in model.iter().enumerate() {
+            assert_eq!(list.rank(*score, member), Some(rank));
+            assert_eq!(
+                elements(list.iter_from(rank, false)).first(),
+                Some(&(*score, member.clone()))
+            );
+        }
+        assert_eq!(list.rank(0.5, "m0"), None);
+
+        for (min, max) in [(0.0, 9.0), (2.0, 4.0), (3.0, 3.0), (4.5, 4.9), (8.0, 20.0)] {
+            let within = |(score, _): &(f64, String)| (min..=max).contains(score);
+            let first = model.iter().position(within);
+            let last = model.iter().rposition(within);
+            assert_eq!(list.first_in(&ScoreBetween(min, max)), first);
+            assert_eq!(list.last_in(&ScoreBetween(min, max)), last);
+        }
+    }
+
+    #[test]
+    fn matches_a_sorted_vec_under_random_inserts_and_removes() {
+        let mut list = SkipList::default();
+        let mut model: Vec<(f64, String)> = Vec::new();
+
+        for round in 0..2000 {
+            let member = format!("m{}", random::below(64));
+            // Few distinct scores, so plenty of elements tie and are
+            // ordered by member.
+            let score = random::below(10) as f64;
+            match model.iter().position(|(_, m)| *m == member) {
+                Some(i) if random::below(3) == 0 || round % 500 > 400 => {
+                    let (score, member) = model.remove(i);
+                    assert!(list.remove(score, &member));
+                    assert!(!list.remove(score, &member));
+                }
+                Some(_) => {}
+                None => {
+                    list.insert(score, member.clone());
+                    let at = model.partition_point(|(s, m)| (*s, m) < (score, &member));
+                    model.insert(at, (score, member));
+                }
+            }
+            if round % 50 == 0 {
+                assert_matches(&list, &model);
+            }
+        }
+        assert_matches(&list, &model);
+    }
+
+    #[test]
+    fn reuses_the_slots_of_removed_nodes() {
+        let mut list = SkipList::default();
+        for i in 0..100 {
+            list.insert(i as f64, format!("m{i}"));
+        }
+        for i in 0..100 {
+            assert!(list.remove(i as f64, &format!("m{i}")));
+        }
+        assert_matches(&list, &[]);
+
+        let nodes = list.nodes.len();
+        for i in 0..100 {
+            list.insert(i as f64, format!("m{i}"));
+        }
+        assert_eq!(list.nodes.len(), nodes);
+        let model: Vec<_> = (0..100).map(|i| (i as f64, format!("m{i}"))).collect();
+        assert_matches(&list, &model);
+    }
+}
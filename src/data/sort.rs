@@ -0,0 +1,252 @@
+use std::cmp::Ordering;
+
+use anyhow::bail;
+
+use crate::data::{
+    Store,
+    list::ListEnd,
+    notify::NotifyFlags,
+    quicklist::ListValue,
+    record::{RecordData, RecordType, WRONGTYPE},
+};
+
+/// Options of SORT and SORT_RO.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SortOptions {
+    /// Pattern of the keys to sort by in place of the elements themselves.
+    /// A pattern without `*` leaves the elements unsorted.
+    pub by: Option<String>,
+    /// Offset and count of the elements to return.
+    pub limit: Option<(i64, i64)>,
+    /// Patterns of the keys to return in place of each element.
+    pub get: Vec<String>,
+    pub descending: bool,
+    /// Compare as strings rather than as numbers.
+    pub alpha: bool,
+}
+
+/// An element with the value it is sorted by.
+struct SortItem {
+    element: String,
+    /// The `BY` value for ALPHA sorts, `None` if its key is missing.
+    key: Option<String>,
+    score: f64,
+}
+
+/// Parses a value SORT compares as a number. Like `strtod`, an empty string
+/// reads as 0.
+fn parse_sort_score(value: &str) -> Option<f64> {
+    if value.is_empty() {
+        return Some(0.0);
+    }
+    value
+        .trim_start()
+        .parse::<f64>()
+        .ok()
+        .filter(|score| !score.is_nan())
+}
+
+impl Store {
+    /// The value a SORT pattern names for `element`: `#` is the element
+    /// itself; otherwise the first `*` is replaced by the element to give a
+    /// string key, or a hash key and field if followed by `->field`.
+    fn lookup_by_pattern(&self, pattern: &str, element: &str) -> Option<String> {
+        if pattern == "#" {
+            return Some(element.to_string());
+        }
+        let star = pattern.find('*')?;
+        let (key_pattern, field) = match pattern[star + 1..].find("->") {
+            Some(arrow) if star + 1 + arrow + 2 < pattern.len() => {
+                let arrow = star + 1 + arrow;
+                (&pattern[..arrow], Some(&pattern[arrow + 2..]))
+            }
+            _ => (pattern, None),
+        };
+        let key = format!(
+            "{}{element}{}",
+            &key_pattern[..star],
+            &key_pattern[star + 1..]
+        );
+
+        self.expire_if_needed(&key);
+        let entry = self.entries.get(&key)?;
+        match (&entry.record, field) {
+            (RecordType::String(value), None) => {
+                Some(String::from_utf8_lossy(&value.to_bytes()).into_owned())
+            }
+            (RecordType::Hash(hash), Some(field)) => hash
+                .get(field.as_bytes())
+                .map(|value| String::from_utf8_lossy(value).into_owned()),
+            _ => None,
+        }
+    }
+
+    /// Elements of the list, set or sorted set at `key`, sorted and
+    /// limited, each replaced by the values of the `GET` patterns if there
+    /// are any. `storing` makes unsorted sets come out in a fixed order.
+    fn sorted(
+        &self,
+        key: &str,
+        options: &SortOptions,
+        storing: bool,
+    ) -> anyhow::Result<Vec<Option<String>>> {
+        let mut sort = options
+            .by
+            .as_ref()
+            .is_none_or(|pattern| pattern.contains('*'));
+        let mut alpha = options.alpha;
+        let mut by = options.by.as_deref().filter(|_| sort);
+        let mut limit = options.limit;
+
+        self.expire_if_needed(key);
+        let mut elements: Vec<String> = match self.entries.get(key).as_deref() {
+            None => Vec::new(),
+            Some(entry) => match &entry.record {
+                RecordType::List(list) => list
+                    .iter()
+                    .map(|element| String::from_utf8_lossy(element).into_owned())
+                    .collect(),
+                RecordType::Set(set) => {
+                    // A set's order is arbitrary, which a stored result
+                    // shouldn't depend on.
+                    if !sort && storing {
+                        (sort, alpha, by) = (true, true, None);
+                    }
+                    set.iter()
+                        .map(|member| String::from_utf8_lossy(&member).into_owned())
+                        .collect()
+                }
+                RecordType::ZSet(zset) if sort => {
+                    zset.iter().map(|(member, _)| member.clone()).collect()
+                }
+                // Unsorted, a sorted set keeps its own order, so LIMIT picks
+                // ranks straight off the skiplist.
+                RecordType::ZSet(zset) => {
+                    let (start, stop) = match limit.take() {
+                        None => (0, -1),
+                        Some((_, 0)) => return Ok(Vec::new()),
+                        Some((offset, count)) if count < 0 => (offset.max(0), -1),
+                        Some((offset, count)) => {
+                            let start = offset.max(0);
+                            (start, start.saturating_add(count - 1))
+                        }
+                    };
+                    zset.range_by_rank(start, stop, options.descending)
+                        .into_iter()
+                        .map(|(member, _)| member)
+                        .collect()
+                }
+                _ => bail!(WRONGTYPE),
+            },
+        };
+
+        if sort {
+            let mut items = Vec::with_capacity(elements.len());
+            for element in elements {
+                let value = match by {
+                    Some(pattern) => self.lookup_by_pattern(pattern, &element),
+                    None => None,
+                };
+                // Elements whose BY key is missing score 0.
+                let score = match (&value, by) {
+                    _ if alpha => 0.0,
+                    (None, Some(_)) => 0.0,
+                    (value, _) => match parse_sort_score(value.as_deref().unwrap_or(&element)) {
+                        Some(score) => score,
+                        None => bail!("One or more scores can't be converted into double"),
+                    },
+                };
+                items.push(SortItem {
+                    element,
+                    key: value,
+                    score,
+                });
+            }
+
+            items.sort_by(|a, b| {
+                let order = if !alpha {
+                    a.score
+                        .partial_cmp(&b.score)
+                        .unwrap_or(Ordering::Equal)
+                        .then_with(|| a.element.cmp(&b.element))
+                } else if by.is_some() {
+                    a.key.cmp(&b.key)
+                } else {
+                    a.element.cmp(&b.element)
+                };
+                if options.descending {
+                    order.reverse()
+                } else {
+                    order
+                }
+            });
+            elements = items.into_iter().map(|item| item.element).collect();
+        }
+
+        if let Some((offset, count)) = limit {
+            let start = (offset.max(0) as usize).min(elements.len());
+            let end = if count < 0 {
+                elements.len()
+            } else {
+                start.saturating_add(count as usize).min(elements.len())
+            };
+            elements = elements.drain(start..end).collect();
+        }
+
+        if options.get.is_empty() {
+            return Ok(elements.into_iter().map(Some).collect());
+        }
+        Ok(elements
+            .iter()
+            .flat_map(|element| {
+                options
+                    .get
+                    .iter()
+                    .map(|pattern| self.lookup_by_pattern(pattern, element))
+            })
+            .collect())
+    }
+
+    pub fn sort(&self, key: &str, options: &SortOptions) -> anyhow::Result<Vec<Option<String>>> {
+        let _guard = self.lock_shared();
+        self.sorted(key, options, false)
+    }
+
+    /// Stores what SORT would return as a list at `destination`, missing
+    /// values as empty strings, and returns its length.
+    pub fn sort_store(
+        &self,
+        key: &str,
+        options: &SortOptions,
+        destination: &str,
+    ) -> anyhow::Result<usize> {
+        let len = {
+            let _guard = self.lock_exclusive();
+            let values = self.sorted(key, options, true)?;
+
+            self.expire_if_needed(destination);
+            if values.is_empty() {
+                if self.entries.remove(destination).is_some() {
+                    self.notify_keyspace_event(NotifyFlags::GENERIC, "del", destination);
+                }
+                return Ok(0);
+            }
+
+            let limit = self.listpack_limit();
+            let mut list = ListValue::default();
+            for value in &values {
+                let value = value.as_deref().unwrap_or_default();
+                list.push(ListEnd::Right, value.as_bytes(), limit);
+            }
+            self.entries.insert(
+                destination.to_string(),
+                RecordData::new(RecordType::List(list), None),
+            );
+            self.notify_keyspace_event(NotifyFlags::LIST, "sortstore", destination);
+            values.len()
+        };
+
+        self.serve_blocked(destination);
+        Ok(len)
+    }
+}
@@ -1,212 +1,585 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    sync::Arc,
-    time::SystemTime,
+    collections::BTreeSet,
+    sync::{Arc, Mutex, PoisonError, RwLockReadGuard, RwLockWriteGuard},
+    time::Duration,
 };
 
-use dashmap::DashMap;
-use tokio::sync::{RwLock, oneshot};
-
+use crate::clock::Clock;
+use crate::config::Config;
 use crate::data::{
-    record::{RecordData, RecordType},
-    stream::{StramValue, StreamEntryID, StreamRecord},
+    blocking::BlockedClients,
+    notify::NotifyFlags,
+    record::{RecordData, RecordType, WRONGTYPE},
+    stream::{
+        ReadFrom, StramValue, StreamEntryID, StreamInfo, StreamInfoFull, StreamRecord, TrimOptions,
+    },
+    string::{SetCondition, SetExpiration, SetOptions},
 };
+use crate::pubsub::PubSub;
+use dashmap::{DashMap, mapref::entry::Entry};
 
 #[derive(Debug, Default, Clone)]
 pub struct Store {
-    entries: Arc<DashMap<String, RecordData>>,
-    waiters: Arc<RwLock<HashMap<String, VecDeque<oneshot::Sender<()>>>>>,
+    pub(super) entries: Arc<DashMap<String, RecordData>>,
+    /// Held shared by single-key writes and exclusively by commands that
+    /// change several keys at once, so those appear atomic to other clients.
+    keyspace: Arc<std::sync::RwLock<()>>,
+    pub(super) blocked: Arc<BlockedClients>,
+    /// Keys with a TTL or hash field TTL, by when it falls due. Overwriting
+    /// a key or changing its TTL leaves a stale entry behind, which is
+    /// harmless: the key is only checked again once it comes up.
+    deadlines: Arc<Mutex<BTreeSet<(u64, String)>>>,
+    config: Arc<Config>,
+    pubsub: Arc<PubSub>,
+    clock: Arc<Clock>,
 }
 
 impl Store {
-    async fn notify_waiters(&self, key: &str) {
-        let mut waiters = self.waiters.write().await;
-        let Some(queue) = waiters.get_mut(key) else {
-            return;
-        };
-
-        while let Some(waiter) = queue.pop_front() {
-            if waiter.send(()).is_ok() {
-                return;
-            }
+    /// A store driven by `clock`, such as a [`Clock::manual`] one.
+    #[cfg(test)]
+    pub fn with_clock(clock: Clock) -> Self {
+        Self {
+            clock: Arc::new(clock),
+            ..Default::default()
         }
     }
 
-    pub fn set(&self, key: String, value: String, duration: Option<SystemTime>) {
-        self.entries
-            .insert(key, RecordData::new(RecordType::String(value), duration));
+    pub fn clock(&self) -> &Clock {
+        &self.clock
     }
 
-    pub fn get(&self, key: &str) -> Option<String> {
-        if let Some(entry) = self.entries.get(key) {
-            if entry.is_expired() {
-                drop(entry);
-                self.entries.remove(key);
-                return None;
-            }
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
 
-            match &entry.record {
-                RecordType::String(value) => Some(value.clone()),
-                _ => None,
-            }
-        } else {
-            None
+    pub fn pubsub(&self) -> &PubSub {
+        &self.pubsub
+    }
+
+    pub(super) fn lock_shared(&self) -> RwLockReadGuard<'_, ()> {
+        self.keyspace.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(super) fn lock_exclusive(&self) -> RwLockWriteGuard<'_, ()> {
+        self.keyspace
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Publishes `event` for `key` on the keyspace and keyevent channels, as
+    /// far as `notify-keyspace-events` enables them.
+    pub(super) fn notify_keyspace_event(&self, class: NotifyFlags, event: &str, key: &str) {
+        let flags = self.config.notify_keyspace_events();
+        if !flags.contains(class) {
+            return;
+        }
+
+        if flags.contains(NotifyFlags::KEYSPACE) {
+            self.pubsub.publish(&format!("__keyspace@0__:{key}"), event);
+        }
+        if flags.contains(NotifyFlags::KEYEVENT) {
+            self.pubsub.publish(&format!("__keyevent@0__:{event}"), key);
         }
     }
 
-    pub async fn rpush(&self, key: String, value: String) -> usize {
-        let mut entry = self
+    /// Drops `key` if its TTL has passed, firing the `expired` event, or
+    /// else any hash fields whose TTL has.
+    pub(super) fn expire_if_needed(&self, key: &str) {
+        let now = self.clock.now_ms();
+        if self
             .entries
-            .entry(key.clone())
-            .or_insert_with(|| RecordData::new(RecordType::List(VecDeque::new()), None));
+            .remove_if(key, |_, entry| entry.is_expired(now))
+            .is_some()
+        {
+            self.notify_keyspace_event(NotifyFlags::EXPIRED, "expired", key);
+            return;
+        }
+        self.expire_hash_fields(key, now);
+    }
 
-        match &mut entry.record {
-            RecordType::List(list) => {
-                list.push_back(value);
+    /// Has the active expire cycle look at `key` once `at` passes.
+    pub(super) fn track_deadline(&self, key: &str, at: u64) {
+        self.deadlines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert((at, key.to_string()));
+    }
 
-                self.notify_waiters(&key).await;
+    /// Deletes `key` if it holds a collection that has become empty, firing
+    /// the `del` event. Called after the command's own event.
+    pub(super) fn delete_if_empty(&self, key: &str) {
+        if self
+            .entries
+            .remove_if(key, |_, entry| entry.record.is_empty_collection())
+            .is_some()
+        {
+            self.notify_keyspace_event(NotifyFlags::GENERIC, "del", key);
+        }
+    }
 
-                list.len()
+    /// Evicts every key and hash field whose TTL has passed, so expirations
+    /// are observed even for keys that are never touched again.
+    pub fn active_expire_cycle(&self) {
+        let now = self.clock.refresh();
+        let mut due = Vec::new();
+        {
+            let mut deadlines = self
+                .deadlines
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            while let Some((at, _)) = deadlines.first()
+                && now > *at
+            {
+                let (_, key) = deadlines.pop_first().expect("checked above");
+                due.push(key);
             }
-            _ => 0,
+        }
+
+        for key in due {
+            self.expire_if_needed(&key);
         }
     }
 
-    pub async fn lpush(&self, key: String, value: String) -> usize {
-        let mut entry = self
-            .entries
-            .entry(key.clone())
-            .or_insert_with(|| RecordData::new(RecordType::List(VecDeque::new()), None));
+    /// Stores a string under `key`, returning whether it was written (the
+    /// condition held) and, when `options.get` is set, the previous value.
+    pub fn set(
+        &self,
+        key: String,
+        value: Vec<u8>,
+        options: SetOptions,
+    ) -> anyhow::Result<(bool, Option<Vec<u8>>)> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(&key);
+
+        let entry = self.entries.entry(key.clone());
+        let previous = match &entry {
+            Entry::Occupied(occupied) => Some(occupied.get()),
+            Entry::Vacant(_) => None,
+        };
+        let old_value = match previous.map(|data| &data.record) {
+            Some(RecordType::String(value)) if options.get => Some(value.to_vec()),
+            Some(_) if options.get => {
+                anyhow::bail!(WRONGTYPE)
+            }
+            _ => None,
+        };
 
-        match &mut entry.record {
-            RecordType::List(list) => {
-                list.push_front(value);
+        let apply = match options.condition {
+            None => true,
+            Some(SetCondition::IfMissing) => previous.is_none(),
+            Some(SetCondition::IfExists) => previous.is_some(),
+        };
+        if !apply {
+            return Ok((false, old_value));
+        }
+
+        let expiration = match options.expiration {
+            SetExpiration::Clear => None,
+            SetExpiration::Keep => previous.and_then(RecordData::expiration),
+            SetExpiration::At(at) => {
+                self.track_deadline(&key, at);
+                Some(at)
+            }
+        };
+        entry.insert(RecordData::new(
+            RecordType::String(value.into()),
+            expiration,
+        ));
+
+        self.notify_keyspace_event(NotifyFlags::STRING, "set", &key);
+        Ok((true, old_value))
+    }
+
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(None);
+        };
 
-                self.notify_waiters(&key).await;
+        match &entry.record {
+            RecordType::String(value) => Ok(Some(value.to_vec())),
+            _ => anyhow::bail!(WRONGTYPE),
+        }
+    }
 
-                list.len()
+    pub fn del(&self, keys: &[String]) -> usize {
+        let _guard = self.lock_exclusive();
+        let mut deleted = 0;
+        for key in keys {
+            self.expire_if_needed(key);
+            if self.entries.remove(key).is_some() {
+                self.notify_keyspace_event(NotifyFlags::GENERIC, "del", key);
+                deleted += 1;
             }
-            _ => 0,
         }
+        deleted
     }
 
-    pub fn lpop(&self, key: &str) -> Option<String> {
-        let mut entry = self.entries.get_mut(key)?;
-        let RecordType::List(list) = &mut entry.record else {
-            return None;
+    /// Counts how many of `keys` exist, counting repeated keys each time.
+    pub fn exists(&self, keys: &[String]) -> usize {
+        keys.iter()
+            .filter(|key| {
+                self.expire_if_needed(key);
+                self.entries.contains_key(key.as_str())
+            })
+            .count()
+    }
+
+    pub fn type_of(&self, key: &str) -> &'static str {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return "none";
         };
 
-        list.pop_front()
+        entry.type_name()
     }
 
-    pub async fn blpop(&self, key: &str, deadline: Option<SystemTime>) -> Option<(String, String)> {
-        loop {
-            if let Some(value) = self.lpop(key) {
-                return Some((key.to_string(), value));
-            }
+    /// How the value at `key` is encoded, or `None` if there is no key.
+    pub fn object_encoding(&self, key: &str) -> Option<&'static str> {
+        self.expire_if_needed(key);
+        self.entries.get(key).map(|entry| entry.record.encoding())
+    }
 
-            let receiver = {
-                let mut waiters = self.waiters.write().await;
-                let queue = waiters.entry(key.to_string()).or_default();
-                let (sender, receiver) = oneshot::channel();
-                queue.push_back(sender);
-                receiver
+    /// Adds an entry to the stream at `key`, creating it unless
+    /// `no_mkstream` is set, in which case `None` is returned for a missing
+    /// key. The stream is then trimmed if `trim` is given.
+    pub fn xadd(
+        &self,
+        key: String,
+        field: String,
+        value: Vec<(String, String)>,
+        no_mkstream: bool,
+        trim: Option<&TrimOptions>,
+    ) -> anyhow::Result<Option<StreamEntryID>> {
+        let (id, trimmed) = {
+            let _guard = self.lock_shared();
+            self.expire_if_needed(&key);
+            if no_mkstream && !self.entries.contains_key(&key) {
+                return Ok(None);
+            }
+            let add = |stream_record: &mut StreamRecord| -> anyhow::Result<_> {
+                let id = stream_record.xadd(field, value, self.clock.now_ms())?;
+                let trimmed = trim.map_or(0, |trim| {
+                    stream_record.trim(trim, self.config.stream_node_max_entries())
+                });
+                Ok((id, trimmed))
             };
 
-            if let Some(dl) = deadline {
-                if SystemTime::now() >= dl {
-                    return None;
+            match self.entries.entry(key.clone()) {
+                Entry::Occupied(mut entry) => {
+                    let RecordType::Stream(stream_record) = &mut entry.get_mut().record else {
+                        anyhow::bail!(WRONGTYPE);
+                    };
+                    add(stream_record)?
                 }
-
-                let remaining = dl.duration_since(SystemTime::now()).ok()?;
-                if tokio::time::timeout(remaining, receiver).await.is_err() {
-                    return None;
+                // Only create the stream once the ID has been accepted.
+                Entry::Vacant(entry) => {
+                    let mut stream_record = StreamRecord::default();
+                    let added = add(&mut stream_record)?;
+                    entry.insert(RecordData::new(RecordType::Stream(stream_record), None));
+                    added
                 }
-            } else {
-                receiver.await.ok()?;
             }
+        };
+
+        self.notify_keyspace_event(NotifyFlags::STREAM, "xadd", &key);
+        if trimmed > 0 {
+            self.notify_keyspace_event(NotifyFlags::STREAM, "xtrim", &key);
         }
+        self.serve_blocked(&key);
+        Ok(Some(id))
     }
 
-    pub fn lrange(&self, key: &str, start: isize, stop: isize) -> Vec<String> {
-        let Some(entry) = self.entries.get(key) else {
-            return Vec::new();
+    /// Number of entries in the stream at `key`.
+    pub fn xlen(&self, key: &str) -> anyhow::Result<usize> {
+        self.expire_if_needed(key);
+        match self.entries.get(key).as_deref().map(|entry| &entry.record) {
+            None => Ok(0),
+            Some(RecordType::Stream(stream_record)) => Ok(stream_record.len()),
+            Some(_) => anyhow::bail!(WRONGTYPE),
+        }
+    }
+
+    /// Deletes the entries of the stream at `key` with the given IDs,
+    /// returning how many there were.
+    pub fn xdel(&self, key: &str, ids: &[StreamEntryID]) -> anyhow::Result<usize> {
+        let deleted = {
+            let _guard = self.lock_shared();
+            self.expire_if_needed(key);
+            let Some(mut entry) = self.entries.get_mut(key) else {
+                return Ok(0);
+            };
+            let RecordType::Stream(stream_record) = &mut entry.record else {
+                anyhow::bail!(WRONGTYPE);
+            };
+            stream_record.xdel(ids)
         };
-        let RecordType::List(list) = &entry.record else {
-            return Vec::new();
+
+        if deleted > 0 {
+            self.notify_keyspace_event(NotifyFlags::STREAM, "xdel", key);
+        }
+        Ok(deleted)
+    }
+
+    /// Trims the stream at `key`, returning how many entries were evicted.
+    pub fn xtrim(&self, key: &str, trim: &TrimOptions) -> anyhow::Result<usize> {
+        let trimmed = {
+            let _guard = self.lock_shared();
+            self.expire_if_needed(key);
+            let Some(mut entry) = self.entries.get_mut(key) else {
+                return Ok(0);
+            };
+            let RecordType::Stream(stream_record) = &mut entry.record else {
+                anyhow::bail!(WRONGTYPE);
+            };
+            stream_record.trim(trim, self.config.stream_node_max_entries())
         };
 
-        let len = list.len() as isize;
-        let start = if start < 0 { len + start } else { start }.max(0) as usize;
-        let stop = if stop < 0 { len + stop } else { stop }.min(len - 1) as usize;
+        if trimmed > 0 {
+            self.notify_keyspace_event(NotifyFlags::STREAM, "xtrim", key);
+        }
+        Ok(trimmed)
+    }
+
+    /// Sets the last ID of the stream at `key`, as
+    /// [`StreamRecord::set_id`] does.
+    pub fn xsetid(
+        &self,
+        key: &str,
+        last_id: StreamEntryID,
+        entries_added: Option<u64>,
+        max_deleted_id: Option<StreamEntryID>,
+    ) -> anyhow::Result<()> {
+        {
+            let _guard = self.lock_shared();
+            self.expire_if_needed(key);
+            let Some(mut entry) = self.entries.get_mut(key) else {
+                anyhow::bail!("no such key");
+            };
+            let RecordType::Stream(stream_record) = &mut entry.record else {
+                anyhow::bail!(WRONGTYPE);
+            };
+            stream_record.set_id(last_id, entries_added, max_deleted_id)?;
+        }
 
-        list.range(start..=stop).map(|s| s.to_owned()).collect()
+        self.notify_keyspace_event(NotifyFlags::STREAM, "xsetid", key);
+        Ok(())
     }
 
-    pub fn llen(&self, key: &str) -> usize {
+    /// What XINFO STREAM reports about the stream at `key`.
+    pub fn xinfo_stream(&self, key: &str) -> anyhow::Result<StreamInfo> {
+        self.expire_if_needed(key);
         let Some(entry) = self.entries.get(key) else {
-            return 0;
+            anyhow::bail!("no such key");
         };
-        let RecordType::List(list) = &entry.record else {
-            return 0;
+        let RecordType::Stream(stream_record) = &entry.record else {
+            anyhow::bail!(WRONGTYPE);
         };
 
-        list.len()
+        Ok(stream_record.info(self.config.stream_node_max_entries()))
     }
 
-    pub fn type_of(&self, key: &str) -> &'static str {
+    /// What XINFO STREAM FULL reports about the stream at `key`, listing
+    /// up to `count` entries and pending entries, all of them if 0.
+    pub fn xinfo_stream_full(&self, key: &str, count: usize) -> anyhow::Result<StreamInfoFull> {
+        self.expire_if_needed(key);
         let Some(entry) = self.entries.get(key) else {
-            return "none";
+            anyhow::bail!("no such key");
+        };
+        let RecordType::Stream(stream_record) = &entry.record else {
+            anyhow::bail!(WRONGTYPE);
         };
 
-        entry.type_name()
+        let count = if count == 0 { usize::MAX } else { count };
+        Ok(StreamInfoFull {
+            info: stream_record.info(self.config.stream_node_max_entries()),
+            entries: stream_record.xrange(
+                &StreamEntryID::MIN,
+                &StreamEntryID::MAX,
+                false,
+                Some(count),
+            ),
+            groups: stream_record.group_details(count),
+        })
     }
 
-    pub fn xadd(
+    /// Entries of the stream at `key` from `start` to `end` inclusive, as
+    /// [`StreamRecord::xrange`] returns them.
+    pub fn xrange(
         &self,
-        key: String,
-        field: String,
-        value: HashMap<String, String>,
-    ) -> anyhow::Result<StreamEntryID> {
-        let mut entry = self
-            .entries
-            .entry(key)
-            .or_insert_with(|| RecordData::new(RecordType::Stream(StreamRecord::default()), None));
-
-        let RecordType::Stream(stream_record) = &mut entry.record else {
-            anyhow::bail!("WRONGTYPE Operation against a key holding the wrong kind of value");
+        key: &str,
+        start: &StreamEntryID,
+        end: &StreamEntryID,
+        reverse: bool,
+        count: Option<usize>,
+    ) -> anyhow::Result<StramValue> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(StramValue::default());
         };
-        Ok(stream_record.xadd(field, value)?)
-    }
-
-    pub fn xrange(&self, key: &str, start: String, end: String) -> anyhow::Result<StramValue> {
-        let entry = self
-            .entries
-            .get(key)
-            .ok_or_else(|| anyhow::anyhow!("no such key"))?;
 
         let RecordType::Stream(stream_record) = &entry.record else {
-            anyhow::bail!("WRONGTYPE Operation against a key holding the wrong kind of value");
+            anyhow::bail!(WRONGTYPE);
         };
 
-        Ok(stream_record.xrange(start, end)?)
+        Ok(stream_record.xrange(start, end, reverse, count))
     }
 
-    pub fn xread(&self, key_id: Vec<(String, String)>) -> anyhow::Result<Vec<StramValue>> {
+    /// Up to `count` entries of each stream after the position given for
+    /// it, leaving out streams with none.
+    pub fn xread(
+        &self,
+        streams: &[(String, ReadFrom)],
+        count: Option<usize>,
+    ) -> anyhow::Result<Vec<(String, StramValue)>> {
+        let _guard = self.lock_shared();
         let mut result = Vec::new();
-        for (key, id) in key_id {
-            let entry = self
-                .entries
-                .get(&key)
-                .ok_or_else(|| anyhow::anyhow!("no such key"))?;
-
+        for (key, from) in streams {
+            self.expire_if_needed(key);
+            let Some(entry) = self.entries.get(key) else {
+                continue;
+            };
             let RecordType::Stream(stream_record) = &entry.record else {
-                anyhow::bail!("WRONGTYPE Operation against a key holding the wrong kind of value");
+                anyhow::bail!(WRONGTYPE);
             };
 
-            result.push(stream_record.xread(id)?);
+            let entries = stream_record.xread(from, count);
+            if !entries.0.is_empty() {
+                result.push((key.clone(), entries));
+            }
         }
 
         Ok(result)
     }
+
+    /// [`Store::xread`] that waits up to `timeout`, forever if `None`, for
+    /// entries to be added to one of the streams. `$` is taken as the last
+    /// ID of the stream when the wait starts.
+    pub async fn bxread(
+        &self,
+        client_id: u64,
+        streams: Vec<(String, ReadFrom)>,
+        count: Option<usize>,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<Option<Vec<(String, StramValue)>>> {
+        let mut resolved = Vec::with_capacity(streams.len());
+        for (key, from) in streams {
+            let from = match from {
+                ReadFrom::New => {
+                    self.expire_if_needed(&key);
+                    let last_id = match self.entries.get(&key).as_deref().map(|entry| &entry.record)
+                    {
+                        None => StreamEntryID::default(),
+                        Some(RecordType::Stream(stream_record)) => stream_record.last_id().clone(),
+                        Some(_) => anyhow::bail!(WRONGTYPE),
+                    };
+                    ReadFrom::After(last_id)
+                }
+                from => from,
+            };
+            resolved.push((key, from));
+        }
+
+        let keys = resolved.iter().map(|(key, _)| key.clone()).collect();
+        self.block_on(client_id, keys, None, timeout, move |store| {
+            let result = store.xread(&resolved, count)?;
+            Ok((!result.is_empty()).then_some(result))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::data::ListEnd;
+
+    const START_MS: u64 = 1_000_000;
+
+    fn store() -> Store {
+        Store::with_clock(Clock::manual(START_MS))
+    }
+
+    fn set_expiring_at(store: &Store, key: &str, at: u64) {
+        let options = SetOptions {
+            expiration: SetExpiration::At(at),
+            ..Default::default()
+        };
+        store
+            .set(key.to_string(), b"value".to_vec(), options)
+            .unwrap();
+    }
+
+    #[test]
+    fn keys_expire_once_the_clock_passes_their_ttl() {
+        let store = store();
+        set_expiring_at(&store, "key", START_MS + 1000);
+
+        store.clock().advance(Duration::from_millis(1000));
+        assert_eq!(store.get("key").unwrap(), Some(b"value".to_vec()));
+
+        store.clock().advance(Duration::from_millis(1));
+        assert_eq!(store.get("key").unwrap(), None);
+    }
+
+    #[test]
+    fn active_expire_cycle_evicts_untouched_keys() {
+        let store = store();
+        set_expiring_at(&store, "key", START_MS + 1000);
+
+        store.clock().advance(Duration::from_millis(1001));
+        store.active_expire_cycle();
+        assert!(!store.entries.contains_key("key"));
+    }
+
+    #[tokio::test]
+    async fn blocking_pop_times_out_when_the_clock_passes_the_deadline() {
+        let store = store();
+        let waiter = tokio::spawn({
+            let store = store.clone();
+            async move {
+                let keys = vec!["list".to_string()];
+                let timeout = Some(Duration::from_secs(1));
+                store.bmpop(1, keys, ListEnd::Left, 1, timeout).await
+            }
+        });
+        // Let the waiter take its deadline before the clock moves.
+        tokio::task::yield_now().await;
+
+        store.clock().advance(Duration::from_millis(999));
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        store.clock().advance(Duration::from_millis(1));
+        assert_eq!(waiter.await.unwrap().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn blocking_pop_is_served_by_a_push_before_the_deadline() {
+        let store = store();
+        let waiter = tokio::spawn({
+            let store = store.clone();
+            async move {
+                let keys = vec!["list".to_string()];
+                let timeout = Some(Duration::from_secs(1));
+                store.bmpop(1, keys, ListEnd::Left, 1, timeout).await
+            }
+        });
+        tokio::task::yield_now().await;
+
+        store
+            .push(
+                "list".to_string(),
+                ListEnd::Right,
+                vec![b"element".to_vec()],
+                false,
+            )
+            .unwrap();
+        assert_eq!(
+            waiter.await.unwrap().unwrap(),
+            Some(("list".to_string(), vec![b"element".to_vec()]))
+        );
+    }
 }
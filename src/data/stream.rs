@@ -1,118 +1,324 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{collections::BTreeMap, fmt, ops::Bound};
 
 use thiserror::Error;
 
+use crate::data::consumer_group::{ConsumerGroup, GroupDetail};
+
+/// Fields and values of a stream entry, in the order they were added.
+pub type StreamFields = Vec<(String, String)>;
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct StreamRecord {
-    value: StramValue,
-    last_id: StreamEntryID,
+    pub(super) entries: BTreeMap<StreamEntryID, StreamFields>,
+    pub(super) last_id: StreamEntryID,
+    /// Entries ever added, counting those since deleted.
+    pub(super) entries_added: u64,
+    /// Greatest ID deleted with XDEL.
+    pub(super) max_deleted_id: StreamEntryID,
+    pub(super) groups: BTreeMap<String, ConsumerGroup>,
 }
 
 impl StreamRecord {
     pub fn xadd(
         &mut self,
         field: String,
-        value: HashMap<String, String>,
+        value: StreamFields,
+        now_ms: u64,
     ) -> Result<StreamEntryID, StreamRecordError> {
-        let entry_id = StreamEntryID::new(&field, &self.last_id)?;
-        if entry_id.ms == 0 && entry_id.seq == 0 {
+        if self.last_id == StreamEntryID::MAX {
+            return Err(StreamRecordError::Exhausted);
+        }
+        let entry_id = StreamEntryID::new(&field, &self.last_id, now_ms)?;
+        if entry_id == StreamEntryID::MIN {
             return Err(StreamRecordError::MustBeGreater00);
         }
         if entry_id <= self.last_id {
             return Err(StreamRecordError::EqualOrSmallerThanLastID);
         }
 
-        self.value.0.insert(field, value);
+        self.entries.insert(entry_id.clone(), value);
         self.last_id = entry_id.clone();
+        self.entries_added += 1;
         Ok(entry_id)
     }
 
-    pub fn xrange(&self, start: String, end: String) -> Result<StramValue, StreamRecordError> {
-        let start = if start == "-" {
-            self.value
-                .0
-                .first_key_value()
-                .map(|(k, _)| StreamEntryID::parse_id(k, &self.last_id))
-                .unwrap_or(Ok(StreamEntryID { ms: 0, seq: 0 }))?
-        } else {
-            start.try_into()?
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Deletes the entries with the given IDs, returning how many there
+    /// were. The last ID is kept, so later entries still have to exceed it.
+    pub fn xdel(&mut self, ids: &[StreamEntryID]) -> usize {
+        let mut deleted = 0;
+        for id in ids {
+            if self.entries.remove(id).is_some() {
+                if *id > self.max_deleted_id {
+                    self.max_deleted_id = id.clone();
+                }
+                deleted += 1;
+            }
+        }
+        deleted
+    }
+
+    /// Evicts the oldest entries as `options` asks, returning how many. An
+    /// approximate trim evicts entries `node_size` at a time, like the
+    /// nodes of a Redis stream, and leaves any it can't evict whole.
+    pub fn trim(&mut self, options: &TrimOptions, node_size: usize) -> usize {
+        let mut evict = match &options.strategy {
+            TrimStrategy::MaxLen(max_len) => self.entries.len().saturating_sub(*max_len),
+            TrimStrategy::MinId(min_id) => self.entries.range(..min_id).count(),
         };
-        let end = if end == "+" {
-            self.value
-                .0
-                .last_key_value()
-                .map(|(k, _)| StreamEntryID::parse_id(k, &self.last_id))
-                .unwrap_or(Ok(StreamEntryID {
-                    ms: u128::MAX,
-                    seq: u64::MAX,
-                }))?
+        if options.approximate {
+            let limit = match options.limit {
+                None => match node_size.saturating_mul(100) {
+                    limit @ 1..=10_000 => limit,
+                    _ => 10_000,
+                },
+                Some(0) => usize::MAX,
+                Some(limit) => limit,
+            };
+            let node_size = node_size.max(1);
+            evict = evict.min(limit) / node_size * node_size;
+        }
+
+        for _ in 0..evict {
+            self.entries.pop_first();
+        }
+        evict
+    }
+
+    /// Entries from `start` to `end` inclusive, from the end backwards if
+    /// `reverse` is set, and at most `count` of them if given.
+    pub fn xrange(
+        &self,
+        start: &StreamEntryID,
+        end: &StreamEntryID,
+        reverse: bool,
+        count: Option<usize>,
+    ) -> StramValue {
+        if start > end {
+            return StramValue::default();
+        }
+        let range = self.entries.range(start..=end);
+        let count = count.unwrap_or(usize::MAX);
+        let entries = if reverse {
+            range
+                .rev()
+                .take(count)
+                .map(|(id, fields)| (id.clone(), fields.clone()))
+                .collect()
         } else {
-            end.try_into()?
+            range
+                .take(count)
+                .map(|(id, fields)| (id.clone(), fields.clone()))
+                .collect()
         };
+        StramValue(entries)
+    }
 
-        let mut result = BTreeMap::new();
-        for (key, value) in &self.value.0 {
-            let entry_id = StreamEntryID::parse_id(key, &self.last_id)?;
-            if entry_id >= start && entry_id <= end {
-                result.insert(key.clone(), value.clone());
+    /// ID of the first entry, 0-0 if there are none.
+    pub fn first_id(&self) -> StreamEntryID {
+        self.entries
+            .first_key_value()
+            .map(|(id, _)| id.clone())
+            .unwrap_or_default()
+    }
+
+    /// ID of the last entry ever added, which later ones must exceed.
+    pub fn last_id(&self) -> &StreamEntryID {
+        &self.last_id
+    }
+
+    /// Sets the last ID, as XSETID does, along with the entries ever added
+    /// and the greatest deleted ID if given.
+    pub fn set_id(
+        &mut self,
+        last_id: StreamEntryID,
+        entries_added: Option<u64>,
+        max_deleted_id: Option<StreamEntryID>,
+    ) -> Result<(), StreamRecordError> {
+        if last_id < self.max_deleted_id {
+            return Err(StreamRecordError::SmallerThanMaxDeletedID);
+        }
+        if let Some((top_id, _)) = self.entries.last_key_value() {
+            if last_id < *top_id {
+                return Err(StreamRecordError::SmallerThanTopItem);
             }
+            if entries_added.is_some_and(|added| added < self.entries.len() as u64) {
+                return Err(StreamRecordError::EntriesAddedSmallerThanLength);
+            }
+        }
+
+        self.last_id = last_id;
+        if let Some(entries_added) = entries_added {
+            self.entries_added = entries_added;
+        }
+        if let Some(max_deleted_id) = max_deleted_id.filter(|id| *id != StreamEntryID::MIN) {
+            self.max_deleted_id = max_deleted_id;
         }
-        Ok(StramValue(result))
+        Ok(())
     }
 
-    pub fn xread(&self, id: String) -> Result<StramValue, StreamRecordError> {
-        let id = id.try_into()?;
+    /// What XINFO STREAM reports, with radix tree figures estimated as if
+    /// the entries were kept in nodes of `node_size`.
+    pub fn info(&self, node_size: usize) -> StreamInfo {
+        let radix_tree_keys = self.entries.len().div_ceil(node_size.max(1));
+        let entry = |(id, fields): (&StreamEntryID, &StreamFields)| (id.clone(), fields.clone());
+        StreamInfo {
+            length: self.entries.len(),
+            radix_tree_keys,
+            radix_tree_nodes: radix_tree_keys + 1,
+            last_generated_id: self.last_id.clone(),
+            max_deleted_id: self.max_deleted_id.clone(),
+            entries_added: self.entries_added,
+            first_id: self.first_id(),
+            groups: self.groups.len(),
+            first_entry: self.entries.first_key_value().map(entry),
+            last_entry: self.entries.last_key_value().map(entry),
+        }
+    }
 
-        let mut result = BTreeMap::new();
-        for value in &self.value.0 {
-            let entry_id = StreamEntryID::parse_id(value.0, &self.last_id)?;
-            if entry_id > id {
-                result.insert(value.0.clone(), value.1.clone());
+    /// Up to `count` entries after `from`, all of them if `None`.
+    pub fn xread(&self, from: &ReadFrom, count: Option<usize>) -> StramValue {
+        let after = match from {
+            ReadFrom::After(id) => id,
+            ReadFrom::New => &self.last_id,
+            ReadFrom::Last => {
+                return StramValue(
+                    self.entries
+                        .last_key_value()
+                        .map(|(id, fields)| (id.clone(), fields.clone()))
+                        .into_iter()
+                        .collect(),
+                );
             }
-        }
-        Ok(StramValue(result))
+        };
+
+        StramValue(
+            self.entries
+                .range((Bound::Excluded(after), Bound::Unbounded))
+                .take(count.unwrap_or(usize::MAX))
+                .map(|(id, fields)| (id.clone(), fields.clone()))
+                .collect(),
+        )
     }
 }
 
+/// What XINFO STREAM reports about a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub length: usize,
+    pub radix_tree_keys: usize,
+    pub radix_tree_nodes: usize,
+    pub last_generated_id: StreamEntryID,
+    pub max_deleted_id: StreamEntryID,
+    pub entries_added: u64,
+    pub first_id: StreamEntryID,
+    pub groups: usize,
+    pub first_entry: Option<(StreamEntryID, StreamFields)>,
+    pub last_entry: Option<(StreamEntryID, StreamFields)>,
+}
+
+/// What XINFO STREAM FULL reports about a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamInfoFull {
+    pub info: StreamInfo,
+    pub entries: StramValue,
+    pub groups: Vec<GroupDetail>,
+}
+
+/// Which entries trimming a stream evicts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrimStrategy {
+    /// All but this many of the newest (`MAXLEN`).
+    MaxLen(usize),
+    /// Those with a smaller ID (`MINID`).
+    MinId(StreamEntryID),
+}
+
+/// Trimming options of XADD and XTRIM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrimOptions {
+    pub strategy: TrimStrategy,
+    /// Only evict whole nodes, which may leave more entries (`~`).
+    pub approximate: bool,
+    /// Most entries an approximate trim evicts (`LIMIT`): `None` for 100
+    /// nodes' worth up to 10000, 0 for no limit.
+    pub limit: Option<usize>,
+}
+
+/// Where XREAD starts reading a stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadFrom {
+    /// Entries with a greater ID.
+    After(StreamEntryID),
+    /// Entries added from now on (`$`).
+    New,
+    /// The last entry (`+`).
+    Last,
+}
+
 #[derive(Debug, Error)]
 pub enum StreamRecordError {
-    #[error("Stream entry ID error: {0}")]
+    #[error(transparent)]
     StreamEntryIDError(#[from] StreamEntryIDError),
     #[error("The ID specified in XADD must be greater than 0-0")]
     MustBeGreater00,
     #[error("The ID specified in XADD is equal or smaller than the target stream top item")]
     EqualOrSmallerThanLastID,
+    #[error("The stream has exhausted the last possible ID, unable to add more items")]
+    Exhausted,
+    #[error("The ID specified in XSETID is smaller than current max_deleted_entry_id")]
+    SmallerThanMaxDeletedID,
+    #[error("The ID specified in XSETID is smaller than the target stream top item")]
+    SmallerThanTopItem,
+    #[error("The entries_added specified in XSETID is smaller than the target stream length")]
+    EntriesAddedSmallerThanLength,
 }
 
+/// Entries read from a stream, in the order they were asked for.
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
-pub struct StramValue(pub BTreeMap<String, HashMap<String, String>>);
+pub struct StramValue(pub Vec<(StreamEntryID, StreamFields)>);
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct StreamEntryID {
-    ms: u128,
+    ms: u64,
     seq: u64,
 }
 
 impl StreamEntryID {
-    pub fn new(source: &str, last_id: &StreamEntryID) -> Result<Self, StreamEntryIDError> {
+    pub const MIN: Self = Self { ms: 0, seq: 0 };
+    pub const MAX: Self = Self {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(
+        source: &str,
+        last_id: &StreamEntryID,
+        now_ms: u64,
+    ) -> Result<Self, StreamRecordError> {
         if source == "*" {
-            return Ok(Self::gen_id(last_id));
+            return Self::gen_id(last_id, now_ms).ok_or(StreamRecordError::Exhausted);
         }
 
         Self::parse_id(source, last_id)
     }
 
-    fn parse_id(source: &str, last_id: &StreamEntryID) -> Result<Self, StreamEntryIDError> {
+    fn parse_id(source: &str, last_id: &StreamEntryID) -> Result<Self, StreamRecordError> {
         let parts: Vec<&str> = source.split('-').collect();
         if parts.len() != 2 {
-            return Err(StreamEntryIDError::InvalidFormat);
+            return Err(StreamEntryIDError::InvalidFormat.into());
         }
 
         let ms = parts[0]
-            .parse::<u128>()
+            .parse::<u64>()
             .map_err(|_| StreamEntryIDError::InvalidFormat)?;
         let seq = if parts[1] == "*" {
+            // No sequence number is left after the last one at this ms.
             Self::gen_seq(ms, last_id.ms, last_id.seq)
+                .ok_or(StreamRecordError::EqualOrSmallerThanLastID)?
         } else {
             parts[1]
                 .parse::<u64>()
@@ -122,48 +328,85 @@ impl StreamEntryID {
         Ok(Self { ms, seq })
     }
 
-    fn gen_id(last_id: &StreamEntryID) -> Self {
-        let ms = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-        let seq = Self::gen_seq(ms, last_id.ms, last_id.seq);
-        Self { ms, seq }
+    /// Parses an ID given as `ms-seq` or, leaving the sequence number out,
+    /// as `ms`, which stands for `ms-missing_seq`.
+    pub fn parse(source: &str, missing_seq: u64) -> Result<Self, StreamEntryIDError> {
+        let (ms, seq) = match source.split_once('-') {
+            Some((ms, seq)) => (ms, seq.parse::<u64>().ok()),
+            None => (source, Some(missing_seq)),
+        };
+        match (ms.parse::<u64>(), seq) {
+            (Ok(ms), Some(seq)) => Ok(Self { ms, seq }),
+            _ => Err(StreamEntryIDError::InvalidFormat),
+        }
     }
 
-    fn gen_seq(ms: u128, last_ms: u128, last_seq: u64) -> u64 {
-        if ms == last_ms { last_seq + 1 } else { 0 }
+    /// Parses one end of a range, which may also be `-` for the smallest
+    /// ID or `+` for the greatest.
+    pub fn parse_bound(source: &str, missing_seq: u64) -> Result<Self, StreamEntryIDError> {
+        match source {
+            "-" => Ok(Self::MIN),
+            "+" => Ok(Self::MAX),
+            source => Self::parse(source, missing_seq),
+        }
+    }
+
+    /// The smallest ID greater than this one.
+    pub fn next(&self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(Self { ms: self.ms, seq }),
+            None => Some(Self {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
     }
-}
 
-impl TryFrom<String> for StreamEntryID {
-    type Error = StreamEntryIDError;
+    /// The greatest ID smaller than this one.
+    pub fn prev(&self) -> Option<Self> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(Self { ms: self.ms, seq }),
+            None => Some(Self {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
 
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        let parts: Vec<&str> = value.split('-').collect();
-        if parts.len() != 2 {
-            return Err(StreamEntryIDError::InvalidFormat);
+    /// The ID for `*`, or `None` once the last ID is the greatest possible.
+    fn gen_id(last_id: &StreamEntryID, now_ms: u64) -> Option<Self> {
+        // Never go below the last ID, even if the clock is behind it, and
+        // move on to the next ms once its sequence numbers run out.
+        if now_ms > last_id.ms {
+            Some(Self { ms: now_ms, seq: 0 })
+        } else {
+            last_id.next()
         }
+    }
 
-        let ms = parts[0]
-            .parse::<u128>()
-            .map_err(|_| StreamEntryIDError::InvalidFormat)?;
-        let seq = parts[1]
-            .parse::<u64>()
-            .map_err(|_| StreamEntryIDError::InvalidFormat)?;
+    fn gen_seq(ms: u64, last_ms: u64, last_seq: u64) -> Option<u64> {
+        if ms == last_ms {
+            last_seq.checked_add(1)
+        } else {
+            Some(0)
+        }
+    }
+}
 
-        Ok(Self { ms, seq })
+impl fmt::Display for StreamEntryID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
     }
 }
 
 impl From<StreamEntryID> for String {
     fn from(value: StreamEntryID) -> Self {
-        format!("{}-{}", value.ms, value.seq)
+        value.to_string()
     }
 }
 
 #[derive(Debug, Error)]
 pub enum StreamEntryIDError {
-    #[error("Invalid stream ID format")]
+    #[error("Invalid stream ID specified as stream command argument")]
     InvalidFormat,
 }
@@ -0,0 +1,597 @@
+use std::borrow::Cow;
+
+use dashmap::mapref::entry::Entry;
+
+use crate::data::{
+    Store,
+    notify::NotifyFlags,
+    record::{RecordData, RecordType, WRONGTYPE},
+};
+
+/// Largest string a write may produce, matching Redis' default
+/// `proto-max-bulk-len`.
+pub const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// Longest string stored inline in its record, as Redis' embstr encoding.
+const EMBSTR_MAX_LEN: usize = 44;
+
+/// A short string kept inside the value itself rather than in an
+/// allocation of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedStr {
+    len: u8,
+    bytes: [u8; EMBSTR_MAX_LEN],
+}
+
+impl EmbeddedStr {
+    fn new(value: &[u8]) -> Option<Self> {
+        if value.len() > EMBSTR_MAX_LEN {
+            return None;
+        }
+        let mut bytes = [0; EMBSTR_MAX_LEN];
+        bytes[..value.len()].copy_from_slice(value);
+        Some(Self {
+            len: value.len() as u8,
+            bytes,
+        })
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+/// Value of a string key. Kept as bytes because APPEND, GETRANGE and
+/// SETRANGE address byte offsets, which need not fall on UTF-8 boundaries.
+/// Values that are canonical integers are stored as such, so counters don't
+/// reparse their value on every increment, and short values are embedded
+/// to save an allocation. Writes in place turn either into raw bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StringValue {
+    Int(i64),
+    Embstr(EmbeddedStr),
+    Raw(Vec<u8>),
+}
+
+impl Default for StringValue {
+    fn default() -> Self {
+        Self::Raw(Vec::new())
+    }
+}
+
+impl StringValue {
+    /// Stores `value` as given, without trying the integer encoding, for
+    /// values such as floats whose exact formatting should be kept.
+    pub fn text(value: Vec<u8>) -> Self {
+        match EmbeddedStr::new(&value) {
+            Some(embedded) => Self::Embstr(embedded),
+            None => Self::Raw(value),
+        }
+    }
+
+    /// Name of the encoding, as OBJECT ENCODING reports it.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::Int(_) => "int",
+            Self::Embstr(_) => "embstr",
+            Self::Raw(_) => "raw",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Int(n) => n.to_string().len(),
+            Self::Embstr(embedded) => embedded.as_bytes().len(),
+            Self::Raw(bytes) => bytes.len(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            Self::Int(n) => Cow::Owned(n.to_string().into_bytes()),
+            Self::Embstr(embedded) => Cow::Borrowed(embedded.as_bytes()),
+            Self::Raw(bytes) => Cow::Borrowed(bytes),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.to_bytes().into_owned()
+    }
+
+    /// The value as a 64-bit integer, if it is written as one exactly.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int(n) => Some(*n),
+            _ => parse_canonical_i64(&self.to_bytes()),
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(n) => Some(*n as f64),
+            _ => std::str::from_utf8(&self.to_bytes())
+                .ok()?
+                .parse::<f64>()
+                .ok()
+                .filter(|n| !n.is_nan()),
+        }
+    }
+
+    pub fn append(&mut self, bytes: &[u8]) -> usize {
+        let raw = self.bytes_mut();
+        raw.extend_from_slice(bytes);
+        raw.len()
+    }
+
+    /// Bytes between `start` and `end` inclusive, where negative offsets
+    /// count from the end.
+    pub fn get_range(&self, start: i64, end: i64) -> Vec<u8> {
+        let bytes = self.to_bytes();
+        let len = bytes.len() as i64;
+        if len == 0 || (start < 0 && end < 0 && start > end) {
+            return Vec::new();
+        }
+
+        let start = if start < 0 { len + start } else { start }.max(0);
+        let end = if end < 0 { len + end } else { end }.clamp(0, len - 1);
+        if start > end {
+            return Vec::new();
+        }
+        bytes[start as usize..=end as usize].to_vec()
+    }
+
+    /// Overwrites the bytes starting at `offset`, zero-padding the value if
+    /// it is shorter than that.
+    pub fn set_range(&mut self, offset: usize, bytes: &[u8]) -> usize {
+        let raw = self.bytes_mut();
+        let end = offset + bytes.len();
+        if raw.len() < end {
+            raw.resize(end, 0);
+        }
+        raw[offset..end].copy_from_slice(bytes);
+        raw.len()
+    }
+
+    /// The value as mutable bytes, converting it to the raw encoding.
+    pub fn bytes_mut(&mut self) -> &mut Vec<u8> {
+        if !matches!(self, Self::Raw(_)) {
+            *self = Self::Raw(self.to_vec());
+        }
+        match self {
+            Self::Raw(bytes) => bytes,
+            _ => unreachable!("other encodings were converted above"),
+        }
+    }
+}
+
+impl From<Vec<u8>> for StringValue {
+    fn from(value: Vec<u8>) -> Self {
+        match parse_canonical_i64(&value) {
+            Some(n) => Self::Int(n),
+            None => Self::text(value),
+        }
+    }
+}
+
+/// Parses `bytes` as an i64 only if formatting the result gives the same
+/// bytes back, so "007", "+1" or " 1" stay strings.
+pub(super) fn parse_canonical_i64(bytes: &[u8]) -> Option<i64> {
+    if bytes.is_empty() || bytes.len() > 20 {
+        return None;
+    }
+    let n = std::str::from_utf8(bytes).ok()?.parse::<i64>().ok()?;
+    (n.to_string().as_bytes() == bytes).then_some(n)
+}
+
+/// Result of LCS: the common subsequence and the matching ranges, listed
+/// from the end of the strings backwards like Redis does.
+#[derive(Debug, Default)]
+pub struct Lcs {
+    pub sequence: Vec<u8>,
+    pub matches: Vec<LcsMatch>,
+}
+
+/// A run of bytes common to both strings, as inclusive byte ranges.
+#[derive(Debug, Clone, Copy)]
+pub struct LcsMatch {
+    pub a: (usize, usize),
+    pub b: (usize, usize),
+}
+
+impl LcsMatch {
+    pub fn len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+impl Lcs {
+    /// Classic dynamic-programming LCS, keeping only matches of at least
+    /// `min_match_len` bytes.
+    pub fn compute(a: &[u8], b: &[u8], min_match_len: usize) -> Self {
+        let width = b.len() + 1;
+        let mut table = vec![0u32; (a.len() + 1) * width];
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                table[i * width + j] = if a[i - 1] == b[j - 1] {
+                    table[(i - 1) * width + j - 1] + 1
+                } else {
+                    table[(i - 1) * width + j].max(table[i * width + j - 1])
+                };
+            }
+        }
+
+        let mut sequence = vec![0u8; table[a.len() * width + b.len()] as usize];
+        let mut idx = sequence.len();
+        let mut matches = Vec::new();
+        let mut current: Option<LcsMatch> = None;
+        let (mut i, mut j) = (a.len(), b.len());
+
+        while i > 0 && j > 0 {
+            let mut emit = false;
+            if a[i - 1] == b[j - 1] {
+                idx -= 1;
+                sequence[idx] = a[i - 1];
+                match &mut current {
+                    None => {
+                        current = Some(LcsMatch {
+                            a: (i - 1, i - 1),
+                            b: (j - 1, j - 1),
+                        });
+                    }
+                    Some(range) if range.a.0 == i && range.b.0 == j => {
+                        range.a.0 -= 1;
+                        range.b.0 -= 1;
+                    }
+                    Some(_) => emit = true,
+                }
+                if current.is_some_and(|range| range.a.0 == 0 || range.b.0 == 0) {
+                    emit = true;
+                }
+                i -= 1;
+                j -= 1;
+            } else {
+                if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                    i -= 1;
+                } else {
+                    j -= 1;
+                }
+                emit = current.is_some();
+            }
+
+            if emit
+                && let Some(range) = current.take()
+                && range.len() >= min_match_len
+            {
+                matches.push(range);
+            }
+        }
+
+        Self { sequence, matches }
+    }
+}
+
+/// `NX` / `XX` guard on a write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    IfMissing,
+    IfExists,
+}
+
+/// What happens to a key's TTL when it is written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SetExpiration {
+    /// Drop any existing TTL, the default for plain writes.
+    #[default]
+    Clear,
+    /// Keep the existing TTL (`KEEPTTL`).
+    Keep,
+    /// Expire at the given unix milliseconds.
+    At(u64),
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SetOptions {
+    pub condition: Option<SetCondition>,
+    pub expiration: SetExpiration,
+    /// Return the previous value (`GET`).
+    pub get: bool,
+}
+
+impl Store {
+    pub fn append(&self, key: String, value: &[u8]) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(&key);
+
+        let mut entry = self
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| RecordData::new(RecordType::String(StringValue::default()), None));
+        let RecordType::String(string) = &mut entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+        if string.len() + value.len() > MAX_STRING_LEN {
+            anyhow::bail!("string exceeds maximum allowed size (proto-max-bulk-len)");
+        }
+        let len = string.append(value);
+        drop(entry);
+
+        self.notify_keyspace_event(NotifyFlags::STRING, "append", &key);
+        Ok(len)
+    }
+
+    pub fn strlen(&self, key: &str) -> anyhow::Result<usize> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(0);
+        };
+        let RecordType::String(string) = &entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+
+        Ok(string.len())
+    }
+
+    pub fn getrange(&self, key: &str, start: i64, end: i64) -> anyhow::Result<Vec<u8>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(Vec::new());
+        };
+        let RecordType::String(string) = &entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+
+        Ok(string.get_range(start, end))
+    }
+
+    /// Overwrites part of a string, zero-padding it as needed. An empty
+    /// `value` only reports the current length and never creates the key.
+    pub fn setrange(&self, key: String, offset: usize, value: &[u8]) -> anyhow::Result<usize> {
+        if value.is_empty() {
+            return self.strlen(&key);
+        }
+
+        let _guard = self.lock_shared();
+        self.expire_if_needed(&key);
+        if offset + value.len() > MAX_STRING_LEN {
+            anyhow::bail!("string exceeds maximum allowed size (proto-max-bulk-len)");
+        }
+
+        let mut entry = self
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| RecordData::new(RecordType::String(StringValue::default()), None));
+        let RecordType::String(string) = &mut entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+        let len = string.set_range(offset, value);
+        drop(entry);
+
+        self.notify_keyspace_event(NotifyFlags::STRING, "setrange", &key);
+        Ok(len)
+    }
+
+    /// Adds `delta` to the integer stored at `key`, starting from 0 and
+    /// keeping the key's TTL.
+    pub fn incr_by(&self, key: String, delta: i64) -> anyhow::Result<i64> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(&key);
+
+        let mut entry = self
+            .entries
+            .entry(key.clone())
+            .or_insert_with(|| RecordData::new(RecordType::String(StringValue::Int(0)), None));
+        let RecordType::String(string) = &mut entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+        let Some(current) = string.as_i64() else {
+            anyhow::bail!("value is not an integer or out of range");
+        };
+        let Some(value) = current.checked_add(delta) else {
+            anyhow::bail!("increment or decrement would overflow");
+        };
+        *string = StringValue::Int(value);
+        drop(entry);
+
+        self.notify_keyspace_event(NotifyFlags::STRING, "incrby", &key);
+        Ok(value)
+    }
+
+    /// Adds `delta` to the number stored at `key`, returning the new value
+    /// formatted the way it is stored.
+    pub fn incr_by_float(&self, key: String, delta: f64) -> anyhow::Result<String> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(&key);
+
+        let add = |current: f64| {
+            let value = current + delta;
+            if !value.is_finite() {
+                anyhow::bail!("increment would produce NaN or Infinity");
+            }
+            Ok(format_float(value))
+        };
+        // Nothing is written unless the increment succeeds.
+        let formatted = match self.entries.entry(key.clone()) {
+            Entry::Occupied(mut entry) => {
+                let RecordType::String(string) = &mut entry.get_mut().record else {
+                    anyhow::bail!(WRONGTYPE);
+                };
+                let Some(current) = string.as_f64() else {
+                    anyhow::bail!("value is not a valid float");
+                };
+                let formatted = add(current)?;
+                *string = StringValue::text(formatted.clone().into_bytes());
+                formatted
+            }
+            Entry::Vacant(entry) => {
+                let formatted = add(0.0)?;
+                let string = StringValue::text(formatted.clone().into_bytes());
+                entry.insert(RecordData::new(RecordType::String(string), None));
+                formatted
+            }
+        };
+
+        self.notify_keyspace_event(NotifyFlags::STRING, "incrbyfloat", &key);
+        Ok(formatted)
+    }
+
+    /// Longest common subsequence of two string keys, missing keys counting
+    /// as empty strings.
+    pub fn lcs(&self, a: &str, b: &str, min_match_len: usize) -> anyhow::Result<Lcs> {
+        let _guard = self.lock_shared();
+
+        let mut values = Vec::with_capacity(2);
+        for key in [a, b] {
+            self.expire_if_needed(key);
+            let value = match self.entries.get(key).as_deref().map(|data| &data.record) {
+                None => StringValue::default(),
+                Some(RecordType::String(string)) => string.clone(),
+                Some(_) => anyhow::bail!("The specified keys must contain string values"),
+            };
+            values.push(value);
+        }
+
+        let cells = (values[0].len() + 1).saturating_mul(values[1].len() + 1);
+        if cells.saturating_mul(size_of::<u32>()) > MAX_STRING_LEN {
+            anyhow::bail!(
+                "Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len"
+            );
+        }
+
+        Ok(Lcs::compute(
+            &values[0].to_bytes(),
+            &values[1].to_bytes(),
+            min_match_len,
+        ))
+    }
+
+    pub fn getdel(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+
+        let Entry::Occupied(entry) = self.entries.entry(key.to_string()) else {
+            return Ok(None);
+        };
+        let RecordType::String(value) = &entry.get().record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+        let value = value.to_vec();
+        entry.remove();
+
+        self.notify_keyspace_event(NotifyFlags::GENERIC, "del", key);
+        Ok(Some(value))
+    }
+
+    /// Reads a string and updates its TTL; `SetExpiration::Keep` leaves it
+    /// untouched and `SetExpiration::Clear` persists the key.
+    pub fn getex(&self, key: &str, expiration: SetExpiration) -> anyhow::Result<Option<Vec<u8>>> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+
+        let Some(mut entry) = self.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        let RecordType::String(value) = &entry.record else {
+            anyhow::bail!(WRONGTYPE);
+        };
+        let value = value.to_vec();
+
+        let event = match expiration {
+            SetExpiration::Keep => None,
+            SetExpiration::Clear if entry.expiration().is_none() => None,
+            SetExpiration::Clear => {
+                entry.set_expiration(None);
+                Some("persist")
+            }
+            SetExpiration::At(at) => {
+                entry.set_expiration(Some(at));
+                self.track_deadline(key, at);
+                Some("expire")
+            }
+        };
+        drop(entry);
+
+        if let Some(event) = event {
+            self.notify_keyspace_event(NotifyFlags::GENERIC, event, key);
+        }
+        Ok(Some(value))
+    }
+
+    /// Reads several keys at once; keys that are missing or hold another type
+    /// come back as `None`.
+    pub fn mget(&self, keys: &[String]) -> Vec<Option<Vec<u8>>> {
+        let _guard = self.lock_shared();
+
+        keys.iter()
+            .map(|key| {
+                self.expire_if_needed(key);
+                match &self.entries.get(key)?.record {
+                    RecordType::String(value) => Some(value.to_vec()),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    pub fn mset(&self, pairs: Vec<(String, Vec<u8>)>) {
+        let _guard = self.lock_exclusive();
+        self.write_strings(pairs);
+    }
+
+    /// Writes every pair only if none of the keys exist, atomically with
+    /// respect to other clients.
+    pub fn msetnx(&self, pairs: Vec<(String, Vec<u8>)>) -> bool {
+        let _guard = self.lock_exclusive();
+
+        for (key, _) in &pairs {
+            self.expire_if_needed(key);
+            if self.entries.contains_key(key) {
+                return false;
+            }
+        }
+
+        self.write_strings(pairs);
+        true
+    }
+
+    fn write_strings(&self, pairs: Vec<(String, Vec<u8>)>) {
+        for (key, value) in pairs {
+            self.entries.insert(
+                key.clone(),
+                RecordData::new(RecordType::String(value.into()), None),
+            );
+            self.notify_keyspace_event(NotifyFlags::STRING, "set", &key);
+        }
+    }
+}
+
+/// Formats the result of a float increment the way Redis stores it: in
+/// plain notation with up to 17 significant digits and no trailing zeros.
+/// Fewer digits are used when they read back within rounding error, so
+/// that `0.1 + 0.2` gives `0.3` as Redis' long double arithmetic does.
+pub(super) fn format_float(value: f64) -> String {
+    let with_digits = |digits: usize| {
+        let scientific = format!("{value:.*e}", digits - 1);
+        let (_, exponent) = scientific.split_once('e').expect("{:e} has an exponent");
+        let exponent: i64 = exponent.parse().expect("{:e} has an integer exponent");
+        // Like Redis' %.17Lf, never print more than 17 decimals.
+        let decimals = (digits as i64 - 1 - exponent).clamp(0, 17) as usize;
+        let formatted = format!("{value:.decimals$}");
+        if formatted.contains('.') {
+            formatted
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        } else {
+            formatted
+        }
+    };
+
+    (15..17)
+        .map(with_digits)
+        .find(|formatted| {
+            formatted
+                .parse::<f64>()
+                .is_ok_and(|parsed| (parsed - value).abs() <= f64::EPSILON * value.abs())
+        })
+        .unwrap_or_else(|| with_digits(17))
+}
@@ -0,0 +1,756 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::bail;
+
+use crate::data::{
+    Store,
+    dict::Dict,
+    notify::NotifyFlags,
+    record::{RecordData, RecordType, WRONGTYPE},
+    set::SetOperation,
+    skiplist::{Bounds, SkipList},
+    string::SetCondition,
+};
+
+/// Value of a sorted set key: a skiplist ordering the members by score, and
+/// a hash table from each member to its score for direct lookups.
+#[derive(Debug, Clone, Default)]
+pub struct ZSetValue {
+    scores: Dict<String, f64>,
+    list: SkipList,
+}
+
+impl PartialEq for ZSetValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.scores == other.scores
+    }
+}
+
+// Scores are never NaN, so they compare as a total order.
+impl Eq for ZSetValue {}
+
+impl ZSetValue {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, returning the one it replaced.
+    pub fn insert(&mut self, member: String, score: f64) -> Option<f64> {
+        let previous = self.scores.insert(member.clone(), score);
+        match previous {
+            Some(previous) if previous == score => {}
+            Some(previous) => {
+                self.list.remove(previous, &member);
+                self.list.insert(score, member);
+            }
+            None => self.list.insert(score, member),
+        }
+        previous
+    }
+
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.list.remove(score, member);
+        Some(score)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, f64)> {
+        self.scores.iter().map(|(member, &score)| (member, score))
+    }
+
+    /// Removes and returns the element with the lowest or highest score.
+    pub fn pop(&mut self, end: ScoreEnd) -> Option<(String, f64)> {
+        let reverse = end == ScoreEnd::Max;
+        let first = if reverse {
+            self.len().checked_sub(1)?
+        } else {
+            0
+        };
+        let (member, score) = self.list.iter_from(first, reverse).next()?;
+        let member = member.to_string();
+        self.remove(&member);
+        Some((member, score))
+    }
+
+    /// Zero-based rank of `member`, counting from the highest score if
+    /// `reverse` is set.
+    pub fn rank(&self, member: &str, reverse: bool) -> Option<usize> {
+        let rank = self.list.rank(self.score(member)?, member)?;
+        Some(if reverse { self.len() - 1 - rank } else { rank })
+    }
+
+    /// How many elements fall within `bounds`.
+    pub fn count(&self, bounds: &impl Bounds) -> usize {
+        match (self.list.first_in(bounds), self.list.last_in(bounds)) {
+            (Some(first), Some(last)) if last >= first => last - first + 1,
+            _ => 0,
+        }
+    }
+
+    /// Elements ranked `start` to `stop` inclusive, where negative ranks
+    /// count from the end.
+    pub(super) fn range_by_rank(&self, start: i64, stop: i64, reverse: bool) -> Vec<(String, f64)> {
+        let len = self.len() as i64;
+        let start = if start < 0 { len + start } else { start }.max(0);
+        let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+        if start > stop {
+            return Vec::new();
+        }
+
+        let first = if reverse { len - 1 - start } else { start };
+        self.list
+            .iter_from(first as usize, reverse)
+            .take((stop - start) as usize + 1)
+            .map(|(member, score)| (member.to_string(), score))
+            .collect()
+    }
+
+    /// Elements within `bounds`, skipping `offset` of them and returning at
+    /// most `count` if it isn't negative.
+    pub fn range_within(
+        &self,
+        bounds: &impl Bounds,
+        reverse: bool,
+        (offset, count): (i64, i64),
+    ) -> Vec<(String, f64)> {
+        let first = if reverse {
+            self.list.last_in(bounds)
+        } else {
+            self.list.first_in(bounds)
+        };
+        let Some(first) = first.filter(|_| offset >= 0) else {
+            return Vec::new();
+        };
+
+        self.list
+            .iter_from(first, reverse)
+            .skip(offset as usize)
+            .take_while(|&(member, score)| {
+                if reverse {
+                    bounds.above_min(score, member)
+                } else {
+                    bounds.below_max(score, member)
+                }
+            })
+            .take(usize::try_from(count).unwrap_or(usize::MAX))
+            .map(|(member, score)| (member.to_string(), score))
+            .collect()
+    }
+
+    /// The elements `spec` selects, in the order it asks for.
+    fn select(&self, spec: &ZRangeSpec) -> Vec<(String, f64)> {
+        let limit = spec.limit.unwrap_or((0, -1));
+        match &spec.by {
+            ZRangeBy::Rank(start, stop) => self.range_by_rank(*start, *stop, spec.reverse),
+            ZRangeBy::Score(range) => self.range_within(range, spec.reverse, limit),
+            ZRangeBy::Lex(range) => self.range_within(range, spec.reverse, limit),
+        }
+    }
+}
+
+/// Range of scores, each end of which may be excluded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: f64,
+    pub max: f64,
+    pub min_exclusive: bool,
+    pub max_exclusive: bool,
+}
+
+impl Bounds for ScoreRange {
+    fn above_min(&self, score: f64, _member: &str) -> bool {
+        if self.min_exclusive {
+            score > self.min
+        } else {
+            score >= self.min
+        }
+    }
+
+    fn below_max(&self, score: f64, _member: &str) -> bool {
+        if self.max_exclusive {
+            score < self.max
+        } else {
+            score <= self.max
+        }
+    }
+}
+
+/// One end of a range of members: `-`, `+`, `[member` or `(member`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexBound {
+    /// Before every member.
+    Min,
+    /// After every member.
+    Max,
+    Inclusive(String),
+    Exclusive(String),
+}
+
+/// Range of members, meaningful when every element has the same score.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl Bounds for LexRange {
+    fn above_min(&self, _score: f64, member: &str) -> bool {
+        match &self.min {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(min) => member >= min.as_str(),
+            LexBound::Exclusive(min) => member > min.as_str(),
+        }
+    }
+
+    fn below_max(&self, _score: f64, member: &str) -> bool {
+        match &self.max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(max) => member <= max.as_str(),
+            LexBound::Exclusive(max) => member < max.as_str(),
+        }
+    }
+}
+
+/// How ZRANGE picks its elements.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZRangeBy {
+    Rank(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+/// Elements selected by ZRANGE and ZRANGESTORE.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZRangeSpec {
+    pub by: ZRangeBy,
+    /// Walk from the highest score down.
+    pub reverse: bool,
+    /// Offset and count (`LIMIT`); a negative count means no limit.
+    pub limit: Option<(i64, i64)>,
+}
+
+/// End of a sorted set that ZPOPMIN, ZPOPMAX and ZMPOP take from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreEnd {
+    Min,
+    Max,
+}
+
+impl ScoreEnd {
+    fn pop_event(self) -> &'static str {
+        match self {
+            Self::Min => "zpopmin",
+            Self::Max => "zpopmax",
+        }
+    }
+}
+
+/// How ZUNION and ZINTER combine the scores a member has in each input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn combine(self, a: f64, b: f64) -> f64 {
+        match self {
+            // Infinities of opposite sign add up to 0 rather than NaN.
+            Self::Sum => Some(a + b).filter(|sum| !sum.is_nan()).unwrap_or(0.0),
+            Self::Min => a.min(b),
+            Self::Max => a.max(b),
+        }
+    }
+}
+
+fn store_event(operation: SetOperation) -> &'static str {
+    match operation {
+        SetOperation::Inter => "zinterstore",
+        SetOperation::Union => "zunionstore",
+        SetOperation::Diff => "zdiffstore",
+    }
+}
+
+/// Key of a sorted set along with the elements popped from it.
+type Popped = (String, Vec<(String, f64)>);
+
+/// `GT` / `LT` guard on updating a score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreComparison {
+    Greater,
+    Less,
+}
+
+impl ScoreComparison {
+    fn allows(self, current: f64, score: f64) -> bool {
+        match self {
+            Self::Greater => score > current,
+            Self::Less => score < current,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZAddOptions {
+    pub condition: Option<SetCondition>,
+    pub comparison: Option<ScoreComparison>,
+    /// Count updated elements along with new ones (`CH`).
+    pub changed: bool,
+    /// Add the score to the current one (`INCR`).
+    pub increment: bool,
+}
+
+impl Store {
+    /// Runs `f` over the sorted set at `key`, or returns `None` if there is
+    /// none.
+    pub(super) fn with_zset<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&ZSetValue) -> T,
+    ) -> anyhow::Result<Option<T>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(None);
+        };
+        let RecordType::ZSet(zset) = &entry.record else {
+            bail!(WRONGTYPE);
+        };
+
+        Ok(Some(f(zset)))
+    }
+
+    /// Sets the score of each member, returning how many were added, or
+    /// with `CH` changed, and with `INCR` the new score of the only member,
+    /// `None` if the options kept it from changing.
+    pub fn zadd(
+        &self,
+        key: &str,
+        pairs: Vec<(f64, String)>,
+        options: ZAddOptions,
+    ) -> anyhow::Result<(usize, Option<f64>)> {
+        let (added, updated, last_score) = {
+            let _guard = self.lock_shared();
+            let exists = self.with_zset(key, |_| ())?.is_some();
+            if !exists && options.condition == Some(SetCondition::IfExists) {
+                return Ok((0, None));
+            }
+
+            let mut entry = self
+                .entries
+                .entry(key.to_string())
+                .or_insert_with(|| RecordData::new(RecordType::ZSet(ZSetValue::default()), None));
+            let RecordType::ZSet(zset) = &mut entry.record else {
+                bail!(WRONGTYPE);
+            };
+            let (mut added, mut updated, mut last_score) = (0, 0, None);
+            for (value, member) in pairs {
+                let current = zset.score(&member);
+                let score = match current {
+                    Some(current) if options.increment => current + value,
+                    _ => value,
+                };
+                if score.is_nan() {
+                    bail!("resulting score is not a number (NaN)");
+                }
+                let allowed = match (current, options.condition) {
+                    (Some(_), Some(SetCondition::IfMissing))
+                    | (None, Some(SetCondition::IfExists)) => false,
+                    (Some(current), _) => options
+                        .comparison
+                        .is_none_or(|comparison| comparison.allows(current, score)),
+                    (None, _) => true,
+                };
+                if !allowed {
+                    continue;
+                }
+
+                last_score = Some(score);
+                match current {
+                    None => added += 1,
+                    Some(current) if current != score => updated += 1,
+                    Some(_) => continue,
+                }
+                zset.insert(member, score);
+            }
+            (added, updated, last_score)
+        };
+
+        if added + updated > 0 {
+            let event = if options.increment { "zincr" } else { "zadd" };
+            self.notify_keyspace_event(NotifyFlags::ZSET, event, key);
+            self.serve_blocked(key);
+        }
+        let count = if options.changed {
+            added + updated
+        } else {
+            added
+        };
+        Ok((count, last_score))
+    }
+
+    /// Removes `members`, returning how many were there.
+    pub fn zrem(&self, key: &str, members: &[String]) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+        let removed = match self.entries.get_mut(key) {
+            None => 0,
+            Some(mut entry) => {
+                let RecordType::ZSet(zset) = &mut entry.record else {
+                    bail!(WRONGTYPE);
+                };
+                members
+                    .iter()
+                    .filter(|member| zset.remove(member).is_some())
+                    .count()
+            }
+        };
+
+        if removed > 0 {
+            self.notify_keyspace_event(NotifyFlags::ZSET, "zrem", key);
+            self.delete_if_empty(key);
+        }
+        Ok(removed)
+    }
+
+    pub fn zscore(&self, key: &str, member: &str) -> anyhow::Result<Option<f64>> {
+        Ok(self.with_zset(key, |zset| zset.score(member))?.flatten())
+    }
+
+    pub fn zmscore(&self, key: &str, members: &[String]) -> anyhow::Result<Vec<Option<f64>>> {
+        Ok(self
+            .with_zset(key, |zset| {
+                members.iter().map(|member| zset.score(member)).collect()
+            })?
+            .unwrap_or_else(|| vec![None; members.len()]))
+    }
+
+    /// Rank of `member` along with its score, counting from the highest
+    /// score if `reverse` is set.
+    pub fn zrank(
+        &self,
+        key: &str,
+        member: &str,
+        reverse: bool,
+    ) -> anyhow::Result<Option<(usize, f64)>> {
+        Ok(self
+            .with_zset(key, |zset| {
+                Some((zset.rank(member, reverse)?, zset.score(member)?))
+            })?
+            .flatten())
+    }
+
+    pub fn zcard(&self, key: &str) -> anyhow::Result<usize> {
+        Ok(self.with_zset(key, ZSetValue::len)?.unwrap_or(0))
+    }
+
+    pub fn zcount(&self, key: &str, range: &ScoreRange) -> anyhow::Result<usize> {
+        Ok(self.with_zset(key, |zset| zset.count(range))?.unwrap_or(0))
+    }
+
+    pub fn zrange(&self, key: &str, spec: &ZRangeSpec) -> anyhow::Result<Vec<(String, f64)>> {
+        Ok(self
+            .with_zset(key, |zset| zset.select(spec))?
+            .unwrap_or_default())
+    }
+
+    /// Stores the elements `spec` selects from `source` in `destination`,
+    /// replacing whatever was there, and returns how many there are.
+    pub fn zrangestore(
+        &self,
+        destination: &str,
+        source: &str,
+        spec: &ZRangeSpec,
+    ) -> anyhow::Result<usize> {
+        let len = {
+            let _guard = self.lock_exclusive();
+            let elements = self
+                .with_zset(source, |zset| zset.select(spec))?
+                .unwrap_or_default();
+            self.store_zset(destination, elements, "zrangestore")
+        };
+
+        if len > 0 {
+            self.serve_blocked(destination);
+        }
+        Ok(len)
+    }
+
+    /// Replaces `destination` with a sorted set of `elements`, firing
+    /// `event`, or deletes it if there are none. Returns how many there are.
+    pub(super) fn store_zset(
+        &self,
+        destination: &str,
+        elements: Vec<(String, f64)>,
+        event: &str,
+    ) -> usize {
+        self.expire_if_needed(destination);
+        if elements.is_empty() {
+            if self.entries.remove(destination).is_some() {
+                self.notify_keyspace_event(NotifyFlags::GENERIC, "del", destination);
+            }
+            return 0;
+        }
+
+        let len = elements.len();
+        let mut zset = ZSetValue::default();
+        for (member, score) in elements {
+            zset.insert(member, score);
+        }
+        self.entries.insert(
+            destination.to_string(),
+            RecordData::new(RecordType::ZSet(zset), None),
+        );
+        self.notify_keyspace_event(NotifyFlags::ZSET, event, destination);
+        len
+    }
+
+    /// Members of the sorted set or set at `key` with their scores, a plain
+    /// set's members all scoring 1, or `None` if there is no such key.
+    fn scored_members(&self, key: &str) -> anyhow::Result<Option<HashMap<String, f64>>> {
+        self.expire_if_needed(key);
+        let Some(entry) = self.entries.get(key) else {
+            return Ok(None);
+        };
+        match &entry.record {
+            RecordType::ZSet(zset) => Ok(Some(
+                zset.iter()
+                    .map(|(member, score)| (member.clone(), score))
+                    .collect(),
+            )),
+            RecordType::Set(set) => Ok(Some(
+                set.iter()
+                    .map(|member| (String::from_utf8_lossy(&member).into_owned(), 1.0))
+                    .collect(),
+            )),
+            _ => bail!(WRONGTYPE),
+        }
+    }
+
+    /// Elements resulting from `operation` over the sorted sets at `keys`,
+    /// ordered by score, with each input's scores multiplied by its weight
+    /// and a member's scores combined by `aggregate`. A missing key counts
+    /// as an empty set.
+    fn combine_zsets(
+        &self,
+        operation: SetOperation,
+        keys: &[String],
+        weights: &[f64],
+        aggregate: Aggregate,
+    ) -> anyhow::Result<Vec<(String, f64)>> {
+        let mut inputs = Vec::with_capacity(keys.len());
+        for key in keys {
+            inputs.push(self.scored_members(key)?);
+        }
+        let weighted = |score: f64, i: usize| {
+            let score = score * weights.get(i).copied().unwrap_or(1.0);
+            if score.is_nan() { 0.0 } else { score }
+        };
+
+        let mut inputs = inputs.into_iter().enumerate();
+        let Some((_, first)) = inputs.next() else {
+            return Ok(Vec::new());
+        };
+        let mut result: HashMap<String, f64> = first
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(member, score)| {
+                let score = match operation {
+                    SetOperation::Diff => score,
+                    _ => weighted(score, 0),
+                };
+                (member, score)
+            })
+            .collect();
+        for (i, input) in inputs {
+            let input = input.unwrap_or_default();
+            match operation {
+                SetOperation::Union => {
+                    for (member, score) in input {
+                        let score = weighted(score, i);
+                        result
+                            .entry(member)
+                            .and_modify(|total| *total = aggregate.combine(*total, score))
+                            .or_insert(score);
+                    }
+                }
+                SetOperation::Inter => result.retain(|member, total| match input.get(member) {
+                    Some(&score) => {
+                        *total = aggregate.combine(*total, weighted(score, i));
+                        true
+                    }
+                    None => false,
+                }),
+                SetOperation::Diff => result.retain(|member, _| !input.contains_key(member)),
+            }
+        }
+
+        let mut elements: Vec<_> = result.into_iter().collect();
+        elements.sort_by(|(a, a_score), (b, b_score)| a_score.total_cmp(b_score).then(a.cmp(b)));
+        Ok(elements)
+    }
+
+    /// ZUNION, ZINTER or ZDIFF over the sorted sets at `keys`.
+    pub fn zset_operation(
+        &self,
+        operation: SetOperation,
+        keys: &[String],
+        weights: &[f64],
+        aggregate: Aggregate,
+    ) -> anyhow::Result<Vec<(String, f64)>> {
+        let _guard = self.lock_shared();
+        self.combine_zsets(operation, keys, weights, aggregate)
+    }
+
+    /// Stores the result of [`Store::zset_operation`] in `destination`,
+    /// replacing whatever was there, and returns its size.
+    pub fn zset_operation_store(
+        &self,
+        operation: SetOperation,
+        destination: &str,
+        keys: &[String],
+        weights: &[f64],
+        aggregate: Aggregate,
+    ) -> anyhow::Result<usize> {
+        let len = {
+            let _guard = self.lock_exclusive();
+            let elements = self.combine_zsets(operation, keys, weights, aggregate)?;
+            self.store_zset(destination, elements, store_event(operation))
+        };
+
+        if len > 0 {
+            self.serve_blocked(destination);
+        }
+        Ok(len)
+    }
+
+    /// Size of the intersection of the sorted sets at `keys`, counting no
+    /// further than `limit` if it isn't 0.
+    pub fn zintercard(&self, keys: &[String], limit: usize) -> anyhow::Result<usize> {
+        let _guard = self.lock_shared();
+        let mut sizes = Vec::with_capacity(keys.len());
+        for key in keys {
+            self.expire_if_needed(key);
+            sizes.push(
+                match self.entries.get(key).as_deref().map(|entry| &entry.record) {
+                    None => None,
+                    Some(RecordType::ZSet(zset)) => Some(zset.len()),
+                    Some(RecordType::Set(set)) => Some(set.len()),
+                    Some(_) => bail!(WRONGTYPE),
+                },
+            );
+        }
+        if sizes.contains(&None) {
+            return Ok(0);
+        }
+
+        // Read guards never wait on one another, so every input can be held
+        // at once while the smallest is walked and the others probed.
+        let entries: Vec<_> = keys
+            .iter()
+            .filter_map(|key| self.entries.get(key))
+            .collect();
+        if entries.len() < keys.len() {
+            return Ok(0);
+        }
+
+        let smallest = (0..keys.len()).min_by_key(|&i| sizes[i]).unwrap_or(0);
+        let in_all = |member: &[u8]| {
+            entries.iter().enumerate().all(|(i, entry)| {
+                i == smallest
+                    || match &entry.record {
+                        RecordType::ZSet(zset) => std::str::from_utf8(member)
+                            .is_ok_and(|member| zset.score(member).is_some()),
+                        RecordType::Set(set) => set.contains(member),
+                        _ => false,
+                    }
+            })
+        };
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        Ok(match &entries[smallest].record {
+            RecordType::ZSet(zset) => zset
+                .iter()
+                .filter(|(member, _)| in_all(member.as_bytes()))
+                .take(limit)
+                .count(),
+            RecordType::Set(set) => set
+                .iter()
+                .filter(|member| in_all(member))
+                .take(limit)
+                .count(),
+            _ => 0,
+        })
+    }
+
+    /// Removes up to `count` elements from `end` of the sorted set at `key`,
+    /// or returns `None` if there is none.
+    pub fn zpop(
+        &self,
+        key: &str,
+        end: ScoreEnd,
+        count: usize,
+    ) -> anyhow::Result<Option<Vec<(String, f64)>>> {
+        let _guard = self.lock_shared();
+        self.expire_if_needed(key);
+        let Some(mut entry) = self.entries.get_mut(key) else {
+            return Ok(None);
+        };
+        let RecordType::ZSet(zset) = &mut entry.record else {
+            bail!(WRONGTYPE);
+        };
+        let elements: Vec<_> = (0..count).map_while(|_| zset.pop(end)).collect();
+        drop(entry);
+
+        if !elements.is_empty() {
+            self.notify_keyspace_event(NotifyFlags::ZSET, end.pop_event(), key);
+            self.delete_if_empty(key);
+        }
+        Ok(Some(elements))
+    }
+
+    /// Pops up to `count` elements from `end` of the first non-empty sorted
+    /// set among `keys`, returning its key along with them.
+    pub fn zmpop(
+        &self,
+        keys: &[String],
+        end: ScoreEnd,
+        count: usize,
+    ) -> anyhow::Result<Option<Popped>> {
+        for key in keys {
+            if let Some(elements) = self.zpop(key, end, count)?
+                && !elements.is_empty()
+            {
+                return Ok(Some((key.clone(), elements)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// [`Store::zmpop`] that waits up to `timeout`, forever if `None`, for
+    /// one of `keys` to receive elements.
+    pub async fn bzmpop(
+        &self,
+        client_id: u64,
+        keys: Vec<String>,
+        end: ScoreEnd,
+        count: usize,
+        timeout: Option<Duration>,
+    ) -> anyhow::Result<Option<Popped>> {
+        let attempt_keys = keys.clone();
+        self.block_on(client_id, keys, None, timeout, move |store| {
+            store.zmpop(&attempt_keys, end, count)
+        })
+        .await
+    }
+}
@@ -0,0 +1,91 @@
+/// Glob-style matching with the same rules Redis uses for `KEYS`, `PSUBSCRIBE`
+/// and friends: `*`, `?`, `[abc]`, `[^a-z]` and `\` escapes.
+pub fn matches(pattern: &str, string: &str) -> bool {
+    matches_bytes(pattern.as_bytes(), string.as_bytes())
+}
+
+pub fn matches_bytes(mut pattern: &[u8], mut string: &[u8]) -> bool {
+    while let Some(&p) = pattern.first() {
+        match p {
+            b'*' => {
+                while pattern.len() > 1 && pattern[1] == b'*' {
+                    pattern = &pattern[1..];
+                }
+                if pattern.len() == 1 {
+                    return true;
+                }
+                for start in 0..=string.len() {
+                    if matches_bytes(&pattern[1..], &string[start..]) {
+                        return true;
+                    }
+                }
+                return false;
+            }
+            b'?' => {
+                if string.is_empty() {
+                    return false;
+                }
+                string = &string[1..];
+            }
+            b'[' => {
+                let Some(&c) = string.first() else {
+                    return false;
+                };
+                pattern = &pattern[1..];
+                let negate = pattern.first() == Some(&b'^');
+                if negate {
+                    pattern = &pattern[1..];
+                }
+
+                let mut found = false;
+                loop {
+                    match pattern {
+                        [] => break,
+                        [b']', ..] => break,
+                        [b'\\', escaped, ..] => {
+                            found |= *escaped == c;
+                            pattern = &pattern[2..];
+                        }
+                        [start, b'-', end, ..] if *end != b']' => {
+                            let (low, high) = if start <= end {
+                                (*start, *end)
+                            } else {
+                                (*end, *start)
+                            };
+                            found |= (low..=high).contains(&c);
+                            pattern = &pattern[3..];
+                        }
+                        [other, ..] => {
+                            found |= *other == c;
+                            pattern = &pattern[1..];
+                        }
+                    }
+                }
+
+                if found == negate {
+                    return false;
+                }
+                string = &string[1..];
+                if pattern.is_empty() {
+                    return string.is_empty();
+                }
+            }
+            b'\\' if pattern.len() >= 2 => {
+                if string.first() != Some(&pattern[1]) {
+                    return false;
+                }
+                pattern = &pattern[1..];
+                string = &string[1..];
+            }
+            _ => {
+                if string.first() != Some(&p) {
+                    return false;
+                }
+                string = &string[1..];
+            }
+        }
+        pattern = &pattern[1..];
+    }
+
+    string.is_empty()
+}
@@ -1,20 +1,40 @@
 use std::sync::Arc;
+use std::time::Duration;
 
+use bytes::BytesMut;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 
-
+use crate::client::Client;
 use crate::data::Store;
+use crate::resp_parser::RespValue;
 
+mod client;
+mod clock;
 mod command;
+mod config;
 mod data;
+mod glob;
+mod pubsub;
+mod random;
 mod resp_parser;
 
+const ACTIVE_EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+
 #[tokio::main]
 async fn main() {
     let listener = TcpListener::bind("127.0.0.1:6379").await.unwrap();
     let memory = Arc::new(Store::default());
 
+    let expire_memory = memory.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACTIVE_EXPIRE_INTERVAL);
+        loop {
+            interval.tick().await;
+            expire_memory.active_expire_cycle();
+        }
+    });
+
     loop {
         let (socket, _) = listener.accept().await.unwrap();
         let memory_clone = memory.clone();
@@ -25,17 +45,60 @@ async fn main() {
 }
 
 async fn process(mut socket: TcpStream, memory: Arc<Store>) {
-    let mut buffer = [0; 1024];
+    let (mut client, mut messages) = Client::new();
+    let mut buffer = BytesMut::with_capacity(4096);
 
-    loop {
-        let bytes_read = socket.read(&mut buffer).await.unwrap();
-        if bytes_read == 0 {
-            break;
-        }
+    'connection: loop {
+        tokio::select! {
+            bytes_read = socket.read_buf(&mut buffer) => {
+                if !matches!(bytes_read, Ok(n) if n > 0) {
+                    break;
+                }
 
-        let content = resp_parser::parse(&buffer[..bytes_read]);
-        let response = command::handler(content, memory.clone()).await;
-        let response_str: String = response.into();
-        socket.write_all(response_str.as_bytes()).await.unwrap();
+                loop {
+                    let response = match resp_parser::parse(&mut buffer) {
+                        Ok(Some(request)) if request.is_empty() => continue,
+                        Ok(Some(request)) => {
+                            // Keep reading while the command runs, so a client
+                            // that disconnects while blocked is noticed and its
+                            // command dropped, instead of lingering as a waiter.
+                            let handler = command::handler(request, memory.clone(), &mut client);
+                            tokio::pin!(handler);
+                            loop {
+                                tokio::select! {
+                                    response = &mut handler => break response,
+                                    bytes_read = socket.read_buf(&mut buffer) => {
+                                        if !matches!(bytes_read, Ok(n) if n > 0) {
+                                            break 'connection;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        Ok(None) => break,
+                        Err(err) => {
+                            let _ = write_reply(&mut socket, RespValue::Error(err.to_string())).await;
+                            break 'connection;
+                        }
+                    };
+
+                    if write_reply(&mut socket, response).await.is_err() || client.is_closing() {
+                        break 'connection;
+                    }
+                }
+            }
+            Some(message) = messages.recv() => {
+                if write_reply(&mut socket, message).await.is_err() {
+                    break;
+                }
+            }
+        }
     }
+
+    client.unsubscribe_all(memory.pubsub());
+}
+
+async fn write_reply(socket: &mut TcpStream, reply: RespValue) -> std::io::Result<()> {
+    let bytes: Vec<u8> = reply.into();
+    socket.write_all(&bytes).await
 }
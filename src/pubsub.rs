@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use dashmap::DashMap;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::glob;
+use crate::resp_parser::RespValue;
+
+type Subscribers = HashMap<u64, UnboundedSender<RespValue>>;
+
+/// Channel and pattern subscriptions, keyed by client id.
+#[derive(Debug, Default)]
+pub struct PubSub {
+    channels: DashMap<String, Subscribers>,
+    patterns: DashMap<String, Subscribers>,
+}
+
+impl PubSub {
+    pub fn subscribe(&self, channel: String, client_id: u64, sender: UnboundedSender<RespValue>) {
+        self.channels
+            .entry(channel)
+            .or_default()
+            .insert(client_id, sender);
+    }
+
+    pub fn unsubscribe(&self, channel: &str, client_id: u64) {
+        Self::remove(&self.channels, channel, client_id);
+    }
+
+    pub fn psubscribe(&self, pattern: String, client_id: u64, sender: UnboundedSender<RespValue>) {
+        self.patterns
+            .entry(pattern)
+            .or_default()
+            .insert(client_id, sender);
+    }
+
+    pub fn punsubscribe(&self, pattern: &str, client_id: u64) {
+        Self::remove(&self.patterns, pattern, client_id);
+    }
+
+    /// Delivers `message` to every subscriber of `channel` and of any pattern
+    /// matching it, returning how many clients received it.
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let mut receivers = 0;
+
+        if let Some(subscribers) = self.channels.get(channel) {
+            for sender in subscribers.values() {
+                let payload = RespValue::Array(vec![
+                    RespValue::BulkString(Some("message".to_string())),
+                    RespValue::BulkString(Some(channel.to_string())),
+                    RespValue::BulkString(Some(message.to_string())),
+                ]);
+                if sender.send(payload).is_ok() {
+                    receivers += 1;
+                }
+            }
+        }
+
+        for entry in self.patterns.iter() {
+            if !glob::matches(entry.key(), channel) {
+                continue;
+            }
+            for sender in entry.value().values() {
+                let payload = RespValue::Array(vec![
+                    RespValue::BulkString(Some("pmessage".to_string())),
+                    RespValue::BulkString(Some(entry.key().clone())),
+                    RespValue::BulkString(Some(channel.to_string())),
+                    RespValue::BulkString(Some(message.to_string())),
+                ]);
+                if sender.send(payload).is_ok() {
+                    receivers += 1;
+                }
+            }
+        }
+
+        receivers
+    }
+
+    fn remove(map: &DashMap<String, Subscribers>, name: &str, client_id: u64) {
+        if let Some(mut subscribers) = map.get_mut(name) {
+            subscribers.remove(&client_id);
+        }
+        map.remove_if(name, |_, subscribers| subscribers.is_empty());
+    }
+}
@@ -0,0 +1,28 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+thread_local! {
+    static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+}
+
+/// A pseudo-random number from a per-thread xorshift generator, seeded from
+/// the randomness std uses for hash maps. Fine for picking random elements,
+/// not for anything that has to be unpredictable.
+fn next_u64() -> u64 {
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+/// A pseudo-random number in `0..bound`, which must not be empty.
+pub fn below(bound: usize) -> usize {
+    (next_u64() % bound as u64) as usize
+}
@@ -1,51 +1,166 @@
+use bytes::{Buf, BytesMut};
+use thiserror::Error;
+
+/// Largest bulk argument accepted, matching Redis' `proto-max-bulk-len`.
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+const MAX_MULTIBULK_LEN: i64 = 1024 * 1024;
+const MAX_INLINE_LEN: usize = 64 * 1024;
+/// Error codes sent as the error's prefix in place of the generic `ERR`,
+/// when an error message starts with one of them.
+const ERROR_CODES: [&str; 5] = [
+    "WRONGTYPE",
+    "INVALIDOBJ",
+    "UNBLOCKED",
+    "NOGROUP",
+    "BUSYGROUP",
+];
+
 pub enum RespValue {
     SimpleString(String),
     Error(String),
     Integer(i64),
     BulkString(Option<String>),
+    /// A bulk string that may hold arbitrary bytes, such as string values.
+    BulkBytes(Option<Vec<u8>>),
     Array(Vec<RespValue>),
     Null,
     NullArray,
+    /// Several replies written back to back, for commands such as SUBSCRIBE
+    /// that answer once per argument.
+    Multiple(Vec<RespValue>),
 }
 
-impl From<RespValue> for String {
-    fn from(value: RespValue) -> String {
-        match value {
-            RespValue::SimpleString(s) => format!("+{}\r\n", s),
-            RespValue::Error(e) => format!("-ERR {}\r\n", e),
-            RespValue::Integer(n) => format!(":{}\r\n", n),
-            RespValue::BulkString(Some(s)) => format!("${}\r\n{}\r\n", s.len(), s),
-            RespValue::BulkString(None) | RespValue::Null => "$-1\r\n".to_string(),
-            RespValue::NullArray => "*-1\r\n".to_string(),
+impl From<RespValue> for Vec<u8> {
+    fn from(value: RespValue) -> Vec<u8> {
+        let mut result = Vec::new();
+        value.write_to(&mut result);
+        result
+    }
+}
+
+impl RespValue {
+    fn write_to(self, out: &mut Vec<u8>) {
+        match self {
+            RespValue::SimpleString(s) => out.extend_from_slice(format!("+{}\r\n", s).as_bytes()),
+            RespValue::Error(e) => {
+                let code = e
+                    .split(' ')
+                    .next()
+                    .filter(|word| ERROR_CODES.contains(word));
+                match code {
+                    Some(_) => out.extend_from_slice(format!("-{}\r\n", e).as_bytes()),
+                    None => out.extend_from_slice(format!("-ERR {}\r\n", e).as_bytes()),
+                }
+            }
+            RespValue::Integer(n) => out.extend_from_slice(format!(":{}\r\n", n).as_bytes()),
+            RespValue::BulkString(Some(s)) => Self::write_bulk(out, s.as_bytes()),
+            RespValue::BulkBytes(Some(bytes)) => Self::write_bulk(out, &bytes),
+            RespValue::BulkString(None) | RespValue::BulkBytes(None) | RespValue::Null => {
+                out.extend_from_slice(b"$-1\r\n")
+            }
+            RespValue::NullArray => out.extend_from_slice(b"*-1\r\n"),
             RespValue::Array(items) => {
-                let mut result = format!("*{}\r\n", items.len());
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
                 for item in items {
-                    result.push_str(&String::from(item));
+                    item.write_to(out);
+                }
+            }
+            RespValue::Multiple(replies) => {
+                for reply in replies {
+                    reply.write_to(out);
                 }
-                result
             }
         }
     }
+
+    fn write_bulk(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+        out.extend_from_slice(bytes);
+        out.extend_from_slice(b"\r\n");
+    }
 }
 
-pub fn parse(source: &[u8]) -> Vec<String> {
-    let source = String::from_utf8_lossy(source).to_string();
-    let mut res = Vec::new();
+/// Takes one complete request off the front of `buffer`, or returns `None`
+/// when more bytes are needed. Both multibulk (`*1\r\n$4\r\nPING\r\n`) and
+/// inline (`PING\r\n`) requests are understood; arguments are binary-safe.
+pub fn parse(buffer: &mut BytesMut) -> Result<Option<Vec<Vec<u8>>>, ProtocolError> {
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+    if buffer[0] != b'*' {
+        return parse_inline(buffer);
+    }
 
-    source.split("\r\n").for_each(|line| {
-        if line == "*" || line == "-" || line == "+" {
-            res.push(line.to_string());
-            return;
+    let Some((header, mut pos)) = read_line(buffer, 0) else {
+        return Ok(None);
+    };
+    let count = parse_length(&header[1..])
+        .filter(|count| *count <= MAX_MULTIBULK_LEN)
+        .ok_or(ProtocolError::InvalidMultibulkLength)?;
+
+    let mut request = Vec::with_capacity(count.max(0) as usize);
+    for _ in 0..count {
+        let Some((header, start)) = read_line(buffer, pos) else {
+            return Ok(None);
+        };
+        if header.first() != Some(&b'$') {
+            return Err(ProtocolError::ExpectedBulk(
+                header.first().map(|&b| b as char).unwrap_or(' '),
+            ));
+        }
+        let len = parse_length(&header[1..])
+            .filter(|len| (0..=MAX_BULK_LEN).contains(len))
+            .ok_or(ProtocolError::InvalidBulkLength)? as usize;
+
+        let end = start + len;
+        if buffer.len() < end + 2 {
+            return Ok(None);
         }
-        if line.starts_with('*') || line.starts_with('$') || line.is_empty() {
-            return;
+        if &buffer[end..end + 2] != b"\r\n" {
+            return Err(ProtocolError::InvalidBulkLength);
         }
-        if line.starts_with('+') || line.starts_with(':') {
-            res.push(line[1..].to_string());
-            return;
+        request.push(buffer[start..end].to_vec());
+        pos = end + 2;
+    }
+
+    buffer.advance(pos);
+    Ok(Some(request))
+}
+
+fn parse_inline(buffer: &mut BytesMut) -> Result<Option<Vec<Vec<u8>>>, ProtocolError> {
+    let Some(newline) = buffer.iter().position(|&b| b == b'\n') else {
+        if buffer.len() > MAX_INLINE_LEN {
+            return Err(ProtocolError::InlineTooBig);
         }
-        res.push(line.to_string());
-    });
+        return Ok(None);
+    };
+
+    let request = buffer[..newline]
+        .split(|b| b.is_ascii_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .map(<[u8]>::to_vec)
+        .collect();
+    buffer.advance(newline + 1);
+    Ok(Some(request))
+}
+
+fn read_line(buffer: &[u8], from: usize) -> Option<(&[u8], usize)> {
+    let len = buffer[from..].windows(2).position(|w| w == b"\r\n")?;
+    Some((&buffer[from..from + len], from + len + 2))
+}
+
+fn parse_length(digits: &[u8]) -> Option<i64> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
 
-    res
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    #[error("Protocol error: invalid multibulk length")]
+    InvalidMultibulkLength,
+    #[error("Protocol error: invalid bulk length")]
+    InvalidBulkLength,
+    #[error("Protocol error: expected '$', got '{0}'")]
+    ExpectedBulk(char),
+    #[error("Protocol error: too big inline request")]
+    InlineTooBig,
 }